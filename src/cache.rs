@@ -68,9 +68,12 @@ pub fn extend(x: &str, y: &str) -> String {
 mod tests {
     use crate::{
         cache::{extend, hash_read, hash_str, key},
-        toastfile::{Task, DEFAULT_LOCATION, DEFAULT_USER},
+        env_file::apply,
+        runner::task_environment,
+        toastfile::{environment, parse, Task, DEFAULT_LOCATION, DEFAULT_USER},
     };
-    use std::{collections::HashMap, path::Path};
+    use std::{collections::HashMap, fs::write, path::Path};
+    use tempfile::tempdir;
 
     #[test]
     fn key_pure() {
@@ -81,9 +84,14 @@ mod tests {
 
         let task = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -112,9 +120,14 @@ mod tests {
 
         let task = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -149,9 +162,14 @@ mod tests {
 
         let task1 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: environment1,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -164,9 +182,14 @@ mod tests {
 
         let task2 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: environment2,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -201,9 +224,14 @@ mod tests {
 
         let task1 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: environment1,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -216,9 +244,14 @@ mod tests {
 
         let task2 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: environment2,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -250,9 +283,14 @@ mod tests {
 
         let task = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -276,15 +314,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn key_cache_neutral_environment() {
+        let input = r#"
+image: encom:os-12
+env_file: .env
+tasks:
+  foo:
+    environment:
+      cache_keyed: null
+    cache_neutral_environment:
+      cache_neutral: null
+    command: echo wibble
+        "#
+        .trim();
+
+        // Compute the key the way the runner does, with the values coming from an environment file
+        // [ref:unkeyed_environment_not_committed].
+        let task_key = |keyed: &str, neutral: &str| {
+            let dir = tempdir().unwrap();
+            write(
+                dir.path().join(".env"),
+                format!("cache_keyed={}\ncache_neutral={}\n", keyed, neutral),
+            )
+            .unwrap();
+
+            let mut toastfile = parse(input).unwrap();
+            apply(&mut toastfile, dir.path()).unwrap();
+            let task = &toastfile.tasks["foo"];
+            let full_environment = environment(task).unwrap();
+
+            key(
+                "corge",
+                task,
+                "grault",
+                &task_environment(&[], &full_environment, task).keyed,
+            )
+        };
+
+        assert_eq!(task_key("bar", "baz"), task_key("bar", "qux"));
+        assert_ne!(task_key("bar", "baz"), task_key("qux", "baz"));
+    }
+
     #[test]
     fn key_input_files_hash() {
         let previous_key = "corge";
 
         let task = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -312,9 +397,14 @@ mod tests {
 
         let task1 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -327,9 +417,14 @@ mod tests {
 
         let task2 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -356,9 +451,14 @@ mod tests {
 
         let task1 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -371,9 +471,14 @@ mod tests {
 
         let task2 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -400,9 +505,14 @@ mod tests {
 
         let task1 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -415,9 +525,14 @@ mod tests {
 
         let task2 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -444,9 +559,14 @@ mod tests {
 
         let task1 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -459,9 +579,14 @@ mod tests {
 
         let task2 = Task {
             description: None,
+            extends: None,
             dependencies: vec![],
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
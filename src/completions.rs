@@ -0,0 +1,343 @@
+use clap::App;
+
+// The name of the program, as the completion scripts refer to it
+const BIN_NAME: &str = "toast";
+
+// The shells we can generate completion scripts for
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+// The names of the shells, as accepted on the command line. Each one is recognized by `from_name`.
+// [tag:shell_valid]
+pub const SHELL_NAMES: &[&str] = &["bash", "zsh", "fish"];
+
+impl Shell {
+    // Look up a shell by its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None,
+        }
+    }
+}
+
+// What the completion scripts need to know about the command-line interface in order to complete
+// task names, which clap doesn't know about
+pub struct Interface<'a> {
+    // The options for choosing the toastfile, which are passed along when asking for task names
+    pub file_options: &'a [String],
+
+    // All the options which take a value, so their values aren't mistaken for task names
+    pub value_options: &'a [String],
+
+    // The subcommands which take tasks, and the ones which don't
+    pub task_subcommands: &'a [&'a str],
+    pub taskless_subcommands: &'a [&'a str],
+
+    // The hidden option which prints the names of the tasks, one per line
+    pub complete_tasks_option: &'a str,
+}
+
+// Generate a completion script. The script clap generates is extended to complete task names by
+// asking Toast for them, so they come from the same toastfile Toast would use.
+pub fn script(mut app: App, shell: Shell, interface: &Interface) -> String {
+    let mut buffer = vec![];
+    app.gen_completions_to(
+        BIN_NAME,
+        match shell {
+            Shell::Bash => clap::Shell::Bash,
+            Shell::Zsh => clap::Shell::Zsh,
+            Shell::Fish => clap::Shell::Fish,
+        },
+        &mut buffer,
+    );
+    let script = String::from_utf8_lossy(&buffer).into_owned();
+
+    match shell {
+        Shell::Bash => bash(&script, interface),
+        Shell::Zsh => zsh(&script, interface),
+        Shell::Fish => fish(&script, interface),
+    }
+}
+
+// Join words into the pattern of a `case` branch in Bash or zsh.
+fn case_pattern(words: &[String]) -> String {
+    words.join("|")
+}
+
+// The options which take a value, other than the ones for choosing the toastfile
+fn other_value_options(interface: &Interface) -> Vec<String> {
+    interface
+        .value_options
+        .iter()
+        .filter(|option| !interface.file_options.contains(option))
+        .cloned()
+        .collect()
+}
+
+// Extend clap's Bash script. Clap offers the placeholder for the `TASKS` argument as a completion,
+// so that's removed.
+fn bash(script: &str, interface: &Interface) -> String {
+    format!(
+        r#"{script}
+_toast_with_tasks() {{
+    _toast "$@"
+
+    local cur prev word first='' file=() i
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    if declare -F _get_comp_words_by_ref > /dev/null 2>&1; then
+        _get_comp_words_by_ref -n : cur prev
+    fi
+
+    # Find the toastfile and the first positional argument.
+    for (( i = 1; i < COMP_CWORD; i++ )); do
+        word="${{COMP_WORDS[i]}}"
+        case "$word" in
+            {file_options})
+                file=(--file "${{COMP_WORDS[i+1]}}")
+                (( i++ ))
+                ;;
+            --file=*)
+                file=("$word")
+                ;;
+            {other_value_options})
+                (( i++ ))
+                ;;
+            -*)
+                ;;
+            *)
+                if [[ -z "$first" ]]; then
+                    first="$word"
+                fi
+                ;;
+        esac
+    done
+
+    # Only complete tasks where they're expected.
+    case "$prev" in
+        {value_options})
+            return 0
+            ;;
+    esac
+    if [[ "$cur" == -* ]]; then
+        return 0
+    fi
+    case "$first" in
+        {taskless_subcommands})
+            return 0
+            ;;
+        ''|{task_subcommands})
+            ;;
+        *)
+            COMPREPLY=()
+            ;;
+    esac
+
+    local task
+    while IFS='' read -r task; do
+        COMPREPLY+=("$(printf '%q' "$task")")
+    done < <(compgen -W "$(toast "${{file[@]}}" {complete_tasks_option} 2> /dev/null)" -- "$cur")
+    if declare -F __ltrim_colon_completions > /dev/null 2>&1; then
+        __ltrim_colon_completions "$cur"
+    fi
+}}
+
+complete -F _toast_with_tasks -o bashdefault -o default toast
+"#,
+        script = script.replace("<TASKS>...", "").trim_end(),
+        file_options = case_pattern(interface.file_options),
+        other_value_options = case_pattern(&other_value_options(interface)),
+        value_options = case_pattern(interface.value_options),
+        task_subcommands = interface.task_subcommands.join("|"),
+        taskless_subcommands = interface.taskless_subcommands.join("|"),
+        complete_tasks_option = interface.complete_tasks_option,
+    )
+}
+
+// Extend clap's zsh script. Clap's `_toast` function is renamed, and the new one completes tasks
+// when the first positional argument is a task rather than a subcommand. The `TASKS` arguments of
+// the subcommands are completed with task names rather than file names.
+fn zsh(script: &str, interface: &Interface) -> String {
+    let script = script
+        .replacen("\n_toast() {\n", "\n_toast_subcommands() {\n", 1)
+        .replace("'::tasks:_files'", "'*::tasks:_toast_tasks'");
+    let script = script
+        .trim_end()
+        .trim_end_matches("_toast \"$@\"")
+        .trim_end();
+
+    format!(
+        r#"{script}
+
+_toast_tasks() {{
+    local -a tasks
+    tasks=(${{(f)"$(_call_program tasks toast ${{_toast_file[@]}} {complete_tasks_option} 2> /dev/null)"}})
+    compadd -a tasks
+}}
+
+_toast() {{
+    local word first=0 i
+    local -a _toast_file
+
+    # Find the toastfile and the first positional argument.
+    for (( i = 2; i < CURRENT; i++ )); do
+        word="$words[i]"
+        case "$word" in
+            ({file_options})
+                _toast_file=(--file "${{(Q)words[i+1]}}")
+                (( i++ ))
+                ;;
+            (--file=*)
+                _toast_file=("${{(Q)word}}")
+                ;;
+            ({other_value_options})
+                (( i++ ))
+                ;;
+            (-*)
+                ;;
+            (*)
+                if (( first == 0 )); then
+                    first=$i
+                fi
+                ;;
+        esac
+    done
+
+    # If the first positional argument is a task, the rest are tasks too.
+    case "$words[first]" in
+        ({subcommands})
+            _toast_subcommands "$@"
+            ;;
+        (*)
+            if (( first == 0 )); then
+                _toast_subcommands "$@"
+            fi
+            if [[ "$PREFIX" != -* ]]; then
+                _toast_tasks
+            fi
+            ;;
+    esac
+}}
+
+_toast "$@"
+"#,
+        script = script,
+        file_options = case_pattern(interface.file_options),
+        other_value_options = case_pattern(&other_value_options(interface)),
+        subcommands = interface
+            .taskless_subcommands
+            .iter()
+            .chain(interface.task_subcommands.iter())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("|"),
+        complete_tasks_option = interface.complete_tasks_option,
+    )
+}
+
+// Extend clap's fish script.
+fn fish(script: &str, interface: &Interface) -> String {
+    format!(
+        r#"{script}
+
+function __toast_tasks
+    set -l tokens (commandline -opc)
+    set -l file
+    for i in (seq (count $tokens))
+        if contains -- $tokens[$i] {file_options}
+            set file --file $tokens[(math $i + 1)]
+        else if string match -q -- '--file=*' $tokens[$i]
+            set file $tokens[$i]
+        end
+    end
+    toast $file {complete_tasks_option} 2> /dev/null
+end
+
+complete -c toast -n "not __fish_seen_subcommand_from {taskless_subcommands}; and not __fish_prev_arg_in {value_options}" -f -a "(__toast_tasks)"
+"#,
+        script = script.trim_end(),
+        file_options = interface.file_options.join(" "),
+        taskless_subcommands = interface.taskless_subcommands.join(" "),
+        value_options = interface.value_options.join(" "),
+        complete_tasks_option = interface.complete_tasks_option,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::completions::{script, Interface, Shell};
+    use clap::{App, Arg, SubCommand};
+
+    fn app<'a, 'b>() -> App<'a, 'b> {
+        App::new("Toast")
+            .arg(
+                Arg::with_name("file")
+                    .short("f")
+                    .long("file")
+                    .takes_value(true)
+                    .global(true),
+            )
+            .subcommand(
+                SubCommand::with_name("run")
+                    .arg(Arg::with_name("tasks").value_name("TASKS").multiple(true)),
+            )
+            .subcommand(SubCommand::with_name("list"))
+    }
+
+    fn interface_script(shell: Shell) -> String {
+        script(
+            app(),
+            shell,
+            &Interface {
+                file_options: &["-f".to_owned(), "--file".to_owned()],
+                value_options: &[
+                    "-f".to_owned(),
+                    "--file".to_owned(),
+                    "-c".to_owned(),
+                    "--config-file".to_owned(),
+                ],
+                task_subcommands: &["run"],
+                taskless_subcommands: &["list"],
+                complete_tasks_option: "--complete-tasks",
+            },
+        )
+    }
+
+    #[test]
+    fn script_bash() {
+        let script = interface_script(Shell::Bash);
+
+        assert!(script.contains("toast \"${file[@]}\" --complete-tasks"));
+        assert!(script.contains("        list)\n            return 0"));
+        assert!(!script.contains("<TASKS>"));
+        assert!(script.ends_with("complete -F _toast_with_tasks -o bashdefault -o default toast\n"));
+    }
+
+    #[test]
+    fn script_zsh() {
+        let script = interface_script(Shell::Zsh);
+
+        assert!(script.starts_with("#compdef toast\n"));
+        assert!(script.contains("\n_toast_subcommands() {\n"));
+        assert!(script.contains("'*::tasks:_toast_tasks'"));
+        assert!(!script.contains("_files"));
+        assert!(script.contains("toast ${_toast_file[@]} --complete-tasks"));
+        assert_eq!(script.matches("\n_toast \"$@\"").count(), 1);
+    }
+
+    #[test]
+    fn script_fish() {
+        let script = interface_script(Shell::Fish);
+
+        assert!(script.contains("contains -- $tokens[$i] -f --file\n"));
+        assert!(script.contains("not __fish_seen_subcommand_from list;"));
+        assert!(script.contains("toast $file --complete-tasks"));
+    }
+}
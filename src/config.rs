@@ -22,6 +22,12 @@ pub struct Config {
 
     #[serde(default = "default_write_remote_cache")]
     pub write_remote_cache: bool,
+
+    #[serde(default)]
+    pub redact_patterns: Vec<String>,
+
+    #[serde(default)]
+    pub passthrough_environment: Vec<String>,
 }
 
 fn default_docker_repo() -> String {
@@ -61,6 +67,8 @@ mod tests {
             write_local_cache: true,
             read_remote_cache: false,
             write_remote_cache: false,
+            redact_patterns: vec![],
+            passthrough_environment: vec![],
         };
 
         assert_eq!(parse(EMPTY_CONFIG).unwrap(), result);
@@ -74,6 +82,10 @@ read_local_cache: false
 write_local_cache: false
 read_remote_cache: true
 write_remote_cache: true
+redact_patterns:
+  - bar
+passthrough_environment:
+  - BAZ
     "#
         .trim();
 
@@ -83,6 +95,8 @@ write_remote_cache: true
             write_local_cache: false,
             read_remote_cache: true,
             write_remote_cache: true,
+            redact_patterns: vec!["bar".to_owned()],
+            passthrough_environment: vec!["BAZ".to_owned()],
         };
 
         assert_eq!(parse(config).unwrap(), result);
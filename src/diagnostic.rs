@@ -0,0 +1,496 @@
+use crate::{failure::Failure, toastfile::FileFormat};
+use atty::Stream;
+use colored::{Color, Colorize};
+use std::{collections::HashMap, fmt, path::Path};
+use yaml_rust::{
+    parser::{MarkedEventReceiver, Parser},
+    scanner::{Marker, TScalarStyle},
+    Event,
+};
+
+// A location in a source file. The line and column are 1-based, and the length is the number of
+// characters to underline.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+// Errors prevent a toastfile from being used, whereas warnings point out things which are likely to
+// be mistakes.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// This struct represents a problem with a toastfile. The `path` is the sequence of keys leading to
+// the offending part of the toastfile, e.g., `["tasks", "build", "input_paths"]`, which is used to
+// find its position in the source. Problems reported by the parser itself have a `position` instead.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub path: Vec<String>,
+    pub position: Option<Position>,
+}
+
+impl Diagnostic {
+    // Construct an error for the part of the toastfile at the given path.
+    pub fn new<S: AsRef<str>>(message: String, path: &[S]) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            path: path.iter().map(|key| key.as_ref().to_owned()).collect(),
+            position: None,
+        }
+    }
+
+    // Construct a warning for the part of the toastfile at the given path.
+    pub fn warning<S: AsRef<str>>(message: String, path: &[S]) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::new(message, path)
+        }
+    }
+}
+
+// A collection of diagnostics, so that all the problems with a toastfile can be reported at once
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    // Add the diagnostics from a check, if it failed.
+    pub fn extend(&mut self, result: Result<(), Diagnostics>) {
+        if let Err(diagnostics) = result {
+            self.0.extend(diagnostics.0);
+        }
+    }
+
+    // The checks visit tasks in no particular order, so the diagnostics are sorted to make the
+    // result deterministic. Tasks expanded from a matrix are reported as the original task
+    // [ref:matrix_source_name], so duplicates are removed too.
+    pub fn sort(&mut self) {
+        self.0
+            .sort_by(|x, y| (&x.path, &x.message).cmp(&(&y.path, &y.message)));
+        self.0.dedup();
+    }
+
+    // Convert the diagnostics into a result, which is an error if there are any diagnostics.
+    pub fn into_result(mut self) -> Result<(), Diagnostics> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            self.sort();
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.0
+                .iter()
+                .map(|diagnostic| diagnostic.message.clone())
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    }
+}
+
+impl From<Diagnostics> for Failure {
+    fn from(diagnostics: Diagnostics) -> Self {
+        Failure::User(diagnostics.to_string(), None)
+    }
+}
+
+// A map from the paths of the keys in a YAML or JSON document to their positions. TOML documents
+// aren't supported, so the map is empty for them.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    positions: HashMap<Vec<String>, Position>,
+}
+
+// The parser reports nodes in document order. Each frame tracks where we are in one of the
+// enclosing collections.
+enum Frame {
+    // The key of the current entry, or `None` if the next node is a key
+    Mapping(Option<String>),
+
+    // The index of the next item
+    Sequence(usize),
+}
+
+// This struct builds a source map from the events emitted by the YAML parser.
+#[derive(Default)]
+struct SourceMapBuilder {
+    frames: Vec<Frame>,
+    path: Vec<String>,
+    positions: HashMap<Vec<String>, Position>,
+}
+
+impl SourceMapBuilder {
+    // Record the position of a node which is starting. If it's a value, return the key of its path.
+    fn begin_node(&mut self, marker: Marker, text: Option<&str>, length: usize) -> Option<String> {
+        let key = match self.frames.last_mut() {
+            Some(Frame::Mapping(key @ None)) => {
+                // This node is a key, so record its position under its own path. Keys which aren't
+                // strings don't have a path.
+                if let Some(text) = text {
+                    *key = Some(text.to_owned());
+                    let mut path = self.path.clone();
+                    path.push(text.to_owned());
+                    self.positions
+                        .entry(path)
+                        .or_insert_with(|| position(marker, length));
+                } else {
+                    *key = Some(String::new());
+                }
+                return None;
+            }
+            Some(Frame::Mapping(key @ Some(_))) => key.take().unwrap(), // Safe due to the pattern
+            Some(Frame::Sequence(index)) => {
+                *index += 1;
+                (*index - 1).to_string()
+            }
+            None => return None,
+        };
+
+        // Items of sequences don't have keys of their own, so the position of the item is used.
+        let mut path = self.path.clone();
+        path.push(key.clone());
+        self.positions
+            .entry(path)
+            .or_insert_with(|| position(marker, length));
+        Some(key)
+    }
+}
+
+impl MarkedEventReceiver for SourceMapBuilder {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        match event {
+            Event::Scalar(text, style, _, _) => {
+                let length = match style {
+                    TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted => {
+                        text.chars().count() + 2
+                    }
+                    TScalarStyle::Literal | TScalarStyle::Foled => 1,
+                    _ => text.chars().count().max(1),
+                };
+                self.begin_node(marker, Some(&text), length);
+            }
+            Event::Alias(_) => {
+                self.begin_node(marker, None, 1);
+            }
+            Event::MappingStart(_) | Event::SequenceStart(_) => {
+                // The root collection doesn't contribute to the path.
+                let key = self.begin_node(marker, None, 1);
+                if !self.frames.is_empty() {
+                    self.path.push(key.unwrap_or_default());
+                }
+                self.frames.push(if let Event::MappingStart(_) = event {
+                    Frame::Mapping(None)
+                } else {
+                    Frame::Sequence(0)
+                });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.frames.pop();
+                if !self.frames.is_empty() {
+                    self.path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Convert a marker from the YAML parser into a position.
+fn position(marker: Marker, length: usize) -> Position {
+    Position {
+        line: marker.line(),
+        column: marker.col() + 1,
+        length,
+    }
+}
+
+impl SourceMap {
+    // Build a source map for a document. If the document can't be parsed, the map is empty.
+    pub fn new(source: &str, file_format: FileFormat) -> Self {
+        match file_format {
+            FileFormat::Yaml | FileFormat::Json => {
+                let mut builder = SourceMapBuilder::default();
+                let mut parser = Parser::new(source.chars());
+                if parser.load(&mut builder, false).is_err() {
+                    return SourceMap::default();
+                }
+                SourceMap {
+                    positions: builder.positions,
+                }
+            }
+            FileFormat::Toml => SourceMap::default(),
+        }
+    }
+
+    // Find the position of the part of the document at the given path. If there's nothing at that
+    // path, which can happen if it refers to a value which was filled in from a base or from the
+    // `defaults`, the position of the longest prefix which exists is returned instead. Tasks
+    // expanded from a matrix (like `test[RUST=1.40]`) are located at the original task.
+    pub fn locate(&self, path: &[String]) -> Option<Position> {
+        let base_path = path
+            .iter()
+            .map(|key| match key.find('[') {
+                Some(index) if key.ends_with(']') => key[..index].to_owned(),
+                _ => key.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        (1..=path.len()).rev().find_map(|length| {
+            self.positions
+                .get(&path[..length])
+                .or_else(|| self.positions.get(&base_path[..length]))
+                .cloned()
+        })
+    }
+}
+
+// Highlight part of a rendered diagnostic, if the diagnostics are going to a terminal.
+fn highlight(text: &str, color: Color) -> String {
+    if atty::is(Stream::Stderr) {
+        text.color(color).bold().to_string()
+    } else {
+        text.to_owned()
+    }
+}
+
+// Render diagnostics in the style of rustc, with a snippet of the source for each one.
+pub fn render(
+    diagnostics: &Diagnostics,
+    file_path: &Path,
+    source: &str,
+    file_format: FileFormat,
+) -> String {
+    let source_map = SourceMap::new(source, file_format);
+    let lines = source.lines().collect::<Vec<_>>();
+
+    // Report the diagnostics in the order they appear in the source, followed by any which can't
+    // be located.
+    let mut located = diagnostics
+        .0
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic,
+                diagnostic
+                    .position
+                    .or_else(|| source_map.locate(&diagnostic.path)),
+            )
+        })
+        .collect::<Vec<_>>();
+    located.sort_by_key(|(_, position)| {
+        position.map_or((true, 0, 0), |position| {
+            (false, position.line, position.column)
+        })
+    });
+
+    located
+        .into_iter()
+        .map(|(diagnostic, position)| {
+            let (label, color) = match diagnostic.severity {
+                Severity::Error => ("error", Color::Red),
+                Severity::Warning => ("warning", Color::Yellow),
+            };
+            let mut result = format!("{}: {}", highlight(label, color), diagnostic.message);
+
+            if let Some(position) = position {
+                let bar = highlight("|", Color::Blue);
+                // The line can be one past the end if the problem is at the end of the file.
+                let line = lines
+                    .get(position.line.saturating_sub(1))
+                    .cloned()
+                    .unwrap_or("");
+                let gutter = " ".repeat(position.line.to_string().len());
+                result.push_str(&format!(
+                    "\n{}{} {}:{}:{}\n{} {}\n{} {} {}\n{} {} {}{}",
+                    gutter,
+                    highlight("-->", Color::Blue),
+                    file_path.to_string_lossy(),
+                    position.line,
+                    position.column,
+                    gutter,
+                    bar,
+                    highlight(&position.line.to_string(), Color::Blue),
+                    bar,
+                    line,
+                    gutter,
+                    bar,
+                    " ".repeat(position.column.saturating_sub(1)),
+                    highlight(&"^".repeat(position.length), color),
+                ));
+            }
+
+            result
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diagnostic::{render, Diagnostic, Diagnostics, Position, Severity, SourceMap},
+        toastfile::FileFormat,
+    };
+    use std::path::Path;
+
+    fn path(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| (*key).to_owned()).collect()
+    }
+
+    #[test]
+    fn source_map_yaml() {
+        let source = r#"
+image: encom:os-12
+tasks:
+  foo:
+    input_paths:
+      - bar
+      - "baz"
+        "#
+        .trim();
+
+        let source_map = SourceMap::new(source, FileFormat::Yaml);
+
+        assert_eq!(
+            source_map.locate(&path(&["image"])),
+            Some(Position {
+                line: 1,
+                column: 1,
+                length: 5,
+            }),
+        );
+        assert_eq!(
+            source_map.locate(&path(&["tasks", "foo"])),
+            Some(Position {
+                line: 3,
+                column: 3,
+                length: 3,
+            }),
+        );
+        assert_eq!(
+            source_map.locate(&path(&["tasks", "foo", "input_paths", "1"])),
+            Some(Position {
+                line: 6,
+                column: 9,
+                length: 5,
+            }),
+        );
+    }
+
+    #[test]
+    fn source_map_json() {
+        let source = "{\n  \"image\": \"encom:os-12\",\n  \"tasks\": { \"foo\": {} }\n}";
+
+        let source_map = SourceMap::new(source, FileFormat::Json);
+
+        assert_eq!(
+            source_map.locate(&path(&["tasks", "foo"])),
+            Some(Position {
+                line: 3,
+                column: 14,
+                length: 5,
+            }),
+        );
+    }
+
+    #[test]
+    fn source_map_fallback() {
+        let source = r#"
+image: encom:os-12
+tasks:
+  foo:
+    matrix:
+      RUST: ['1.40']
+        "#
+        .trim();
+
+        let source_map = SourceMap::new(source, FileFormat::Yaml);
+
+        assert_eq!(
+            source_map.locate(&path(&["tasks", "foo[RUST=1.40]", "location"])),
+            source_map.locate(&path(&["tasks", "foo"])),
+        );
+        assert_eq!(source_map.locate(&path(&["environment"])), None);
+    }
+
+    #[test]
+    fn render_snippet() {
+        let source = r#"
+image: encom:os-12
+tasks:
+  foo:
+    cache: false
+        "#
+        .trim();
+
+        let rendered = render(
+            &Diagnostics(vec![Diagnostic::new(
+                "Something is wrong.".to_owned(),
+                &["tasks", "foo", "cache"],
+            )]),
+            Path::new("toast.yml"),
+            source,
+            FileFormat::Yaml,
+        );
+
+        assert_eq!(
+            rendered,
+            "error: Something is wrong.\n \
+             --> toast.yml:4:5\n  \
+             |\n\
+             4 |     cache: false\n  \
+             |     ^^^^^",
+        );
+    }
+
+    #[test]
+    fn render_without_position() {
+        let rendered = render(
+            &Diagnostics(vec![
+                Diagnostic::new("First.".to_owned(), &["environment"]),
+                Diagnostic::new("Second.".to_owned(), &["environment"]),
+            ]),
+            Path::new("toast.toml"),
+            "image = 'encom:os-12'",
+            FileFormat::Toml,
+        );
+
+        assert_eq!(rendered, "error: First.\n\nerror: Second.");
+    }
+
+    #[test]
+    fn render_warning() {
+        let warning = Diagnostic::warning("Careful.".to_owned(), &["image"]);
+        assert_eq!(warning.severity, Severity::Warning);
+
+        let rendered = render(
+            &Diagnostics(vec![warning]),
+            Path::new("toast.yml"),
+            "image: encom:os-12",
+            FileFormat::Yaml,
+        );
+
+        assert_eq!(
+            rendered,
+            "warning: Careful.\n \
+             --> toast.yml:1:1\n  \
+             |\n\
+             1 | image: encom:os-12\n  \
+             | ^^^^^",
+        );
+    }
+}
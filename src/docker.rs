@@ -1,20 +1,28 @@
-use crate::{failure, failure::Failure, format::CodeStr, spinner::spin};
+use crate::{
+    failure,
+    failure::Failure,
+    format::CodeStr,
+    redact,
+    redact::Redactor,
+    secrets::{ENVIRONMENT_FILE_LOCATION, SECRETS_LOCATION},
+    spinner::spin,
+    tar,
+    toastfile::OutputPath,
+};
+use crossbeam::scope;
 use std::{
     collections::HashMap,
-    fs::{create_dir_all, metadata, rename},
     io,
-    io::Read,
+    io::{sink, stderr, stdout},
     path::{Path, PathBuf},
-    process::{ChildStdin, Command, Stdio},
+    process::{ChildStdin, ChildStdout, Command, Stdio},
     string::ToString,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
 };
-use tempfile::tempdir;
 use uuid::Uuid;
-use walkdir::WalkDir;
 
 // Construct a random image tag.
 pub fn random_tag() -> String {
@@ -66,6 +74,25 @@ pub fn pull_image(image: &str, interrupted: &Arc<AtomicBool>) -> Result<(), Fail
     .map(|_| ())
 }
 
+// List the tagged images in a repository.
+pub fn list_images(repo: &str, interrupted: &Arc<AtomicBool>) -> Result<Vec<String>, Failure> {
+    debug!("Listing images in repository {}\u{2026}", repo.code_str());
+
+    run_quiet(
+        "Listing images\u{2026}",
+        "Unable to list images.",
+        &["image", "ls", "--format", "{{.Repository}}:{{.Tag}}", repo],
+        interrupted,
+    )
+    .map(|output| {
+        output
+            .lines()
+            .filter(|line| !line.is_empty() && !line.ends_with(":<none>"))
+            .map(ToString::to_string)
+            .collect()
+    })
+}
+
 // Delete an image.
 pub fn delete_image(image: &str, interrupted: &Arc<AtomicBool>) -> Result<(), Failure> {
     debug!("Deleting image {}\u{2026}", image.code_str());
@@ -79,7 +106,9 @@ pub fn delete_image(image: &str, interrupted: &Arc<AtomicBool>) -> Result<(), Fa
     .map(|_| ())
 }
 
-// Create a container and return its ID.
+// Create a container and return its ID. The variables in `environment` are stored in the container
+// configuration, so they end up in the image if the container is committed. The script in
+// `environment_file`, if any, is only read when the command runs.
 #[allow(clippy::too_many_arguments)]
 pub fn create_container(
     image: &str,
@@ -87,6 +116,8 @@ pub fn create_container(
     environment: &HashMap<String, String>,
     mount_paths: &[PathBuf],
     mount_readonly: bool,
+    secrets_dir: Option<&Path>,
+    environment_file: Option<&Path>,
     ports: &[String],
     location: &Path,
     user: &str,
@@ -121,6 +152,30 @@ pub fn create_container(
         }
     }
 
+    // Secrets are bind-mounted rather than copied into the container or passed as environment
+    // variables, so they don't end up in the image when the container is committed.
+    if let Some(secrets_dir) = secrets_dir {
+        mount_options.push(format!(
+            "type=bind,source={},target={},readonly",
+            secrets_dir.to_string_lossy(),
+            SECRETS_LOCATION,
+        ));
+    }
+
+    // The same goes for the environment variables which aren't part of the cache key. The command
+    // reads them from a mounted file instead. [ref:unkeyed_environment_not_committed]
+    let command = if let Some(environment_file) = environment_file {
+        mount_options.push(format!(
+            "type=bind,source={},target={},readonly",
+            environment_file.to_string_lossy(),
+            ENVIRONMENT_FILE_LOCATION,
+        ));
+
+        format!(". {}\n{}", ENVIRONMENT_FILE_LOCATION, command)
+    } else {
+        command.to_owned()
+    };
+
     // Why `--init`? (1) PID 1 is supposed to reap orphaned zombie processes, otherwise they can
     // accumulate. Bash does this, but we run `/bin/sh` in the container, which may or may not be
     // Bash. So `--init` runs Tini (https://github.com/krallin/tini) as PID 1, which properly reaps
@@ -149,7 +204,7 @@ pub fn create_container(
         args.extend(vec!["--publish", port]);
     }
 
-    args.extend(vec![image, "/bin/su", "-c", command, user]);
+    args.extend(vec![image, "/bin/su", "-c", &command, user]);
 
     Ok(run_quiet(
         "Creating container\u{2026}",
@@ -161,10 +216,10 @@ pub fn create_container(
     .to_owned())
 }
 
-// Copy files into a container.
-pub fn copy_into_container<R: Read>(
+// Copy files into a container. The closure receives a pipe to which it should write a tar archive.
+pub fn copy_into_container<W: FnOnce(&mut ChildStdin) -> Result<(), Failure>>(
     container: &str,
-    mut tar: R,
+    writer: W,
     interrupted: &Arc<AtomicBool>,
 ) -> Result<(), Failure> {
     debug!(
@@ -176,12 +231,7 @@ pub fn copy_into_container<R: Read>(
         "Copying files into container\u{2026}",
         "Unable to copy files into the container.",
         &["container", "cp", "-", &format!("{}:{}", container, "/")],
-        |mut stdin| {
-            io::copy(&mut tar, &mut stdin)
-                .map_err(failure::system("Unable to copy files into the container."))?;
-
-            Ok(())
-        },
+        writer,
         interrupted,
     )
     .map(|_| ())
@@ -190,121 +240,119 @@ pub fn copy_into_container<R: Read>(
 // Copy files from a container.
 pub fn copy_from_container(
     container: &str,
-    paths: &[PathBuf],
+    output_paths: &[OutputPath],
     source_dir: &Path,
     destination_dir: &Path,
     interrupted: &Arc<AtomicBool>,
 ) -> Result<(), Failure> {
     // Copy each path from the container to the host.
-    for path in paths {
+    for output_path in output_paths {
+        let path = &output_path.path;
         debug!(
             "Copying {} from container {}\u{2026}",
             path.to_string_lossy().code_str(),
             container.code_str()
         );
 
-        // `docker container cp` is not idempotent. For example, suppose there is a directory called
-        // `/foo` in the container and `/bar` does not exist on the host. Consider the following
-        // command:
-        //   `docker cp container:/foo /bar`
-        // The first time that command is run, Docker will create the directory `/bar` on the host
-        // and copy the files from `/foo` into it. But if you run it again, Docker will copy `/bar`
-        // into the directory `/foo`, resulting in `/foo/foo`, which is undesirable. To work around
-        // this, we first copy the path from the container into a temporary directory (where the
-        // target path is guaranteed to not exist). Then we copy/move that to the final destination.
-        let temp_dir =
-            tempdir().map_err(failure::system("Unable to create temporary directory."))?;
-
         // Figure out what needs to go where.
         let source = source_dir.join(path);
-        let intermediate = temp_dir.path().join("data");
         let destination = destination_dir.join(path);
 
-        // Get the path from the container.
-        run_quiet(
+        // Mirroring deletes files on the host, so make sure the destination doesn't resolve (e.g.,
+        // via a symbolic link) to somewhere outside the destination directory.
+        // [ref:mirror_inside_toastfile_dir]
+        if output_path.mirror {
+            check_mirror_destination(&destination, destination_dir)?;
+        }
+
+        // `docker container cp` is not idempotent when the destination is a host path. For example,
+        // suppose there is a directory called `/foo` in the container and `/bar` does not exist on
+        // the host. Consider the following command:
+        //   `docker cp container:/foo /bar`
+        // The first time that command is run, Docker will create the directory `/bar` on the host
+        // and copy the files from `/foo` into it. But if you run it again, Docker will copy `/bar`
+        // into the directory `/foo`, resulting in `/foo/foo`, which is undesirable. To work around
+        // this, we ask Docker to write a tar archive to standard output instead, and we extract it
+        // ourselves.
+        run_quiet_stdout(
             "Copying files from the container\u{2026}",
             "Unable to copy files from the container.",
             &[
                 "container",
                 "cp",
                 &format!("{}:{}", container, source.to_string_lossy()),
-                &intermediate.to_string_lossy(),
+                "-",
             ],
+            |stdout| tar::extract(stdout, &destination, output_path.mirror, interrupted),
             interrupted,
-        )
-        .map(|_| ())?;
-
-        // Check if what we got from the container is a file or a directory.
-        if metadata(&intermediate)
-            .map_err(failure::system(format!(
-                "Unable to retrieve filesystem metadata for path {}.",
-                intermediate.to_string_lossy().code_str(),
-            )))?
-            .is_file()
-        {
-            // It's a file. Determine the destination directory. The `unwrap` is safe because the
-            // root of the filesystem cannot be a file.
-            let destination_dir = destination.parent().unwrap().to_owned();
-
-            // Make sure the destination directory exists.
-            create_dir_all(&destination_dir).map_err(failure::system(format!(
-                "Unable to create directory {}.",
-                destination_dir.to_string_lossy().code_str(),
-            )))?;
-
-            // Move it to the destination.
-            rename(&intermediate, &destination).map_err(failure::system(format!(
-                "Unable to move file {} to destination {}.",
-                intermediate.to_string_lossy().code_str(),
-                destination.to_string_lossy().code_str(),
-            )))?;
-        } else {
-            // It's a directory. Traverse it.
-            for entry in WalkDir::new(&intermediate) {
-                // If we run into an error traversing the filesystem, report it.
-                let entry = entry.map_err(failure::system(format!(
-                    "Unable to traverse directory {}.",
-                    intermediate.to_string_lossy().code_str(),
-                )))?;
-
-                // Figure out what needs to go where. The `unwrap` is safe because `entry` is
-                // guaranteed to be inside `intermediate` (or equal to it).
-                let entry_path = entry.path();
-                let destination_path =
-                    destination.join(entry_path.strip_prefix(&intermediate).unwrap());
-
-                // Check if the current entry is a file or a directory.
-                if entry.file_type().is_dir() {
-                    // It's a directory. Create a directory at the destination.
-                    create_dir_all(&destination_path).map_err(failure::system(format!(
-                        "Unable to create directory {}.",
-                        destination_path.to_string_lossy().code_str(),
-                    )))?;
-                } else {
-                    // It's a file. Move it to the destination.
-                    rename(entry_path, &destination_path).map_err(failure::system(format!(
-                        "Unable to move file {} to destination {}.",
-                        entry_path.to_string_lossy().code_str(),
-                        destination_path.to_string_lossy().code_str(),
-                    )))?;
-                }
+        )?;
+    }
+
+    Ok(())
+}
+
+// Check that a path to be mirrored resolves to a proper subdirectory of the given directory.
+fn check_mirror_destination(destination: &Path, directory: &Path) -> Result<(), Failure> {
+    // Canonicalize the directory.
+    let canonical_directory = directory.canonicalize().map_err(failure::system(format!(
+        "Unable to canonicalize path {}.",
+        directory.to_string_lossy().code_str(),
+    )))?;
+
+    // The destination might not exist yet, so canonicalize its nearest existing ancestor and
+    // append the rest.
+    let mut existing = destination.to_owned();
+    let mut rest = vec![];
+    while existing.symlink_metadata().is_err() {
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                rest.push(name.to_owned());
+                existing = parent.to_owned();
             }
+            _ => break,
         }
     }
+    let mut canonical_destination = existing.canonicalize().map_err(failure::system(format!(
+        "Unable to canonicalize path {}.",
+        existing.to_string_lossy().code_str(),
+    )))?;
+    for name in rest.into_iter().rev() {
+        canonical_destination.push(name);
+    }
+
+    // Make sure the destination is strictly inside the directory.
+    if canonical_destination == canonical_directory
+        || !canonical_destination.starts_with(&canonical_directory)
+    {
+        return Err(Failure::User(
+            format!(
+                "Refusing to mirror {} because it is not inside {}.",
+                destination.to_string_lossy().code_str(),
+                directory.to_string_lossy().code_str(),
+            ),
+            None,
+        ));
+    }
 
     Ok(())
 }
 
-// Start a container.
-pub fn start_container(container: &str, interrupted: &Arc<AtomicBool>) -> Result<(), Failure> {
+// Start a container. If the redactor has anything to mask, the output of the container is routed
+// through it before being shown to the user.
+pub fn start_container(
+    container: &str,
+    redactor: &Redactor,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<(), Failure> {
     debug!("Starting container {}\u{2026}", container.code_str());
 
-    run_loud(
-        "Unable to start container.",
-        &["container", "start", "--attach", container],
-        interrupted,
-    )
-    .map(|_| ())
+    let args = ["container", "start", "--attach", container];
+
+    if redactor.is_empty() {
+        run_loud("Unable to start container.", &args, interrupted)
+    } else {
+        run_loud_redacted("Unable to start container.", &args, redactor, interrupted)
+    }
 }
 
 // Stop a container.
@@ -505,6 +553,67 @@ fn run_quiet_stdin<W: FnOnce(&mut ChildStdin) -> Result<(), Failure>>(
     }
 }
 
+// Run a command and discard its standard output. Accepts a closure which receives a pipe from the
+// standard output stream of the child process.
+fn run_quiet_stdout<R: FnOnce(&mut ChildStdout) -> Result<(), Failure>>(
+    spinner_message: &str,
+    error: &str,
+    args: &[&str],
+    reader: R,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<(), Failure> {
+    // Render a spinner animation and clear it when we're done.
+    let _guard = spin(spinner_message);
+
+    // This is used to determine whether the user interrupted the program during the execution of
+    // the child process.
+    let was_interrupted = interrupted.load(Ordering::SeqCst);
+
+    // Run the child process.
+    let mut child = command(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped()) // [tag:run_quiet_stdout_piped]
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(failure::system(format!(
+            "{} Perhaps you don't have Docker installed.",
+            error
+        )))?;
+
+    // Consume the child's standard output stream.
+    let result = reader(child.stdout.as_mut().unwrap()); // [ref:run_quiet_stdout_piped]
+
+    // Wait for the child to terminate.
+    let output = child.wait_with_output().map_err(failure::system(format!(
+        "{} Perhaps you don't have Docker installed.",
+        error
+    )))?;
+
+    // Handle the result. If the child failed, its error takes precedence over any error from the
+    // reader, since the latter is likely just a consequence of the former.
+    if output.status.success() {
+        result
+    } else {
+        Err(
+            if output.status.code().is_none()
+                || (!was_interrupted && interrupted.load(Ordering::SeqCst))
+            {
+                interrupted.store(true, Ordering::SeqCst);
+                Failure::Interrupted
+            } else {
+                Failure::System(
+                    format!(
+                        "{} Details:\n{}",
+                        error,
+                        String::from_utf8_lossy(&output.stderr)
+                    ),
+                    None,
+                )
+            },
+        )
+    }
+}
+
 // Run a command and inherit standard output and error streams.
 fn run_loud(error: &str, args: &[&str], interrupted: &Arc<AtomicBool>) -> Result<(), Failure> {
     // This is used to determine whether the user interrupted the program during the execution of
@@ -541,6 +650,69 @@ fn run_loud(error: &str, args: &[&str], interrupted: &Arc<AtomicBool>) -> Result
     }
 }
 
+// Run a command and redact its standard output and error streams before writing them to ours.
+fn run_loud_redacted(
+    error: &str,
+    args: &[&str],
+    redactor: &Redactor,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<(), Failure> {
+    // This is used to determine whether the user interrupted the program during the execution of
+    // the child process.
+    let was_interrupted = interrupted.load(Ordering::SeqCst);
+
+    // Run the child process.
+    let mut child = command(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped()) // [tag:run_loud_redacted_stdout_piped]
+        .stderr(Stdio::piped()) // [tag:run_loud_redacted_stderr_piped]
+        .spawn()
+        .map_err(failure::system(format!(
+            "{} Perhaps you don't have Docker installed.",
+            error
+        )))?;
+
+    // Forward both streams concurrently, so the child doesn't block on a full pipe. The `unwrap`s
+    // are safe because both streams are piped.
+    // If a stream can't be forwarded, the rest of it is discarded so the child can still finish.
+    let mut child_stdout = child.stdout.take().unwrap(); // [ref:run_loud_redacted_stdout_piped]
+    let mut child_stderr = child.stderr.take().unwrap(); // [ref:run_loud_redacted_stderr_piped]
+    scope(|s| {
+        s.spawn(|_| {
+            if let Err(e) = redact::copy(redactor, &mut child_stderr, stderr()) {
+                debug!("Unable to forward standard error stream. Details: {}", e);
+                let _ = io::copy(&mut child_stderr, &mut sink());
+            }
+        });
+
+        if let Err(e) = redact::copy(redactor, &mut child_stdout, stdout()) {
+            debug!("Unable to forward standard output stream. Details: {}", e);
+            let _ = io::copy(&mut child_stdout, &mut sink());
+        }
+    })
+    .map_err(|_| Failure::System(format!("{} A thread panicked.", error), None))?;
+
+    // Wait for the child to terminate.
+    let status = child.wait().map_err(failure::system(format!(
+        "{} Perhaps you don't have Docker installed.",
+        error
+    )))?;
+
+    // Handle the result.
+    if status.success() {
+        Ok(())
+    } else {
+        Err(
+            if status.code().is_none() || (!was_interrupted && interrupted.load(Ordering::SeqCst)) {
+                interrupted.store(true, Ordering::SeqCst);
+                Failure::Interrupted
+            } else {
+                Failure::System(error.to_owned(), None)
+            },
+        )
+    }
+}
+
 // Run a command and inherit standard input, output, and error streams.
 fn run_attach(error: &str, args: &[&str], interrupted: &Arc<AtomicBool>) -> Result<(), Failure> {
     // This is used to determine whether the user interrupted the program during the execution of
@@ -579,10 +751,37 @@ fn command(args: &[&str]) -> Command {
 
 #[cfg(test)]
 mod tests {
-    use crate::docker::random_tag;
+    use crate::docker::{check_mirror_destination, random_tag};
+    use std::{fs::create_dir, os::unix::fs::symlink};
+    use tempfile::tempdir;
 
     #[test]
     fn random_impure() {
         assert_ne!(random_tag(), random_tag());
     }
+
+    #[test]
+    fn check_mirror_destination_inside() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("foo")).unwrap();
+
+        assert!(check_mirror_destination(&dir.path().join("foo"), dir.path()).is_ok());
+        assert!(check_mirror_destination(&dir.path().join("foo/bar/baz"), dir.path()).is_ok());
+    }
+
+    #[test]
+    fn check_mirror_destination_same() {
+        let dir = tempdir().unwrap();
+
+        assert!(check_mirror_destination(&dir.path().join("."), dir.path()).is_err());
+    }
+
+    #[test]
+    fn check_mirror_destination_symlink() {
+        let dir = tempdir().unwrap();
+        let other_dir = tempdir().unwrap();
+        symlink(other_dir.path(), dir.path().join("foo")).unwrap();
+
+        assert!(check_mirror_destination(&dir.path().join("foo/bar"), dir.path()).is_err());
+    }
 }
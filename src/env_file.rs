@@ -0,0 +1,284 @@
+use crate::{failure, failure::Failure, format::CodeStr, toastfile::Toastfile};
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+// Parse the contents of an environment file. The syntax is that of dotenv files:
+//
+//   # Comments and blank lines are ignored.
+//   FOO=bar
+//   export BAZ="double-quoted values support \n, \", and \\ escapes"
+//   QUX='single-quoted values are taken literally'
+//   CORGE=unquoted values end at a comment # like this one
+//
+// On failure, the error describes the problematic line.
+pub fn parse(data: &str) -> Result<Vec<(String, String)>, String> {
+    let mut result = vec![];
+
+    for (index, line) in data.lines().enumerate() {
+        // Skip blank lines and comments.
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Split the line into a variable name and a value.
+        let line = match line.find(' ') {
+            Some(space) if &line[..space] == "export" => line[space..].trim_start(),
+            _ => line,
+        };
+        let equals = line
+            .find('=')
+            .ok_or_else(|| format!("Line {} is missing {}.", index + 1, "=".code_str()))?;
+        let variable = line[..equals].trim_end();
+        if variable.is_empty()
+            || !variable
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+        {
+            return Err(format!(
+                "Line {} has an invalid variable name {}.",
+                index + 1,
+                variable.code_str(),
+            ));
+        }
+
+        // Parse the value.
+        let value = parse_value(line[equals + 1..].trim_start())
+            .map_err(|error| format!("Line {} {}.", index + 1, error))?;
+
+        result.push((variable.to_owned(), value));
+    }
+
+    Ok(result)
+}
+
+// Parse the value part of a line in an environment file.
+fn parse_value(value: &str) -> Result<String, &'static str> {
+    let mut chars = value.chars();
+    let quote = chars.next();
+    let rest = chars.as_str();
+
+    if quote == Some('\'') {
+        // Single-quoted values are taken literally.
+        let end = rest
+            .find('\'')
+            .ok_or("has an unterminated single-quoted value")?;
+        check_trailing(&rest[end + 1..])?;
+        Ok(rest[..end].to_owned())
+    } else if quote == Some('"') {
+        // Double-quoted values support a few escape sequences.
+        let mut result = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    check_trailing(&rest[i + 1..])?;
+                    return Ok(result);
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => result.push('\n'),
+                    Some((_, 'r')) => result.push('\r'),
+                    Some((_, 't')) => result.push('\t'),
+                    Some((_, c)) => result.push(c),
+                    None => break,
+                },
+                c => result.push(c),
+            }
+        }
+        Err("has an unterminated double-quoted value")
+    } else {
+        // Unquoted values end at a comment, and surrounding whitespace is ignored.
+        let end = value.find(" #").unwrap_or(value.len());
+        Ok(value[..end].trim_end().to_owned())
+    }
+}
+
+// Make sure there's nothing but whitespace or a comment after a quoted value.
+fn check_trailing(trailing: &str) -> Result<(), &'static str> {
+    let trailing = trailing.trim_start();
+    if trailing.is_empty() || trailing.starts_with('#') {
+        Ok(())
+    } else {
+        Err("has unexpected characters after a quoted value")
+    }
+}
+
+// Read and parse an environment file. Relative paths are relative to `source_dir`.
+pub fn load(path: &Path, source_dir: &Path) -> Result<Vec<(String, String)>, Failure> {
+    let path = source_dir.join(path);
+    let data = read_to_string(&path).map_err(failure::user(format!(
+        "Unable to read file {}.",
+        path.to_string_lossy().code_str(),
+    )))?;
+
+    parse(&data).map_err(|error| {
+        Failure::User(
+            format!(
+                "Unable to parse file {}. {}",
+                path.to_string_lossy().code_str(),
+                error,
+            ),
+            None,
+        )
+    })
+}
+
+// Read the environment files referenced by a toastfile and add the variables to the `environment`
+// of each task. The value from an environment file replaces any default in the toastfile, and the
+// task's own environment file takes precedence over the toastfile's. Since the variables become
+// part of the task's `environment`, a value provided by the host still takes precedence, and the
+// values from the files are part of the cache key.
+pub fn apply(toastfile: &mut Toastfile, source_dir: &Path) -> Result<(), Failure> {
+    // Read each file only once, even if several tasks use it.
+    let mut files = HashMap::<PathBuf, Vec<(String, String)>>::new();
+    let mut read = |path: &Path| -> Result<Vec<(String, String)>, Failure> {
+        if let Some(variables) = files.get(path) {
+            return Ok(variables.clone());
+        }
+        let variables = load(path, source_dir)?;
+        files.insert(path.to_owned(), variables.clone());
+        Ok(variables)
+    };
+
+    let toastfile_variables = match &toastfile.env_file {
+        Some(path) => read(path)?,
+        None => vec![],
+    };
+
+    for task in toastfile.tasks.values_mut() {
+        let task_variables = match &task.env_file {
+            Some(path) => read(path)?,
+            None => vec![],
+        };
+
+        for (variable, value) in toastfile_variables.iter().chain(task_variables.iter()) {
+            // Variables which the task has marked as cache-neutral stay that way.
+            if let Some(default) = task.cache_neutral_environment.get_mut(variable) {
+                *default = Some(value.clone());
+            } else {
+                task.environment
+                    .insert(variable.clone(), Some(value.clone()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        env_file::{apply, parse},
+        toastfile::parse as parse_toastfile,
+    };
+    use std::fs::write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(parse(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn parse_comprehensive() {
+        let data = r#"
+# This is a comment.
+FOO=bar
+
+export BAZ = qux # This is a comment too.
+CORGE='grault # garply'
+WALDO="fred\n\"plugh\""
+XYZZY=
+        "#;
+
+        assert_eq!(
+            parse(data),
+            Ok(vec![
+                ("FOO".to_owned(), "bar".to_owned()),
+                ("BAZ".to_owned(), "qux".to_owned()),
+                ("CORGE".to_owned(), "grault # garply".to_owned()),
+                ("WALDO".to_owned(), "fred\n\"plugh\"".to_owned()),
+                ("XYZZY".to_owned(), "".to_owned()),
+            ]),
+        );
+    }
+
+    #[test]
+    fn parse_missing_equals() {
+        let result = parse("FOO=bar\nBAZ");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Line 2"));
+    }
+
+    #[test]
+    fn parse_invalid_name() {
+        let result = parse("FOO BAR=baz");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("FOO BAR"));
+    }
+
+    #[test]
+    fn parse_unterminated_quote() {
+        assert!(parse("FOO=\"bar").is_err());
+        assert!(parse("FOO='bar").is_err());
+    }
+
+    #[test]
+    fn parse_trailing_characters() {
+        assert!(parse("FOO='bar' baz").is_err());
+    }
+
+    #[test]
+    fn apply_precedence() {
+        let dir = tempdir().unwrap();
+        write(dir.path().join("global.env"), "FOO=global\nBAR=global\n").unwrap();
+        write(dir.path().join("task.env"), "BAR=task\n").unwrap();
+
+        let input = r#"
+image: encom:os-12
+env_file: global.env
+tasks:
+  foo:
+    env_file: task.env
+    environment:
+      FOO: default
+      BAZ: default
+    cache_neutral_environment:
+      BAR: null
+        "#
+        .trim();
+
+        let mut toastfile = parse_toastfile(input).unwrap();
+        apply(&mut toastfile, dir.path()).unwrap();
+
+        let task = &toastfile.tasks["foo"];
+        assert_eq!(task.environment["FOO"], Some("global".to_owned()));
+        assert_eq!(task.environment["BAZ"], Some("default".to_owned()));
+        assert!(!task.environment.contains_key("BAR"));
+        assert_eq!(
+            task.cache_neutral_environment["BAR"],
+            Some("task".to_owned()),
+        );
+    }
+
+    #[test]
+    fn apply_missing_file() {
+        let dir = tempdir().unwrap();
+
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    env_file: missing.env
+        "#
+        .trim();
+
+        let mut toastfile = parse_toastfile(input).unwrap();
+        let result = apply(&mut toastfile, dir.path());
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("missing.env"));
+    }
+}
@@ -0,0 +1,692 @@
+use crate::{failure::Failure, schema};
+use serde_yaml::{Mapping, Value};
+
+// The top-level sections of a toastfile which map names to tasks
+const TASK_SECTIONS: &[&str] = &["tasks", "templates"];
+
+// The top-level section of a toastfile which has the fields of a task
+const DEFAULTS_SECTION: &str = "defaults";
+
+// Count the spaces at the start of a line.
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+// Determine whether a line has nothing but whitespace.
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+// Determine whether a line has nothing but a comment.
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+// Determine whether a line starts an item of a block sequence.
+fn is_item(line: &str) -> bool {
+    let line = line.trim_start();
+    line == "-" || line.starts_with("- ")
+}
+
+// Split a line which starts a mapping entry into the key and the rest of the line. The key is
+// returned as written (e.g., with quotes), along with the position of the colon.
+fn split_key(line: &str) -> Option<(&str, usize)> {
+    let start = indentation(line);
+    let bytes = line.as_bytes();
+    let mut index = start;
+
+    // Skip over a quoted key.
+    if let Some(quote) = line[start..]
+        .chars()
+        .next()
+        .filter(|c| *c == '"' || *c == '\'')
+    {
+        index = start + 1 + line[start + 1..].find(quote)? + 1;
+    }
+
+    // Find the colon which ends the key.
+    while index < bytes.len() {
+        if bytes[index] == b':'
+            && bytes
+                .get(index + 1)
+                .map_or(true, |next| *next == b' ' || *next == b'\t')
+        {
+            return Some((line[start..index].trim_end(), index));
+        }
+        index += 1;
+    }
+
+    None
+}
+
+// Remove the quotes around a key or scalar, if there are any.
+fn unquote(text: &str) -> &str {
+    let text = text.trim();
+    if text.len() >= 2
+        && ((text.starts_with('"') && text.ends_with('"'))
+            || (text.starts_with('\'') && text.ends_with('\'')))
+    {
+        &text[1..text.len() - 1]
+    } else {
+        text
+    }
+}
+
+// Determine whether the rest of a line after a key means the value is a block on the following
+// lines, i.e., there's nothing but an optional anchor and an optional comment.
+fn opens_block(rest: &str) -> bool {
+    let mut tokens = rest.split_whitespace();
+    match tokens.next() {
+        None => true,
+        Some(token) if token.starts_with('#') => true,
+        Some(token) if token.starts_with('&') => {
+            tokens.next().map_or(true, |token| token.starts_with('#'))
+        }
+        Some(_) => false,
+    }
+}
+
+// Determine whether the rest of a line after a key starts a block scalar which keeps its trailing
+// blank lines, such as `|+`.
+fn keeps_trailing_lines(rest: &str) -> bool {
+    rest.split_whitespace().next().map_or(false, |indicator| {
+        (indicator.starts_with('|') || indicator.starts_with('>')) && indicator.contains('+')
+    })
+}
+
+// One entry of a mapping, along with the comments before it
+struct Entry {
+    comments: Vec<String>,
+    lines: Vec<String>,
+}
+
+impl Entry {
+    // The key of the entry, without quotes
+    fn key(&self) -> &str {
+        // The first line of an entry always has a key [ref:entry_has_key].
+        split_key(&self.lines[0]).map_or("", |(key, _)| unquote(key))
+    }
+
+    // The rest of the first line of the entry after the key
+    fn rest(&self) -> &str {
+        split_key(&self.lines[0]).map_or("", |(_, colon)| &self.lines[0][colon + 1..])
+    }
+}
+
+// Split the lines of a block mapping into entries, along with any comments after the last one. The
+// result is `None` if the lines aren't a block mapping this formatter understands. Blank lines
+// between entries are removed.
+fn entries(lines: &[String]) -> Option<(Vec<Entry>, Vec<String>)> {
+    let indent = indentation(
+        lines
+            .iter()
+            .find(|line| !is_blank(line) && !is_comment(line))?,
+    );
+
+    let mut entries: Vec<Entry> = vec![];
+    let mut comments = vec![];
+    let mut blanks = 0;
+    for line in lines {
+        if is_blank(line) {
+            blanks += 1;
+            continue;
+        }
+
+        let line_indent = indentation(line);
+        if line_indent < indent && !is_comment(line) {
+            return None;
+        }
+
+        if is_comment(line) && line_indent <= indent {
+            // This comment belongs to the next entry, unless the current entry continues after it.
+            comments.push(line.clone());
+        } else if line_indent == indent && !is_comment(line) && !is_item(line) {
+            // This line starts a new entry. Block scalars which keep their trailing blank lines
+            // need them to be preserved. [tag:entry_has_key]
+            split_key(line)?;
+            if let Some(entry) = entries.last_mut() {
+                if keeps_trailing_lines(entry.rest()) {
+                    entry.lines.extend((0..blanks).map(|_| String::new()));
+                }
+            }
+            entries.push(Entry {
+                comments: comments.split_off(0),
+                lines: vec![line.clone()],
+            });
+            blanks = 0;
+        } else {
+            // This line continues the current entry.
+            let entry = entries.last_mut()?;
+            entry.lines.extend((0..blanks).map(|_| String::new()));
+            entry.lines.append(&mut comments);
+            entry.lines.push(line.clone());
+            blanks = 0;
+        }
+    }
+
+    if let Some(entry) = entries.last_mut() {
+        if keeps_trailing_lines(entry.rest()) && comments.is_empty() {
+            entry.lines.extend((0..blanks).map(|_| String::new()));
+        }
+    }
+
+    Some((entries, comments))
+}
+
+// Sort the dependencies of a task, which are either a flow sequence on the same line or a block
+// sequence. Comments before an item move with it.
+fn sort_dependencies(entry: &mut Entry) {
+    let rest = entry.rest().trim().to_owned();
+
+    // Handle flow sequences like `[foo, bar]` which don't have any quotes or comments inside
+    // them. A trailing comment is kept as is.
+    if rest.starts_with('[') {
+        if let Some(end) = rest.find(']') {
+            let inside = &rest[1..end];
+            let after = &rest[end + 1..];
+            if entry.lines.len() == 1
+                && !inside.contains(|c| "#\"'[".contains(c))
+                && (after.trim().is_empty() || after.trim_start().starts_with('#'))
+            {
+                let mut items = inside
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .collect::<Vec<_>>();
+                items.sort_unstable();
+                let prefix = &entry.lines[0][..entry.lines[0].find('[').unwrap()]; // Safe due to above
+                entry.lines[0] = format!("{}[{}]{}", prefix, items.join(", "), after);
+            }
+        }
+        return;
+    }
+
+    if !opens_block(&rest) {
+        return;
+    }
+
+    // Split the block sequence into items.
+    let item_indent = match entry.lines[1..]
+        .iter()
+        .find(|line| !is_blank(line) && !is_comment(line))
+    {
+        Some(line) if is_item(line) => indentation(line),
+        _ => return,
+    };
+    let mut items: Vec<Vec<String>> = vec![];
+    let mut pending = vec![];
+    for line in &entry.lines[1..] {
+        if is_item(line) && indentation(line) == item_indent {
+            pending.push(line.clone());
+            items.push(pending.split_off(0));
+        } else if is_blank(line) || (is_comment(line) && indentation(line) <= item_indent) {
+            pending.push(line.clone());
+        } else if let Some(item) = items.last_mut() {
+            item.append(&mut pending);
+            item.push(line.clone());
+        } else {
+            return;
+        }
+    }
+
+    // Sort the items by the names of the dependencies.
+    let name = |item: &Vec<String>| {
+        item.iter()
+            .find(|line| is_item(line) && indentation(line) == item_indent)
+            .map(|line| unquote(line.trim_start()[1..].trim()).to_owned())
+            .unwrap_or_default()
+    };
+    items.sort_by_key(name);
+
+    let header = entry.lines[0].clone();
+    entry.lines = vec![header];
+    entry.lines.extend(items.into_iter().flatten());
+    entry.lines.extend(pending);
+}
+
+// Rewrite a multi-line `command` as a literal block scalar indented two spaces more than its key,
+// if its value can be represented that way.
+fn normalize_command(entry: &mut Entry, indent: usize) {
+    let rest = entry.rest().trim().to_owned();
+    let is_block = rest.starts_with('|') || rest.starts_with('>');
+
+    // Determine the value by parsing the entry on its own.
+    let text = entry
+        .lines
+        .iter()
+        .map(|line| format!("{}\n", line.get(indent..).unwrap_or("")))
+        .collect::<String>();
+    let value = match serde_yaml::from_str::<Value>(&text).ok().and_then(|value| {
+        value
+            .get("command")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+    }) {
+        Some(value) => value,
+        None => return,
+    };
+
+    // Only block scalars and multi-line values are rewritten. For the latter, a comment can't be
+    // told apart from a `#` in the value, so lines with `#` are left alone.
+    if !is_block && (!value.contains('\n') || entry.lines.iter().any(|line| line.contains('#'))) {
+        return;
+    }
+
+    // Keep the comment after the block scalar indicator, if there is one.
+    let comment = if is_block {
+        let after_indicator = rest
+            .find(char::is_whitespace)
+            .map_or("", |index| rest[index..].trim());
+        if !after_indicator.is_empty() && !after_indicator.starts_with('#') {
+            return;
+        }
+        after_indicator.to_owned()
+    } else {
+        String::new()
+    };
+
+    // The value must not need an indentation indicator, and it must not have lines of only
+    // whitespace, since those are indistinguishable from blank lines.
+    let content = value.trim_end_matches('\n');
+    let lines = content.split('\n').collect::<Vec<_>>();
+    if content.is_empty()
+        || content.contains('\r')
+        || lines
+            .iter()
+            .find(|line| !line.is_empty())
+            .map_or(false, |line| line.starts_with(|c: char| c.is_whitespace()))
+        || lines
+            .iter()
+            .any(|line| !line.is_empty() && line.trim().is_empty())
+    {
+        return;
+    }
+
+    // Choose the chomping indicator which preserves the trailing line breaks.
+    let trailing = value.len() - content.len();
+    let indicator = match trailing {
+        0 => "|-",
+        1 => "|",
+        _ => "|+",
+    };
+
+    let (key, _) = split_key(&entry.lines[0]).unwrap(); // [ref:entry_has_key]
+    let mut header = format!("{}{}: {}", " ".repeat(indent), key, indicator);
+    if !comment.is_empty() {
+        header.push(' ');
+        header.push_str(&comment);
+    }
+
+    entry.lines = vec![header];
+    entry.lines.extend(lines.iter().map(|line| {
+        if line.is_empty() {
+            String::new()
+        } else {
+            format!("{}{}", " ".repeat(indent + 2), line)
+        }
+    }));
+    entry.lines.extend((1..trailing).map(|_| String::new()));
+}
+
+// Format the fields of a task: put them in canonical order, sort the dependencies, and normalize
+// the `command`. If the lines aren't understood, they're returned unchanged.
+fn format_task(lines: &[String]) -> Vec<String> {
+    let (mut entries, trailing_comments) = match entries(lines) {
+        Some(result) => result,
+        None => return lines.to_vec(),
+    };
+    let indent = indentation(&entries[0].lines[0]);
+
+    // Fields which aren't task fields (such as the YAML merge key `<<`) go first.
+    entries.sort_by_key(|entry| {
+        schema::TASK_FIELDS
+            .iter()
+            .position(|(field, _)| *field == entry.key())
+    });
+
+    let mut result = vec![];
+    for mut entry in entries {
+        match entry.key() {
+            "dependencies" => sort_dependencies(&mut entry),
+            "command" => normalize_command(&mut entry, indent),
+            _ => {}
+        }
+        result.extend(entry.comments);
+        result.extend(entry.lines);
+    }
+    result.extend(trailing_comments);
+    result
+}
+
+// Split lines into the body of a block and the blank lines and outdented comments at the end,
+// which belong to whatever comes next.
+fn split_trailing(lines: &[String], indent: usize) -> (&[String], &[String]) {
+    let body_length = lines
+        .iter()
+        .rposition(|line| !(is_blank(line) || (is_comment(line) && indentation(line) <= indent)))
+        .map_or(0, |index| index + 1);
+    lines.split_at(body_length)
+}
+
+// Format the tasks in a section like `tasks`. The order of the tasks and the lines between them
+// are preserved.
+fn format_tasks(lines: &[String]) -> Vec<String> {
+    let indent = match lines
+        .iter()
+        .find(|line| !is_blank(line) && !is_comment(line))
+    {
+        Some(line) => indentation(line),
+        None => return lines.to_vec(),
+    };
+    if lines
+        .iter()
+        .any(|line| !is_blank(line) && !is_comment(line) && indentation(line) < indent)
+    {
+        return lines.to_vec();
+    }
+
+    // Find the lines which start tasks.
+    let headers = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !is_blank(line) && !is_comment(line) && indentation(line) == indent)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    let mut result = lines[..headers[0]].to_vec();
+    for (i, header) in headers.iter().enumerate() {
+        let end = headers.get(i + 1).cloned().unwrap_or(lines.len());
+        let (body, trailing) = split_trailing(&lines[header + 1..end], indent);
+        result.push(lines[*header].clone());
+        match split_key(&lines[*header]) {
+            Some((_, colon)) if opens_block(&lines[*header][colon + 1..]) && !body.is_empty() => {
+                result.extend(format_task(body));
+            }
+            _ => result.extend(body.iter().cloned()),
+        }
+        result.extend(trailing.iter().cloned());
+    }
+
+    result
+}
+
+// Put a value in a form which doesn't depend on the order of mapping keys or dependencies, for
+// checking that formatting doesn't change the meaning of a toastfile.
+fn canonical(value: &Value) -> Value {
+    match value {
+        Value::Mapping(mapping) => {
+            let mut entries = mapping
+                .iter()
+                .map(|(key, value)| {
+                    let mut value = canonical(value);
+                    if key.as_str() == Some("dependencies") {
+                        if let Value::Sequence(items) = &mut value {
+                            items.sort_by_key(|item| format!("{:?}", item));
+                        }
+                    }
+                    (canonical(key), value)
+                })
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|(key, _)| format!("{:?}", key));
+            Value::Mapping(entries.into_iter().collect::<Mapping>())
+        }
+        Value::Sequence(items) => Value::Sequence(items.iter().map(canonical).collect()),
+        value => value.clone(),
+    }
+}
+
+// Rewrite a YAML toastfile in canonical form. The fields of each task (and of the `defaults`) are
+// put in the order they're documented, dependencies are sorted, and multi-line commands are written
+// as literal block scalars. Comments are preserved, along with everything else.
+pub fn format(source: &str) -> Result<String, Failure> {
+    let original = serde_yaml::from_str::<Value>(source).map_err(failure_to_parse)?;
+
+    let lines = source.lines().map(ToOwned::to_owned).collect::<Vec<_>>();
+
+    // Find the lines which start top-level entries.
+    let headers = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| {
+            !is_blank(line)
+                && !is_comment(line)
+                && indentation(line) == 0
+                && !is_item(line)
+                && !line.starts_with("---")
+                && !line.starts_with("...")
+        })
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    let mut result = lines[..headers.first().cloned().unwrap_or(lines.len())].to_vec();
+    for (i, header) in headers.iter().enumerate() {
+        let end = headers.get(i + 1).cloned().unwrap_or(lines.len());
+        let (body, trailing) = split_trailing(&lines[header + 1..end], 0);
+        result.push(lines[*header].clone());
+        match split_key(&lines[*header]) {
+            Some((key, colon)) if opens_block(&lines[*header][colon + 1..]) => {
+                let key = unquote(key);
+                if key == DEFAULTS_SECTION {
+                    result.extend(format_task(body));
+                } else if TASK_SECTIONS.contains(&key) {
+                    result.extend(format_tasks(body));
+                } else {
+                    result.extend(body.iter().cloned());
+                }
+            }
+            _ => result.extend(body.iter().cloned()),
+        }
+        result.extend(trailing.iter().cloned());
+    }
+
+    // End the file with exactly one line break.
+    while result.last().map_or(false, |line| is_blank(line)) {
+        result.pop();
+    }
+    let mut formatted = result.join("\n");
+    formatted.push('\n');
+
+    // Make sure the meaning of the toastfile didn't change.
+    let formatted_value = serde_yaml::from_str::<Value>(&formatted).map_err(failure_to_parse)?;
+    if canonical(&original) != canonical(&formatted_value) {
+        return Err(Failure::System(
+            "Unable to format the toastfile without changing its meaning.".to_owned(),
+            None,
+        ));
+    }
+
+    Ok(formatted)
+}
+
+// Convert a YAML error into a failure.
+fn failure_to_parse(error: serde_yaml::Error) -> Failure {
+    Failure::User(
+        "Unable to parse the toastfile.".to_owned(),
+        Some(Box::new(error)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formatter::format;
+
+    #[test]
+    fn format_empty() {
+        assert_eq!(format("{}").unwrap(), "{}\n");
+    }
+
+    #[test]
+    fn format_fields_ordered() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  # The task to build the program
+  build:
+    # Run the compiler.
+    command: make
+    input_paths:
+      - src
+    description: Build the program.
+
+    cache: false
+    # Trailing comment
+  test: {}
+        "#
+        .trim();
+
+        let expected = r#"
+image: encom:os-12
+tasks:
+  # The task to build the program
+  build:
+    description: Build the program.
+    cache: false
+    input_paths:
+      - src
+    # Run the compiler.
+    command: make
+    # Trailing comment
+  test: {}
+        "#
+        .trim_start()
+        .trim_end_matches(' ');
+
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_defaults() {
+        let input = r#"
+image: encom:os-12
+defaults:
+  user: user
+  cache: false
+tasks: {}
+        "#
+        .trim();
+
+        let expected = r#"
+image: encom:os-12
+defaults:
+  cache: false
+  user: user
+tasks: {}
+        "#
+        .trim_start()
+        .trim_end_matches(' ');
+
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_dependencies_sorted() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    dependencies:
+    - qux
+    # The second dependency
+    - bar
+  baz:
+    dependencies: [qux, bar] # Flow style
+        "#
+        .trim();
+
+        let expected = r#"
+image: encom:os-12
+tasks:
+  foo:
+    dependencies:
+    # The second dependency
+    - bar
+    - qux
+  baz:
+    dependencies: [bar, qux] # Flow style
+        "#
+        .trim_start()
+        .trim_end_matches(' ');
+
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_command_normalized() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    command: >-  # Folded
+        echo foo
+        echo bar
+
+        echo baz
+  bar:
+    command: "echo foo\necho bar\n"
+  baz:
+    command: echo foo
+        "#
+        .trim();
+
+        let expected = r#"
+image: encom:os-12
+tasks:
+  foo:
+    command: |- # Folded
+      echo foo echo bar
+      echo baz
+  bar:
+    command: |
+      echo foo
+      echo bar
+  baz:
+    command: echo foo
+        "#
+        .trim_start()
+        .trim_end_matches(' ');
+
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_command_preserves_blank_lines() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    command: |
+      echo foo
+
+      echo bar
+    cache: false
+        "#
+        .trim();
+
+        let expected = r#"
+image: encom:os-12
+tasks:
+  foo:
+    cache: false
+    command: |
+      echo foo
+
+      echo bar
+        "#
+        .trim_start()
+        .trim_end_matches(' ');
+
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_idempotent() {
+        let formatted = format(include_str!("../toast.yml")).unwrap();
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn format_toast_yml_formatted() {
+        let toastfile = include_str!("../toast.yml");
+        assert_eq!(format(toastfile).unwrap(), toastfile);
+    }
+}
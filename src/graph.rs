@@ -0,0 +1,300 @@
+use crate::{schedule, toastfile::Toastfile};
+use std::collections::HashMap;
+
+// The formats for rendering the dependency graph
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Dot,
+    Mermaid,
+}
+
+// The names of the formats, as accepted on the command line. Each one is recognized by
+// `from_name`. [tag:graph_format_valid]
+pub const FORMAT_NAMES: &[&str] = &["dot", "mermaid"];
+
+impl Format {
+    // Look up a format by its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "dot" => Some(Format::Dot),
+            "mermaid" => Some(Format::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+// A task, along with everything the graph highlights about it
+struct Node<'a> {
+    name: &'a str,
+
+    // The position of the task in the schedule, starting from 1, if it's scheduled at all
+    position: Option<usize>,
+
+    root: bool,
+    cache: bool,
+
+    // Whether the task would be read from the local cache, if that's known
+    cached: Option<bool>,
+}
+
+impl<'a> Node<'a> {
+    fn label(&self) -> String {
+        match self.position {
+            Some(position) => format!("{}. {}", position, self.name),
+            None => self.name.to_owned(),
+        }
+    }
+}
+
+// Render the tasks of a toastfile and the dependencies between them. Each edge points from a
+// dependency to the task which depends on it, which is the order they run in. The tasks which
+// would run for the given roots are numbered in the order of the schedule, and the rest are grayed
+// out. If the cache status is given, it says which scheduled tasks would be read from the local
+// cache.
+pub fn render(
+    toastfile: &Toastfile,
+    roots: &[&str],
+    cache_status: Option<&HashMap<String, bool>>,
+    format: Format,
+) -> String {
+    let schedule = schedule::compute(toastfile, roots);
+
+    let mut task_names = toastfile.tasks.keys().collect::<Vec<_>>();
+    task_names.sort();
+
+    let nodes = task_names
+        .iter()
+        .map(|name| Node {
+            name,
+            position: schedule
+                .iter()
+                .position(|task| task == name)
+                .map(|index| index + 1),
+            root: roots.contains(&name.as_str()),
+            cache: toastfile.tasks[*name].cache,
+            cached: cache_status.and_then(|status| status.get(*name).cloned()),
+        })
+        .collect::<Vec<_>>();
+
+    let mut edges = vec![];
+    for name in &task_names {
+        let mut dependencies = toastfile.tasks[*name]
+            .dependencies
+            .iter()
+            .collect::<Vec<_>>();
+        dependencies.sort();
+        for dependency in dependencies {
+            edges.push((dependency.as_str(), name.as_str()));
+        }
+    }
+
+    match format {
+        Format::Dot => dot(&nodes, &edges),
+        Format::Mermaid => mermaid(&nodes, &edges),
+    }
+}
+
+// Quote a string for Graphviz.
+fn dot_quote(string: &str) -> String {
+    format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Render the graph in the Graphviz DOT language.
+fn dot(nodes: &[Node], edges: &[(&str, &str)]) -> String {
+    let mut output = "digraph toast {\n    rankdir=LR;\n    node [shape=box];\n".to_owned();
+
+    for node in nodes {
+        let mut styles = vec![];
+        let mut attributes = vec![format!("label={}", dot_quote(&node.label()))];
+        if node.root {
+            styles.push("bold");
+            attributes.push("penwidth=2".to_owned());
+        }
+        if !node.cache {
+            styles.push("dashed");
+        }
+        if let Some(cached) = node.cached {
+            styles.push("filled");
+            attributes.push(format!(
+                "fillcolor={}",
+                if cached { "palegreen" } else { "lightyellow" },
+            ));
+        }
+        if node.position.is_none() {
+            attributes.push("color=gray".to_owned());
+            attributes.push("fontcolor=gray".to_owned());
+        }
+        if !styles.is_empty() {
+            attributes.push(format!("style={}", dot_quote(&styles.join(","))));
+        }
+
+        output.push_str(&format!(
+            "    {} [{}];\n",
+            dot_quote(node.name),
+            attributes.join(", "),
+        ));
+    }
+
+    for (from, to) in edges {
+        output.push_str(&format!("    {} -> {};\n", dot_quote(from), dot_quote(to)));
+    }
+
+    output.push_str("}\n");
+    output
+}
+
+// The Mermaid classes for highlighting tasks, and their styles
+const MERMAID_CLASSES: &[(&str, &str)] = &[
+    ("root", "stroke-width:3px"),
+    ("uncacheable", "stroke-dasharray:5 5"),
+    ("cached", "fill:#cfc"),
+    ("uncached", "fill:#ffc"),
+    ("unscheduled", "color:#999,stroke:#999"),
+];
+
+// Render the graph as a Mermaid flowchart. Task names can contain characters Mermaid doesn't allow
+// in node IDs, so the nodes are numbered instead.
+fn mermaid(nodes: &[Node], edges: &[(&str, &str)]) -> String {
+    let ids = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (node.name, format!("task{}", index)))
+        .collect::<HashMap<_, _>>();
+
+    let mut output = "flowchart LR\n".to_owned();
+
+    for node in nodes {
+        output.push_str(&format!(
+            "    {}[\"{}\"]\n",
+            ids[node.name],
+            node.label().replace('"', "#quot;"),
+        ));
+    }
+
+    for (from, to) in edges {
+        output.push_str(&format!("    {} --> {}\n", ids[from], ids[to]));
+    }
+
+    for (class, style) in MERMAID_CLASSES {
+        let members = nodes
+            .iter()
+            .filter(|node| match *class {
+                "root" => node.root,
+                "uncacheable" => !node.cache,
+                "cached" => node.cached == Some(true),
+                "uncached" => node.cached == Some(false),
+                "unscheduled" => node.position.is_none(),
+                _ => false,
+            })
+            .map(|node| ids[node.name].as_str())
+            .collect::<Vec<_>>();
+        if !members.is_empty() {
+            output.push_str(&format!("    classDef {} {}\n", class, style));
+            output.push_str(&format!("    class {} {}\n", members.join(","), class));
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        graph::{render, Format},
+        toastfile::{parse, Toastfile},
+    };
+    use std::collections::HashMap;
+
+    fn toastfile() -> Toastfile {
+        let input = r#"
+image: encom:os-12
+tasks:
+  build: {}
+  deploy:
+    cache: false
+    dependencies:
+      - test
+  lint:
+    dependencies:
+      - build
+  test:
+    dependencies:
+      - build
+        "#
+        .trim();
+
+        parse(input).unwrap()
+    }
+
+    #[test]
+    fn render_dot() {
+        assert_eq!(
+            render(&toastfile(), &["test"], None, Format::Dot),
+            r#"digraph toast {
+    rankdir=LR;
+    node [shape=box];
+    "build" [label="1. build"];
+    "deploy" [label="deploy", color=gray, fontcolor=gray, style="dashed"];
+    "lint" [label="lint", color=gray, fontcolor=gray];
+    "test" [label="2. test", penwidth=2, style="bold"];
+    "test" -> "deploy";
+    "build" -> "lint";
+    "build" -> "test";
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn render_dot_cache_status() {
+        let mut cache_status = HashMap::new();
+        cache_status.insert("build".to_owned(), true);
+        cache_status.insert("test".to_owned(), false);
+
+        let graph = render(&toastfile(), &["deploy"], Some(&cache_status), Format::Dot);
+
+        assert!(graph
+            .contains("\"build\" [label=\"1. build\", fillcolor=palegreen, style=\"filled\"];\n",));
+        assert!(graph
+            .contains("\"test\" [label=\"2. test\", fillcolor=lightyellow, style=\"filled\"];\n",));
+        assert!(graph
+            .contains("\"deploy\" [label=\"3. deploy\", penwidth=2, style=\"bold,dashed\"];\n",));
+    }
+
+    #[test]
+    fn render_mermaid() {
+        assert_eq!(
+            render(&toastfile(), &["lint", "test"], None, Format::Mermaid),
+            r#"flowchart LR
+    task0["1. build"]
+    task1["deploy"]
+    task2["2. lint"]
+    task3["3. test"]
+    task3 --> task1
+    task0 --> task2
+    task0 --> task3
+    classDef root stroke-width:3px
+    class task2,task3 root
+    classDef uncacheable stroke-dasharray:5 5
+    class task1 uncacheable
+    classDef unscheduled color:#999,stroke:#999
+    class task1 unscheduled
+"#,
+        );
+    }
+
+    #[test]
+    fn render_mermaid_quotes() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  say "hi": {}
+        "#
+        .trim();
+
+        let toastfile = parse(input).unwrap();
+
+        assert!(render(&toastfile, &["say \"hi\""], None, Format::Mermaid)
+            .contains("task0[\"1. say #quot;hi#quot;\"]\n"));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{failure, failure::Failure, format::CodeStr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    fs::{create_dir_all, Metadata},
+    io::Write,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+// The hash cache remembers the hashes of file contents so that unchanged files don't need to be
+// read again. A file is considered unchanged if its canonical path, size, modification time, and
+// inode number are the same as when it was hashed.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HashCache {
+    files: HashMap<PathBuf, FileEntry>,
+
+    // Whether the cache has changed since it was loaded
+    #[serde(skip)]
+    dirty: bool,
+
+    // The paths which have been looked up since the cache was loaded
+    #[serde(skip)]
+    used: HashSet<PathBuf>,
+
+    // Whether lookups should always miss, so every file is read again. The entries which aren't
+    // looked up are still saved.
+    #[serde(skip)]
+    rehash: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+struct FileEntry {
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    inode: u64,
+    hash: String,
+}
+
+impl FileEntry {
+    // Construct an entry from the filesystem metadata for a file and the hash of its contents.
+    fn new(metadata: &Metadata, hash: String) -> Self {
+        FileEntry {
+            size: metadata.size(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            inode: metadata.ino(),
+            hash,
+        }
+    }
+}
+
+impl HashCache {
+    // Look up the hash of the contents of a file, if it's known and the file hasn't changed.
+    pub fn get(&mut self, path: &Path, metadata: &Metadata) -> Option<String> {
+        self.used.insert(path.to_owned());
+
+        if self.rehash {
+            return None;
+        }
+
+        self.files.get(path).and_then(|entry| {
+            if *entry == FileEntry::new(metadata, entry.hash.clone()) {
+                Some(entry.hash.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    // Remember the hash of the contents of a file.
+    pub fn insert(&mut self, path: &Path, metadata: &Metadata, hash: &str) {
+        self.used.insert(path.to_owned());
+        self.files
+            .insert(path.to_owned(), FileEntry::new(metadata, hash.to_owned()));
+        self.dirty = true;
+    }
+}
+
+// Load the hash cache from disk. If the file doesn't exist or can't be parsed, an empty cache is
+// returned, since the cache can always be rebuilt. If `rehash` is `true`, lookups in the returned
+// cache always miss.
+pub fn load(path: &Path, rehash: bool) -> HashCache {
+    debug!(
+        "Attempting to load hash cache {}\u{2026}",
+        path.to_string_lossy().code_str()
+    );
+
+    let mut hash_cache = fs::read_to_string(path)
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_else(|| {
+            debug!("Hash cache not found or unreadable. Starting with an empty one.");
+            HashCache::default()
+        });
+    hash_cache.rehash = rehash;
+    hash_cache
+}
+
+// Write the hash cache to disk, if it has changed. Entries for files which no longer exist are
+// dropped. The file is replaced atomically so concurrent invocations of Toast don't corrupt it.
+pub fn save(hash_cache: &mut HashCache, path: &Path) -> Result<(), Failure> {
+    // If nothing changed, there's nothing to do.
+    if !hash_cache.dirty {
+        return Ok(());
+    }
+
+    debug!(
+        "Writing hash cache {}\u{2026}",
+        path.to_string_lossy().code_str()
+    );
+
+    // Forget about files which have been deleted.
+    let used = &hash_cache.used;
+    hash_cache
+        .files
+        .retain(|file, _| used.contains(file) || file.exists());
+
+    // Make sure the cache directory exists. The `unwrap` is safe because the path refers to a file.
+    let directory = path.parent().unwrap();
+    create_dir_all(directory).map_err(failure::system(format!(
+        "Unable to create directory {}.",
+        directory.to_string_lossy().code_str(),
+    )))?;
+
+    // Write the cache to a temporary file next to the final location.
+    let mut file = NamedTempFile::new_in(directory).map_err(failure::system(format!(
+        "Unable to create temporary file in {}.",
+        directory.to_string_lossy().code_str(),
+    )))?;
+    let data = serde_json::to_string(hash_cache)
+        .map_err(failure::system("Unable to serialize the hash cache."))?;
+    file.write_all(data.as_bytes())
+        .map_err(failure::system(format!(
+            "Unable to write file {}.",
+            file.path().to_string_lossy().code_str(),
+        )))?;
+
+    // Move the file into place.
+    file.persist(path).map_err(failure::system(format!(
+        "Unable to write file {}.",
+        path.to_string_lossy().code_str(),
+    )))?;
+
+    hash_cache.dirty = false;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash_cache::{load, save, HashCache};
+    use std::fs::{metadata, remove_file, write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn get_missing() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("foo");
+        write(&file, "bar").unwrap();
+
+        let mut hash_cache = HashCache::default();
+
+        assert_eq!(hash_cache.get(&file, &metadata(&file).unwrap()), None);
+    }
+
+    #[test]
+    fn get_unchanged() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("foo");
+        write(&file, "bar").unwrap();
+
+        let mut hash_cache = HashCache::default();
+        hash_cache.insert(&file, &metadata(&file).unwrap(), "baz");
+
+        assert_eq!(
+            hash_cache.get(&file, &metadata(&file).unwrap()),
+            Some("baz".to_owned()),
+        );
+    }
+
+    #[test]
+    fn get_changed() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("foo");
+        write(&file, "bar").unwrap();
+
+        let mut hash_cache = HashCache::default();
+        hash_cache.insert(&file, &metadata(&file).unwrap(), "baz");
+        write(&file, "quux").unwrap();
+
+        assert_eq!(hash_cache.get(&file, &metadata(&file).unwrap()), None);
+    }
+
+    #[test]
+    fn save_load() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("foo");
+        let cache_file = dir.path().join("cache").join("hashes.json");
+        write(&file, "bar").unwrap();
+
+        let mut hash_cache = HashCache::default();
+        hash_cache.insert(&file, &metadata(&file).unwrap(), "baz");
+        save(&mut hash_cache, &cache_file).unwrap();
+
+        assert_eq!(
+            load(&cache_file, false).get(&file, &metadata(&file).unwrap()),
+            Some("baz".to_owned()),
+        );
+    }
+
+    #[test]
+    fn save_deleted() {
+        let dir = tempdir().unwrap();
+        let file1 = dir.path().join("foo");
+        let file2 = dir.path().join("bar");
+        let cache_file = dir.path().join("cache").join("hashes.json");
+        write(&file1, "baz").unwrap();
+        write(&file2, "qux").unwrap();
+
+        let mut hash_cache = HashCache::default();
+        hash_cache.insert(&file1, &metadata(&file1).unwrap(), "corge");
+        hash_cache.insert(&file2, &metadata(&file2).unwrap(), "grault");
+        save(&mut hash_cache, &cache_file).unwrap();
+
+        remove_file(&file2).unwrap();
+        let mut hash_cache = load(&cache_file, false);
+        hash_cache.insert(&file1, &metadata(&file1).unwrap(), "garply");
+        save(&mut hash_cache, &cache_file).unwrap();
+
+        let hash_cache = load(&cache_file, false);
+        assert!(hash_cache.files.contains_key(&file1));
+        assert!(!hash_cache.files.contains_key(&file2));
+    }
+
+    #[test]
+    fn load_missing() {
+        let dir = tempdir().unwrap();
+
+        assert_eq!(load(&dir.path().join("foo"), false), HashCache::default());
+    }
+
+    #[test]
+    fn rehash() {
+        let dir = tempdir().unwrap();
+        let file1 = dir.path().join("foo");
+        let file2 = dir.path().join("bar");
+        let cache_file = dir.path().join("cache").join("hashes.json");
+        write(&file1, "baz").unwrap();
+        write(&file2, "qux").unwrap();
+
+        let mut hash_cache = HashCache::default();
+        hash_cache.insert(&file1, &metadata(&file1).unwrap(), "corge");
+        hash_cache.insert(&file2, &metadata(&file2).unwrap(), "grault");
+        save(&mut hash_cache, &cache_file).unwrap();
+
+        let mut hash_cache = load(&cache_file, true);
+        assert_eq!(hash_cache.get(&file1, &metadata(&file1).unwrap()), None);
+        hash_cache.insert(&file1, &metadata(&file1).unwrap(), "garply");
+        save(&mut hash_cache, &cache_file).unwrap();
+
+        let mut hash_cache = load(&cache_file, false);
+        assert_eq!(
+            hash_cache.get(&file1, &metadata(&file1).unwrap()),
+            Some("garply".to_owned()),
+        );
+        assert_eq!(
+            hash_cache.get(&file2, &metadata(&file2).unwrap()),
+            Some("grault".to_owned()),
+        );
+    }
+}
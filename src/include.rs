@@ -0,0 +1,417 @@
+use crate::{
+    diagnostic,
+    diagnostic::Diagnostics,
+    env_file, failure,
+    failure::Failure,
+    format,
+    format::CodeStr,
+    toastfile,
+    toastfile::{FileFormat, Task, Toastfile},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Read and parse a toastfile, along with the toastfiles it includes. Each included toastfile is
+// parsed on its own, so its `defaults`, `environment`, and `templates` only apply to its own tasks.
+// Then its tasks are merged into the including toastfile, with their names (and the names of their
+// dependencies) prefixed with the namespace, if any. [tag:includes_merged]
+pub fn load(toastfile_path: &Path) -> Result<Toastfile, Failure> {
+    load_recursive(toastfile_path, &mut vec![])
+}
+
+// Load a toastfile. The `ancestors` are the toastfiles which are including this one, for detecting
+// cycles.
+fn load_recursive(
+    toastfile_path: &Path,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<Toastfile, Failure> {
+    // Read the file from disk.
+    let toastfile_data = fs::read_to_string(toastfile_path).map_err(failure::user(format!(
+        "Unable to read file {}.",
+        toastfile_path.to_string_lossy().code_str(),
+    )))?;
+
+    // Make sure the toastfile doesn't include itself, directly or indirectly.
+    let canonical_path = fs::canonicalize(toastfile_path).map_err(failure::user(format!(
+        "Unable to read file {}.",
+        toastfile_path.to_string_lossy().code_str(),
+    )))?;
+    if ancestors.contains(&canonical_path) {
+        return Err(Failure::User(
+            format!(
+                "The toastfile {} includes itself.",
+                toastfile_path.to_string_lossy().code_str(),
+            ),
+            None,
+        ));
+    }
+
+    // Parse it. Some problems don't prevent the rest of the toastfile from being loaded, so they're
+    // collected and reported at the end along with any others.
+    let file_format = FileFormat::from_path(toastfile_path);
+    let fail = |diagnostics: Diagnostics| {
+        report(toastfile_path, &toastfile_data, file_format, &diagnostics)
+    };
+    let (mut toastfile, mut diagnostics) =
+        toastfile::parse_partial(&toastfile_data, file_format).map_err(fail)?;
+
+    // Read the environment files, which are relative to the toastfile. This is done before the
+    // included tasks are merged in, since they've already read their own environment files.
+    let mut toastfile_dir = PathBuf::from(toastfile_path);
+    toastfile_dir.pop();
+    env_file::apply(&mut toastfile, &toastfile_dir)?;
+
+    // Now that the variables from the environment files are declared, make sure the references to
+    // variables are valid.
+    diagnostics.extend(toastfile::check_interpolation(&toastfile));
+
+    // Merge in the tasks from the included toastfiles, which are relative to this one.
+    ancestors.push(canonical_path);
+    let own_tasks = toastfile
+        .tasks
+        .keys()
+        .chain(toastfile.templates.keys())
+        .cloned()
+        .collect::<HashSet<_>>();
+    for include in toastfile.include.clone() {
+        let included_path = toastfile_dir.join(&include.path);
+        let included = load_recursive(&included_path, ancestors)?;
+        merge(
+            &mut toastfile.tasks,
+            &own_tasks,
+            included.tasks,
+            include.namespace.as_ref().map(AsRef::as_ref),
+        )
+        .map_err(|error| {
+            Failure::User(
+                format!(
+                    "Unable to include file {} in {}. {}",
+                    included_path.to_string_lossy().code_str(),
+                    toastfile_path.to_string_lossy().code_str(),
+                    error,
+                ),
+                None,
+            )
+        })?;
+    }
+    ancestors.pop();
+
+    // Now that all the tasks are present, make sure the dependencies are valid.
+    diagnostics.extend(toastfile::check_dependencies(&toastfile));
+    diagnostics.into_result().map_err(fail)?;
+
+    Ok(toastfile)
+}
+
+// Construct a failure for the problems with a toastfile, with a snippet of the source for each one.
+fn report(
+    toastfile_path: &Path,
+    toastfile_data: &str,
+    file_format: FileFormat,
+    diagnostics: &Diagnostics,
+) -> Failure {
+    Failure::User(
+        format!(
+            "Unable to parse file {}.\n\n{}",
+            toastfile_path.to_string_lossy().code_str(),
+            diagnostic::render(diagnostics, toastfile_path, toastfile_data, file_format),
+        ),
+        None,
+    )
+}
+
+// Prefix a task name with a namespace, if there is one.
+fn namespaced(namespace: Option<&str>, name: &str) -> String {
+    namespace.map_or_else(
+        || name.to_owned(),
+        |namespace| format!("{}.{}", namespace, name),
+    )
+}
+
+// Merge included tasks into the tasks of a toastfile. An included task can't have the same name as
+// one of the toastfile's own tasks or templates (`own_tasks`). Two included toastfiles can only
+// define a task with the same name if the definitions are identical, e.g., if both of them include
+// the same toastfile.
+fn merge(
+    tasks: &mut HashMap<String, Task>,
+    own_tasks: &HashSet<String>,
+    included_tasks: HashMap<String, Task>,
+    namespace: Option<&str>,
+) -> Result<(), String> {
+    let mut conflicts = vec![];
+    let mut renamed_tasks = vec![];
+    for (name, mut task) in included_tasks {
+        let name = namespaced(namespace, &name);
+        task.dependencies = task
+            .dependencies
+            .iter()
+            .map(|dependency| namespaced(namespace, dependency))
+            .collect();
+
+        if own_tasks.contains(&name)
+            || tasks
+                .get(&name)
+                .map_or(false, |existing_task| *existing_task != task)
+        {
+            conflicts.push(name);
+        } else {
+            renamed_tasks.push((name, task));
+        }
+    }
+
+    if !conflicts.is_empty() {
+        conflicts.sort();
+        return Err(format!(
+            "The following tasks are already defined: {}.",
+            format::series(
+                conflicts
+                    .iter()
+                    .map(|name| format!("{}", name.code_str()))
+                    .collect::<Vec<_>>()
+                    .as_ref(),
+            ),
+        ));
+    }
+
+    tasks.extend(renamed_tasks);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::include::load;
+    use std::fs::{create_dir, write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn load_namespaced() {
+        let dir = tempdir().unwrap();
+        write(
+            dir.path().join("toolchain.yml"),
+            r#"
+image: encom:os-12
+tasks:
+  install_curl:
+    command: apt-get install curl
+  install_rust:
+    dependencies:
+      - install_curl
+    command: curl https://sh.rustup.rs | sh
+            "#
+            .trim(),
+        )
+        .unwrap();
+        write(
+            dir.path().join("toast.yml"),
+            r#"
+image: encom:os-12
+include:
+  - path: toolchain.yml
+    namespace: tools
+tasks:
+  build:
+    dependencies:
+      - tools.install_rust
+    command: cargo build
+            "#
+            .trim(),
+        )
+        .unwrap();
+
+        let toastfile = load(&dir.path().join("toast.yml")).unwrap();
+
+        assert_eq!(toastfile.tasks.len(), 3);
+        assert_eq!(
+            toastfile.tasks["tools.install_rust"].dependencies,
+            vec!["tools.install_curl".to_owned()],
+        );
+    }
+
+    #[test]
+    fn load_relative_to_including_file() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("tools")).unwrap();
+        write(
+            dir.path().join("tools").join("toolchain.yml"),
+            r#"
+image: encom:os-12
+include:
+  - node.yml
+tasks:
+  install_rust: {}
+            "#
+            .trim(),
+        )
+        .unwrap();
+        write(
+            dir.path().join("tools").join("node.yml"),
+            r#"
+image: encom:os-12
+tasks:
+  install_node: {}
+            "#
+            .trim(),
+        )
+        .unwrap();
+        write(
+            dir.path().join("toast.yml"),
+            r#"
+image: encom:os-12
+include:
+  - tools/toolchain.yml
+tasks:
+  build:
+    dependencies:
+      - install_rust
+      - install_node
+            "#
+            .trim(),
+        )
+        .unwrap();
+
+        let toastfile = load(&dir.path().join("toast.yml")).unwrap();
+
+        assert_eq!(toastfile.tasks.len(), 3);
+    }
+
+    #[test]
+    fn load_diamond() {
+        let dir = tempdir().unwrap();
+        write(
+            dir.path().join("common.yml"),
+            "image: encom:os-12\ntasks:\n  install_curl: {}",
+        )
+        .unwrap();
+        write(
+            dir.path().join("rust.yml"),
+            "image: encom:os-12\ninclude:\n  - common.yml\ntasks:\n  install_rust: {}",
+        )
+        .unwrap();
+        write(
+            dir.path().join("node.yml"),
+            "image: encom:os-12\ninclude:\n  - common.yml\ntasks:\n  install_node: {}",
+        )
+        .unwrap();
+        write(
+            dir.path().join("toast.yml"),
+            "image: encom:os-12\ninclude:\n  - rust.yml\n  - node.yml\ntasks: {}",
+        )
+        .unwrap();
+
+        let toastfile = load(&dir.path().join("toast.yml")).unwrap();
+
+        assert_eq!(toastfile.tasks.len(), 3);
+    }
+
+    #[test]
+    fn load_conflict() {
+        let dir = tempdir().unwrap();
+        write(
+            dir.path().join("toolchain.yml"),
+            "image: encom:os-12\ntasks:\n  install_rust: {}",
+        )
+        .unwrap();
+        write(
+            dir.path().join("toast.yml"),
+            "image: encom:os-12\ninclude:\n  - toolchain.yml\ntasks:\n  install_rust: {}",
+        )
+        .unwrap();
+
+        let result = load(&dir.path().join("toast.yml"));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("install_rust"));
+    }
+
+    #[test]
+    fn load_diagnostics() {
+        let dir = tempdir().unwrap();
+        write(
+            dir.path().join("toast.yml"),
+            r#"
+image: encom:os-12
+tasks:
+  build:
+    dependencies:
+      - install_rust
+    location: build
+            "#
+            .trim(),
+        )
+        .unwrap();
+
+        let error = load(&dir.path().join("toast.yml")).unwrap_err().to_string();
+        assert!(error.contains("toast.yml:6:5"));
+        assert!(error.contains("install_rust"));
+    }
+
+    #[test]
+    fn load_env_file_interpolation() {
+        let dir = tempdir().unwrap();
+        write(dir.path().join(".env"), "SRC=src").unwrap();
+        write(
+            dir.path().join("toast.yml"),
+            r#"
+image: encom:os-12
+env_file: .env
+tasks:
+  build:
+    input_paths:
+      - ${SRC}
+    command: cargo build
+            "#
+            .trim(),
+        )
+        .unwrap();
+
+        let toastfile = load(&dir.path().join("toast.yml")).unwrap();
+
+        assert_eq!(
+            toastfile.tasks["build"].environment["SRC"],
+            Some("src".to_owned()),
+        );
+    }
+
+    #[test]
+    fn load_undeclared_variable() {
+        let dir = tempdir().unwrap();
+        write(
+            dir.path().join("toast.yml"),
+            r#"
+image: encom:os-12
+tasks:
+  build:
+    input_paths:
+      - ${SRC}
+    command: cargo build
+            "#
+            .trim(),
+        )
+        .unwrap();
+
+        let error = load(&dir.path().join("toast.yml")).unwrap_err().to_string();
+        assert!(error.contains("undeclared variable"));
+        assert!(error.contains("toast.yml:4:5"));
+    }
+
+    #[test]
+    fn load_cycle() {
+        let dir = tempdir().unwrap();
+        write(
+            dir.path().join("foo.yml"),
+            "image: encom:os-12\ninclude:\n  - toast.yml\ntasks: {}",
+        )
+        .unwrap();
+        write(
+            dir.path().join("toast.yml"),
+            "image: encom:os-12\ninclude:\n  - foo.yml\ntasks: {}",
+        )
+        .unwrap();
+
+        let result = load(&dir.path().join("toast.yml"));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("includes itself"));
+    }
+}
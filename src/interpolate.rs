@@ -0,0 +1,280 @@
+use crate::{
+    failure::Failure,
+    format::CodeStr,
+    toastfile::{OutputPath, Task, Toastfile},
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+// A piece of a string which may contain references to variables like `${FOO}`
+#[derive(Debug, Eq, PartialEq)]
+enum Piece<'a> {
+    Literal(&'a str),
+    Variable(&'a str),
+}
+
+// Split a string into literals and references to variables. `$${` is an escaped `${`.
+fn pieces(input: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut result = vec![];
+    let mut rest = input;
+
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            // This is an escaped `${`.
+            result.push(Piece::Literal(&rest[..start - 1]));
+            result.push(Piece::Literal("${"));
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        result.push(Piece::Literal(&rest[..start]));
+        let end = rest[start..].find('}').ok_or_else(|| {
+            format!(
+                "{} has an unterminated {}.",
+                input.code_str(),
+                "${".code_str()
+            )
+        })?;
+        let variable = &rest[start + 2..start + end];
+        if variable.is_empty() {
+            return Err(format!(
+                "{} refers to a variable with no name.",
+                input.code_str(),
+            ));
+        }
+        result.push(Piece::Variable(variable));
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push(Piece::Literal(rest));
+    Ok(result)
+}
+
+// Find the variables referenced by a string.
+pub fn references(input: &str) -> Result<Vec<&str>, String> {
+    Ok(pieces(input)?
+        .into_iter()
+        .filter_map(|piece| match piece {
+            Piece::Variable(variable) => Some(variable),
+            Piece::Literal(_) => None,
+        })
+        .collect())
+}
+
+// Replace the references to variables in a string with their values.
+pub fn string(input: &str, variables: &HashMap<String, String>) -> Result<String, String> {
+    let mut result = String::new();
+
+    for piece in pieces(input)? {
+        match piece {
+            Piece::Literal(literal) => result.push_str(literal),
+            Piece::Variable(variable) => {
+                result.push_str(variables.get(variable).ok_or_else(|| {
+                    format!(
+                        "{} refers to the undeclared variable {}.",
+                        input.code_str(),
+                        variable.code_str(),
+                    )
+                })?)
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+// Replace the references to variables in a path with their values.
+fn path(input: &Path, variables: &HashMap<String, String>) -> Result<PathBuf, String> {
+    string(&input.to_string_lossy(), variables).map(PathBuf::from)
+}
+
+// The fields of a task which support interpolation, as a list of field names and strings. This is
+// used to check the references at parse time.
+pub fn task_fields(task: &Task) -> Vec<(&'static str, String)> {
+    let mut fields = vec![];
+    fields.extend(
+        task.input_paths
+            .iter()
+            .map(|path| ("input_paths", path.to_string_lossy().into_owned())),
+    );
+    fields.extend(task.output_paths.iter().map(|output_path| {
+        (
+            "output_paths",
+            output_path.path.to_string_lossy().into_owned(),
+        )
+    }));
+    fields.extend(
+        task.mount_paths
+            .iter()
+            .map(|path| ("mount_paths", path.to_string_lossy().into_owned())),
+    );
+    fields.extend(task.ports.iter().map(|port| ("ports", port.clone())));
+    fields.push(("location", task.location.to_string_lossy().into_owned()));
+    fields.push(("user", task.user.clone()));
+    fields
+}
+
+// Replace the references to variables in the fields of a task.
+fn task(task: &Task, variables: &HashMap<String, String>) -> Result<Task, String> {
+    let mut result = task.clone();
+
+    result.input_paths = task
+        .input_paths
+        .iter()
+        .map(|input_path| path(input_path, variables))
+        .collect::<Result<_, _>>()?;
+    result.output_paths = task
+        .output_paths
+        .iter()
+        .map(|output_path| {
+            Ok(OutputPath {
+                path: path(&output_path.path, variables)?,
+                mirror: output_path.mirror,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    result.mount_paths = task
+        .mount_paths
+        .iter()
+        .map(|mount_path| path(mount_path, variables))
+        .collect::<Result<_, _>>()?;
+    result.ports = task
+        .ports
+        .iter()
+        .map(|port| string(port, variables))
+        .collect::<Result<_, _>>()?;
+    result.location = path(&task.location, variables)?;
+    result.user = string(&task.user, variables)?;
+
+    Ok(result)
+}
+
+// Replace the references to variables in a toastfile. The `image` uses the variables in
+// `image_environment`, and each task in `environment` uses the variables for that task. Tasks
+// which aren't in `environment` are left alone.
+pub fn toastfile(
+    toastfile: &Toastfile,
+    image_environment: &HashMap<String, String>,
+    environment: &HashMap<String, HashMap<String, String>>,
+) -> Result<Toastfile, Failure> {
+    let mut result = toastfile.clone();
+    let mut violations = vec![];
+
+    match string(&toastfile.image, image_environment) {
+        Ok(image) => result.image = image,
+        Err(error) => violations.push(error),
+    }
+
+    for (name, variables) in environment {
+        match task(&toastfile.tasks[name], variables) {
+            Ok(task) => {
+                result.tasks.insert(name.clone(), task);
+            }
+            Err(error) => violations.push(format!("In task {}, {}", name.code_str(), error)),
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(result)
+    } else {
+        violations.sort();
+        Err(Failure::User(
+            format!("Unable to interpolate variables. {}", violations.join(" ")),
+            None,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interpolate::{references, string, toastfile},
+        toastfile::parse,
+    };
+    use std::{collections::HashMap, path::Path};
+
+    #[test]
+    fn string_no_variables() {
+        assert_eq!(
+            string("foo $bar", &HashMap::new()),
+            Ok("foo $bar".to_owned())
+        );
+    }
+
+    #[test]
+    fn string_variables() {
+        let mut variables = HashMap::new();
+        variables.insert("FOO".to_owned(), "bar".to_owned());
+        variables.insert("BAZ".to_owned(), "qux".to_owned());
+
+        assert_eq!(
+            string("/${FOO}/${BAZ}${FOO}", &variables),
+            Ok("/bar/quxbar".to_owned()),
+        );
+    }
+
+    #[test]
+    fn string_escaped() {
+        let mut variables = HashMap::new();
+        variables.insert("FOO".to_owned(), "bar".to_owned());
+
+        assert_eq!(string("$${FOO}", &variables), Ok("${FOO}".to_owned()));
+    }
+
+    #[test]
+    fn string_undeclared() {
+        let result = string("${FOO}", &HashMap::new());
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("FOO"));
+    }
+
+    #[test]
+    fn references_unterminated() {
+        assert!(references("foo ${BAR").is_err());
+    }
+
+    #[test]
+    fn references_empty_name() {
+        assert!(references("${}").is_err());
+    }
+
+    #[test]
+    fn references_multiple() {
+        assert_eq!(references("${FOO}-$${BAR}-${BAZ}"), Ok(vec!["FOO", "BAZ"]));
+    }
+
+    #[test]
+    fn toastfile_interpolated() {
+        let input = r#"
+image: encom:${OS_VERSION}
+environment:
+  OS_VERSION: os-12
+tasks:
+  foo:
+    environment:
+      SRC: src
+    input_paths:
+      - ${SRC}/main.rs
+    location: /home/$${SRC}
+    command: echo ${SRC}
+        "#
+        .trim();
+
+        let mut image_environment = HashMap::new();
+        image_environment.insert("OS_VERSION".to_owned(), "os-13".to_owned());
+        let mut variables = HashMap::new();
+        variables.insert("SRC".to_owned(), "lib".to_owned());
+        let mut environment = HashMap::new();
+        environment.insert("foo".to_owned(), variables);
+
+        let result = toastfile(&parse(input).unwrap(), &image_environment, &environment).unwrap();
+        let task = &result.tasks["foo"];
+
+        assert_eq!(result.image, "encom:os-13");
+        assert_eq!(task.input_paths, vec![Path::new("lib/main.rs").to_owned()]);
+        assert_eq!(task.location, Path::new("/home/${SRC}").to_owned());
+        assert_eq!(task.command, Some("echo ${SRC}".to_owned()));
+    }
+}
@@ -0,0 +1,462 @@
+use crate::{
+    diagnostic::{Diagnostic, Diagnostics},
+    env_file,
+    format::CodeStr,
+    interpolate, schedule,
+    toastfile::{source_name, Toastfile},
+};
+use regex::Regex;
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+
+lazy_static! {
+    // Commands which upgrade installed packages to whatever versions are current
+    static ref UPGRADE_REGEX: Regex = Regex::new(
+        r"\b(?:(?:apt-get|apt)\s+(?:-\S+\s+)*(?:upgrade|dist-upgrade|full-upgrade)|apk\s+(?:-\S+\s+)*upgrade|(?:yum|dnf)\s+(?:-\S+\s+)*(?:update|upgrade))\b",
+    )
+    .unwrap();
+
+    // Commands which update the package index
+    static ref UPDATE_REGEX: Regex =
+        Regex::new(r"\b(?:apt-get|apt|apk)\s+(?:-\S+\s+)*update\b").unwrap();
+
+    // Commands which install packages, capturing the arguments
+    static ref INSTALL_REGEX: Regex =
+        Regex::new(r"\b(?:apt-get|apt|apk)\s+(?:-\S+\s+)*(?:install|add)\s+([^;&|\n]*)").unwrap();
+}
+
+// Look for things in a toastfile which are valid but likely to be mistakes. The toastfile is
+// assumed to have passed validation, and `toastfile_dir` is the directory containing it. The
+// result only contains warnings.
+pub fn lint(toastfile: &Toastfile, toastfile_dir: &Path) -> Diagnostics {
+    let mut diagnostics = Diagnostics::default();
+    diagnostics.extend(check_reachability(toastfile));
+    diagnostics.extend(check_unused_variables(toastfile, toastfile_dir));
+    diagnostics.extend(check_output_paths(toastfile));
+    diagnostics.extend(check_input_paths(toastfile, toastfile_dir));
+    diagnostics.extend(check_determinism(toastfile));
+
+    // Tasks expanded from a matrix are reported as the original task, so there may be duplicates.
+    diagnostics.sort();
+    diagnostics
+}
+
+// Check that every task can be reached from the default task, if there is one.
+fn check_reachability(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    if let Some(default) = &toastfile.default {
+        let reachable = schedule::compute(toastfile, &[default])
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        for (name, task) in &toastfile.tasks {
+            if !reachable.contains(name.as_str()) {
+                let name = source_name(name, task);
+                diagnostics.0.push(Diagnostic::warning(
+                    format!(
+                        "Task {} can't be reached from the default task {}.",
+                        name.code_str(),
+                        default.code_str(),
+                    ),
+                    &["tasks", name],
+                ));
+            }
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+// Determine whether a command refers to a variable, as in `$FOO` or `${FOO}`.
+fn refers_to(command: &str, variable: &str) -> bool {
+    ["$", "${"].iter().any(|prefix| {
+        let reference = format!("{}{}", prefix, variable);
+        command.match_indices(&reference).any(|(index, _)| {
+            !command[index + reference.len()..]
+                .chars()
+                .next()
+                .map_or(false, |c| c.is_ascii_alphanumeric() || c == '_')
+        })
+    })
+}
+
+// Check that the variables declared by each task are used by its command. Variables from the
+// top-level `environment` and from environment files are shared, so they're exempt. Variables used
+// in the fields which support interpolation count as used.
+fn check_unused_variables(toastfile: &Toastfile, toastfile_dir: &Path) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    // Read the variables from an environment file. Problems with the file are reported when the
+    // tasks are run, so they're ignored here.
+    let file_variables = |path: &Option<PathBuf>| {
+        path.as_ref()
+            .and_then(|path| env_file::load(path, toastfile_dir).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(variable, _)| variable)
+            .collect::<HashSet<_>>()
+    };
+    let toastfile_variables = file_variables(&toastfile.env_file);
+
+    for (name, task) in &toastfile.tasks {
+        let command = match &task.command {
+            Some(command) => command,
+            None => continue,
+        };
+        let task_variables = file_variables(&task.env_file);
+        let interpolated = interpolate::task_fields(task)
+            .into_iter()
+            .flat_map(|(_, value)| {
+                interpolate::references(&value)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(ToOwned::to_owned)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<HashSet<_>>();
+
+        for (section, variables) in &[
+            ("environment", &task.environment),
+            ("cache_neutral_environment", &task.cache_neutral_environment),
+        ] {
+            for variable in variables.keys() {
+                if !refers_to(command, variable)
+                    && !interpolated.contains(variable)
+                    && !toastfile.environment.contains_key(variable)
+                    && !toastfile_variables.contains(variable)
+                    && !task_variables.contains(variable)
+                {
+                    let name = source_name(name, task);
+                    diagnostics.0.push(Diagnostic::warning(
+                        format!(
+                            "Task {} declares the variable {}, but its command doesn't use it.",
+                            name.code_str(),
+                            variable.code_str(),
+                        ),
+                        &["tasks", name, section, variable],
+                    ));
+                }
+            }
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+// Remove the `.` components from a path, so `./foo/` and `foo` are considered the same.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+// Check that no two tasks have overlapping output paths, since one would overwrite the files of
+// the other.
+fn check_output_paths(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    let mut names = toastfile.tasks.keys().collect::<Vec<_>>();
+    names.sort();
+    for (i, name) in names.iter().enumerate() {
+        let task = &toastfile.tasks[*name];
+        for other_name in &names[..i] {
+            let other_task = &toastfile.tasks[*other_name];
+
+            // Tasks expanded from the same matrix are expected to have the same output paths.
+            if source_name(name, task) == source_name(other_name, other_task) {
+                continue;
+            }
+
+            for (index, output_path) in task.output_paths.iter().enumerate() {
+                let path = normalize(&output_path.path);
+                for other_output_path in &other_task.output_paths {
+                    let other_path = normalize(&other_output_path.path);
+                    if path.starts_with(&other_path) || other_path.starts_with(&path) {
+                        let name = source_name(name, task);
+                        diagnostics.0.push(Diagnostic::warning(
+                            format!(
+                                "Output path {} of task {} overlaps with output path {} of task \
+                                 {}.",
+                                output_path.path.to_string_lossy().code_str(),
+                                name.code_str(),
+                                other_output_path.path.to_string_lossy().code_str(),
+                                source_name(other_name, other_task).code_str(),
+                            ),
+                            &["tasks", name, "output_paths", &index.to_string()],
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+// Check that the input paths exist. Paths which refer to variables are skipped, since their values
+// aren't known until the tasks are run.
+fn check_input_paths(toastfile: &Toastfile, toastfile_dir: &Path) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    for (name, task) in &toastfile.tasks {
+        for (index, path) in task.input_paths.iter().enumerate() {
+            if interpolate::references(&path.to_string_lossy())
+                .map(|variables| variables.is_empty())
+                .unwrap_or(false)
+                && !toastfile_dir.join(path).exists()
+            {
+                let name = source_name(name, task);
+                diagnostics.0.push(Diagnostic::warning(
+                    format!(
+                        "Input path {} of task {} doesn't exist.",
+                        path.to_string_lossy().code_str(),
+                        name.code_str(),
+                    ),
+                    &["tasks", name, "input_paths", &index.to_string()],
+                ));
+            }
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+// Find a reason why the result of a command depends on when it's run, if there is an obvious one.
+fn nondeterminism(command: &str) -> Option<String> {
+    // Treat the command as a single line, even if it continues lines with backslashes.
+    let command = command.replace("\\\n", " ");
+
+    if let Some(upgrade) = UPGRADE_REGEX.find(&command) {
+        return Some(format!(
+            "{} installs whatever versions of packages are current",
+            upgrade.as_str().code_str(),
+        ));
+    }
+
+    if let Some(update) = UPDATE_REGEX.find(&command) {
+        let unpinned = INSTALL_REGEX
+            .captures_iter(&command)
+            .flat_map(|captures| {
+                captures[1]
+                    .split_whitespace()
+                    .filter(|package| !package.starts_with('-') && !package.contains('='))
+                    .map(ToOwned::to_owned)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(package) = unpinned.first() {
+            return Some(format!(
+                "{} is followed by installing {} without pinning its version",
+                update.as_str().code_str(),
+                package.code_str(),
+            ));
+        }
+    }
+
+    None
+}
+
+// Check that the commands of cached tasks don't do anything which is obviously non-deterministic,
+// since the cached result would then depend on when the task happened to run first.
+fn check_determinism(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    for (name, task) in &toastfile.tasks {
+        if !task.cache {
+            continue;
+        }
+
+        if let Some(reason) = task
+            .command
+            .as_ref()
+            .and_then(|command| nondeterminism(command))
+        {
+            let name = source_name(name, task);
+            diagnostics.0.push(Diagnostic::warning(
+                format!(
+                    "Task {} is cached, but its command is non-deterministic: {}. Consider \
+                     pinning versions or setting {}.",
+                    name.code_str(),
+                    reason,
+                    "cache: false".code_str(),
+                ),
+                &["tasks", name, "command"],
+            ));
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lint::{lint, nondeterminism, refers_to},
+        toastfile::parse,
+    };
+    use std::fs::write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn lint_clean() {
+        let dir = tempdir().unwrap();
+        write(dir.path().join("Cargo.toml"), "").unwrap();
+
+        let input = r#"
+image: encom:os-12
+default: build
+tasks:
+  install_rust:
+    command: curl https://sh.rustup.rs -sSf | sh -s -- -y
+  build:
+    dependencies:
+      - install_rust
+    environment:
+      PROFILE: release
+    input_paths:
+      - Cargo.toml
+    output_paths:
+      - target
+    command: cargo build --${PROFILE}
+        "#
+        .trim();
+
+        assert_eq!(lint(&parse(input).unwrap(), dir.path()).0, vec![]);
+    }
+
+    #[test]
+    fn lint_unreachable() {
+        let input = r#"
+image: encom:os-12
+default: foo
+tasks:
+  foo: {}
+  bar: {}
+  baz:
+    matrix:
+      QUX: ['1', '2']
+        "#
+        .trim();
+
+        let diagnostics = lint(&parse(input).unwrap(), tempdir().unwrap().path());
+        assert_eq!(diagnostics.0.len(), 2);
+        assert!(diagnostics.0[0].message.contains("bar"));
+        assert!(diagnostics.0[1].message.contains("baz"));
+        assert!(!diagnostics.0[1].message.contains("QUX"));
+    }
+
+    #[test]
+    fn lint_unused_variable() {
+        let input = r#"
+image: encom:os-12
+environment:
+  SHARED: foo
+tasks:
+  foo:
+    environment:
+      USED: bar
+      UNUSED: baz
+      USED_PREFIX: qux
+    command: echo $USED ${SHARED}
+        "#
+        .trim();
+
+        let diagnostics = lint(&parse(input).unwrap(), tempdir().unwrap().path());
+        assert_eq!(diagnostics.0.len(), 2);
+        assert_eq!(
+            diagnostics.0[0].path,
+            vec!["tasks", "foo", "environment", "UNUSED"],
+        );
+        assert!(diagnostics.to_string().contains("USED_PREFIX"));
+    }
+
+    #[test]
+    fn lint_overlapping_output_paths() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    output_paths:
+      - ./dist
+  bar:
+    output_paths:
+      - dist/bar
+  baz:
+    output_paths:
+      - distribution
+        "#
+        .trim();
+
+        let diagnostics = lint(&parse(input).unwrap(), tempdir().unwrap().path());
+        assert_eq!(diagnostics.0.len(), 1);
+        assert!(diagnostics.0[0].message.contains("dist/bar"));
+        assert!(diagnostics.0[0].message.contains("./dist"));
+    }
+
+    #[test]
+    fn lint_missing_input_paths() {
+        let dir = tempdir().unwrap();
+        write(dir.path().join("present.txt"), "").unwrap();
+
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    environment:
+      FILE: missing.txt
+    input_paths:
+      - present.txt
+      - missing.txt
+      - ${FILE}
+    command: cat ${FILE}
+        "#
+        .trim();
+
+        let diagnostics = lint(&parse(input).unwrap(), dir.path());
+        assert_eq!(diagnostics.0.len(), 1);
+        assert_eq!(
+            diagnostics.0[0].path,
+            vec!["tasks", "foo", "input_paths", "1"],
+        );
+    }
+
+    #[test]
+    fn lint_nondeterministic_command() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    command: apt-get update && apt-get install -y curl
+  bar:
+    cache: false
+    command: apt-get update && apt-get install -y curl
+        "#
+        .trim();
+
+        let diagnostics = lint(&parse(input).unwrap(), tempdir().unwrap().path());
+        assert_eq!(diagnostics.0.len(), 1);
+        assert_eq!(diagnostics.0[0].path, vec!["tasks", "foo", "command"]);
+    }
+
+    #[test]
+    fn refers_to_variable() {
+        assert!(refers_to("echo $FOO", "FOO"));
+        assert!(refers_to("echo ${FOO}/bar", "FOO"));
+        assert!(refers_to("echo $FOO-bar", "FOO"));
+        assert!(!refers_to("echo $FOOBAR", "FOO"));
+        assert!(!refers_to("echo FOO", "FOO"));
+    }
+
+    #[test]
+    fn nondeterminism_pinned() {
+        assert!(nondeterminism("apt-get update\napt-get install -y curl=7.58.0").is_none());
+        assert!(nondeterminism("apt-get install -y curl").is_none());
+        assert!(nondeterminism("apt-get update && \\\n  apt-get install -y curl").is_some());
+        assert!(nondeterminism("apt-get -y upgrade").is_some());
+        assert!(nondeterminism("apk add curl=7.64.0-r2").is_none());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{
+    schedule,
+    toastfile::{OutputPath, Toastfile},
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+// The formats for listing tasks
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+// The names of the formats, as accepted on the command line. Each one is recognized by
+// `from_name`. [tag:list_format_valid]
+pub const FORMAT_NAMES: &[&str] = &["text", "json"];
+
+impl Format {
+    // Look up a format by its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+// A task as it appears in the machine-readable listing. The environment variables are sorted so
+// the output is stable.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct Task {
+    pub name: String,
+    pub description: Option<String>,
+    pub default: bool,
+    pub dependencies: Vec<String>,
+
+    // Every task which runs before this one, in the order they run
+    pub transitive_dependencies: Vec<String>,
+
+    pub cache: bool,
+    pub environment: BTreeMap<String, Option<String>>,
+    pub cache_neutral_environment: BTreeMap<String, Option<String>>,
+    pub input_paths: Vec<PathBuf>,
+    pub output_paths: Vec<OutputPath>,
+    pub mount_paths: Vec<PathBuf>,
+    pub mount_readonly: bool,
+}
+
+// Describe the tasks of a toastfile, sorted by name. The names of the tasks and their dependencies
+// are prefixed with `prefix`, which is how the tasks of a workspace member are addressed.
+pub fn tasks(toastfile: &Toastfile, prefix: &str) -> Vec<Task> {
+    let mut task_names = toastfile.tasks.keys().collect::<Vec<_>>();
+    task_names.sort();
+
+    task_names
+        .into_iter()
+        .map(|task_name| {
+            let task = &toastfile.tasks[task_name];
+            let qualify = |name: &str| format!("{}{}", prefix, name);
+
+            Task {
+                name: qualify(task_name),
+                description: task.description.clone(),
+                default: toastfile.default.as_ref() == Some(task_name),
+                dependencies: task.dependencies.iter().map(|name| qualify(name)).collect(),
+                transitive_dependencies: schedule::compute(toastfile, &[task_name])
+                    .into_iter()
+                    .filter(|name| name != task_name)
+                    .map(qualify)
+                    .collect(),
+                cache: task.cache,
+                environment: task.environment.clone().into_iter().collect(),
+                cache_neutral_environment: task
+                    .cache_neutral_environment
+                    .clone()
+                    .into_iter()
+                    .collect(),
+                input_paths: task.input_paths.clone(),
+                output_paths: task.output_paths.clone(),
+                mount_paths: task.mount_paths.clone(),
+                mount_readonly: task.mount_readonly,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{listing::tasks, toastfile::parse};
+    use serde_json::json;
+
+    #[test]
+    fn tasks_json() {
+        let input = r#"
+image: encom:os-12
+default: test
+tasks:
+  build:
+    description: Build the project.
+    environment:
+      MODE: release
+      TOKEN: null
+    input_paths:
+      - src
+    output_paths:
+      - target
+  lint:
+    dependencies:
+      - build
+    cache_neutral_environment:
+      VERBOSE: '0'
+  test:
+    cache: false
+    dependencies:
+      - lint
+    mount_paths:
+      - cache
+    mount_readonly: true
+        "#
+        .trim();
+
+        let toastfile = parse(input).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(tasks(&toastfile, "")).unwrap(),
+            json!([
+                {
+                    "name": "build",
+                    "description": "Build the project.",
+                    "default": false,
+                    "dependencies": [],
+                    "transitive_dependencies": [],
+                    "cache": true,
+                    "environment": { "MODE": "release", "TOKEN": null },
+                    "cache_neutral_environment": {},
+                    "input_paths": ["src"],
+                    "output_paths": [{ "path": "target", "mirror": false }],
+                    "mount_paths": [],
+                    "mount_readonly": false,
+                },
+                {
+                    "name": "lint",
+                    "description": null,
+                    "default": false,
+                    "dependencies": ["build"],
+                    "transitive_dependencies": ["build"],
+                    "cache": true,
+                    "environment": {},
+                    "cache_neutral_environment": { "VERBOSE": "0" },
+                    "input_paths": [],
+                    "output_paths": [],
+                    "mount_paths": [],
+                    "mount_readonly": false,
+                },
+                {
+                    "name": "test",
+                    "description": null,
+                    "default": true,
+                    "dependencies": ["lint"],
+                    "transitive_dependencies": ["build", "lint"],
+                    "cache": false,
+                    "environment": {},
+                    "cache_neutral_environment": {},
+                    "input_paths": [],
+                    "output_paths": [],
+                    "mount_paths": ["cache"],
+                    "mount_readonly": true,
+                },
+            ]),
+        );
+    }
+
+    #[test]
+    fn tasks_prefix() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  build: {}
+  test:
+    dependencies:
+      - build
+        "#
+        .trim();
+
+        let toastfile = parse(input).unwrap();
+
+        let tasks = tasks(&toastfile, "api:");
+
+        assert_eq!(tasks[1].name, "api:test");
+        assert_eq!(tasks[1].dependencies, vec!["api:build".to_owned()]);
+        assert_eq!(
+            tasks[1].transitive_dependencies,
+            vec!["api:build".to_owned()]
+        );
+    }
+}
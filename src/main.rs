@@ -1,19 +1,35 @@
 mod cache;
+mod completions;
 mod config;
+mod diagnostic;
 mod docker;
+mod env_file;
 mod failure;
 mod format;
+mod formatter;
+mod graph;
+mod hash_cache;
+mod include;
+mod interpolate;
+mod lint;
+mod listing;
+mod redact;
 mod runner;
+mod scaffold;
 mod schedule;
+mod schema;
+mod secrets;
 mod spinner;
 mod tar;
 mod toastfile;
+mod workspace;
 
-use crate::{failure::Failure, format::CodeStr};
+use crate::{failure::Failure, format::CodeStr, hash_cache::HashCache};
 use atty::Stream;
-use clap::{App, AppSettings, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use env_logger::{fmt::Color, Builder};
 use log::{Level, LevelFilter};
+use regex::bytes::Regex;
 use std::{
     collections::{HashMap, HashSet},
     convert::AsRef,
@@ -44,8 +60,9 @@ extern crate scopeguard;
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 // Defaults
-const TOASTFILE_DEFAULT_NAME: &str = "toast.yml";
+const TOASTFILE_DEFAULT_NAMES: &[&str] = &["toast.yml", "toast.json", "toast.toml"];
 const CONFIG_FILE_XDG_PATH: &str = "toast/toast.yml";
+const HASH_CACHE_XDG_PATH: &str = "toast/hashes.json";
 const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
 
 // Command-line argument and option names
@@ -57,8 +74,57 @@ const READ_REMOTE_CACHE_ARG: &str = "read-remote-cache";
 const WRITE_REMOTE_CACHE_ARG: &str = "write-remote-cache";
 const REPO_ARG: &str = "repo";
 const LIST_ARG: &str = "list";
+const CHECK_ARG: &str = "check";
+const FMT_ARG: &str = "fmt";
 const SHELL_ARG: &str = "shell";
+const REHASH_ARG: &str = "rehash";
+const ALL_ARG: &str = "all";
+const PRINT_SCHEMA_ARG: &str = "print-schema";
 const TASKS_ARG: &str = "tasks";
+const KIND_ARG: &str = "kind";
+const FORMAT_ARG: &str = "format";
+const GRAPH_ARG: &str = "graph";
+const CACHE_STATUS_ARG: &str = "cache-status";
+const COMPLETIONS_ARG: &str = "completions";
+const COMPLETE_TASKS_ARG: &str = "complete-tasks";
+
+// Subcommand names
+const RUN_SUBCOMMAND: &str = "run";
+const SHELL_SUBCOMMAND: &str = "shell";
+const LIST_SUBCOMMAND: &str = "list";
+const CHECK_SUBCOMMAND: &str = "check";
+const FMT_SUBCOMMAND: &str = "fmt";
+const CACHE_SUBCOMMAND: &str = "cache";
+const CACHE_CLEAR_SUBCOMMAND: &str = "clear";
+const INIT_SUBCOMMAND: &str = "init";
+const SCHEMA_SUBCOMMAND: &str = "schema";
+const SUBCOMMANDS: &[&str] = &[
+    RUN_SUBCOMMAND,
+    SHELL_SUBCOMMAND,
+    LIST_SUBCOMMAND,
+    CHECK_SUBCOMMAND,
+    FMT_SUBCOMMAND,
+    CACHE_SUBCOMMAND,
+    INIT_SUBCOMMAND,
+    SCHEMA_SUBCOMMAND,
+];
+
+// The options which take a value, as long names and short names. These are needed to find the
+// positional arguments before the arguments are parsed.
+const VALUE_ARGS: &[&str] = &[
+    TOASTFILE_ARG,
+    CONFIG_FILE_ARG,
+    READ_LOCAL_CACHE_ARG,
+    WRITE_LOCAL_CACHE_ARG,
+    READ_REMOTE_CACHE_ARG,
+    WRITE_REMOTE_CACHE_ARG,
+    REPO_ARG,
+    PRINT_SCHEMA_ARG,
+    COMPLETIONS_ARG,
+    FORMAT_ARG,
+    GRAPH_ARG,
+];
+const VALUE_SHORT_ARGS: &[char] = &['f', 'c', 'r'];
 
 // Set up the logger.
 fn set_up_logging() {
@@ -139,22 +205,219 @@ fn parse_bool(s: &str) -> Result<bool, Failure> {
     }
 }
 
+// The things Toast can do, as chosen with a subcommand
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Command {
+    Run,
+    Shell,
+    List(listing::Format),
+    Check,
+    Format {
+        check: bool,
+    },
+    ClearCache,
+    Init,
+    Schema(schema::Kind),
+    Completions(completions::Shell),
+    CompleteTasks,
+    Graph {
+        format: graph::Format,
+        cache_status: bool,
+    },
+}
+
 // This struct represents the command-line arguments.
+#[derive(Clone)]
 pub struct Settings {
+    command: Command,
     toastfile_path: PathBuf,
     docker_repo: String,
     read_local_cache: bool,
     write_local_cache: bool,
     read_remote_cache: bool,
     write_remote_cache: bool,
-    list: bool,
-    spawn_shell: bool,
+    redact_patterns: Vec<Regex>,
+    passthrough_environment: Vec<String>,
+    hash_cache_path: Option<PathBuf>,
+    rehash: bool,
+    all: bool,
     tasks: Option<Vec<String>>,
 }
 
-// Parse the command-line arguments;
-fn settings() -> Result<Settings, Failure> {
-    let matches = App::new("Toast")
+// Describe the names a toastfile can have, for error messages.
+fn toastfile_names() -> String {
+    format!(
+        "a toastfile ({})",
+        TOASTFILE_DEFAULT_NAMES
+            .iter()
+            .map(|name| format!("{}", name.code_str()))
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+// Find the toastfile in a directory, if there is one. It's an error for there to be more than one,
+// since it wouldn't be clear which one to use.
+fn find_toastfile(dir: &Path) -> Result<Option<PathBuf>, Failure> {
+    let candidate_paths = TOASTFILE_DEFAULT_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|path| {
+            fs::metadata(path)
+                .map(|metadata| metadata.file_type().is_file())
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+
+    match candidate_paths.as_slice() {
+        [] => Ok(None),
+        [candidate_path] => Ok(Some(candidate_path.clone())),
+        _ => Err(Failure::User(
+            format!(
+                "Found more than one toastfile in {}: {}.",
+                dir.to_string_lossy().code_str(),
+                format::series(
+                    candidate_paths
+                        .iter()
+                        .map(|path| format!("{}", path.to_string_lossy().code_str()))
+                        .collect::<Vec<_>>()
+                        .as_ref(),
+                ),
+            ),
+            None,
+        )),
+    }
+}
+
+// Find the toastfile in the working directory or the nearest ancestor which has one.
+fn locate_toastfile() -> Result<PathBuf, Failure> {
+    let mut candidate_dir =
+        current_dir().map_err(failure::system("Unable to determine working directory."))?;
+    loop {
+        if let Some(candidate_path) = find_toastfile(&candidate_dir)? {
+            return Ok(candidate_path);
+        }
+        if !candidate_dir.pop() {
+            return Err(Failure::User(
+                format!("Unable to locate {}.", toastfile_names()),
+                None,
+            ));
+        }
+    }
+}
+
+// Fail if the toastfile defines a task with the same name as a subcommand. Workspaces are fine,
+// since their tasks are qualified by member. Problems with the toastfile itself are left for the
+// subcommand to report, if it reads the toastfile at all.
+fn check_shadowed_task(toastfile_path: &Path, subcommand: &str) -> Result<(), Failure> {
+    if let Ok(None) = workspace::load(toastfile_path) {
+        if let Ok(toastfile) = include::load(toastfile_path) {
+            if toastfile.tasks.contains_key(subcommand) {
+                return Err(Failure::User(
+                    format!(
+                        "{} defines a task named {}, which is also a subcommand. To run the task, \
+                         use {}. To use the subcommand, rename the task.",
+                        toastfile_path.to_string_lossy().code_str(),
+                        subcommand.code_str(),
+                        format!("toast {} {}", RUN_SUBCOMMAND, subcommand).code_str(),
+                    ),
+                    None,
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Determine whether the first positional argument is a subcommand [tag:uses_subcommand]. Clap
+// can't tell subcommands apart from task names, and it rejects task names which look like
+// misspelled subcommands (such as `lint` for `init`). So we only tell it about the subcommands
+// when one is used, or when there are no positional arguments (e.g., for `--help`).
+fn uses_subcommand(args: &[String]) -> bool {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return false;
+        }
+
+        let dashes = arg.len() - arg.trim_start_matches('-').len();
+        if dashes >= 2 {
+            if VALUE_ARGS.contains(&&arg[dashes..]) {
+                args.next();
+            }
+            continue;
+        }
+
+        if dashes == 1 && arg.len() > 1 {
+            // A short option which takes a value either ends the group or is followed by it.
+            let shorts = &arg[1..];
+            if let Some(index) = shorts.find(|c| VALUE_SHORT_ARGS.contains(&c)) {
+                if index == shorts.len() - 1 {
+                    args.next();
+                }
+            }
+            continue;
+        }
+
+        return SUBCOMMANDS.contains(&arg.as_str());
+    }
+
+    true
+}
+
+// The variants of the command-line interface
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Cli {
+    // The options and the tasks to run, without the subcommands [ref:uses_subcommand]
+    Tasks,
+
+    // The options, the tasks to run, and the subcommands
+    Subcommands,
+
+    // The visible options and the subcommands, for generating completion scripts. Clap's scripts
+    // can't handle a positional argument alongside subcommands, so tasks are completed separately.
+    Completions,
+}
+
+// Determine the format for listing tasks.
+fn list_format(matches: &ArgMatches) -> listing::Format {
+    matches
+        .value_of(FORMAT_ARG)
+        .and_then(listing::Format::from_name) // [ref:list_format_valid]
+        .unwrap_or(listing::Format::Text)
+}
+
+// Build the command-line interface.
+fn app<'a, 'b>(cli: Cli) -> App<'a, 'b> {
+    // These arguments are for running tasks, which is what Toast does without a subcommand.
+    let all_arg = || {
+        Arg::with_name(ALL_ARG)
+            .short("a")
+            .long(ALL_ARG)
+            .help("Runs the tasks in every member of the workspace which has them")
+    };
+    let rehash_arg = || {
+        Arg::with_name(REHASH_ARG)
+            .long(REHASH_ARG)
+            .help("Ignores the cached hashes of input files and reads them again")
+    };
+    let tasks_arg = || {
+        Arg::with_name(TASKS_ARG)
+            .value_name("TASKS")
+            .multiple(true)
+            .help("Sets the tasks to run")
+    };
+    let format_arg = || {
+        Arg::with_name(FORMAT_ARG)
+            .long(FORMAT_ARG)
+            .value_name("FORMAT")
+            .possible_values(listing::FORMAT_NAMES)
+            .help("Sets the output format")
+            .takes_value(true)
+    };
+
+    let mut app = App::new("Toast")
         .version(VERSION)
         .version_short("v")
         .author("Stephan Boyer <stephan@stephanboyer.com>")
@@ -162,13 +425,19 @@ fn settings() -> Result<Settings, Failure> {
         .setting(AppSettings::ColoredHelp)
         .setting(AppSettings::NextLineHelp)
         .setting(AppSettings::UnifiedHelpMessage)
+        .setting(AppSettings::DisableHelpSubcommand)
+        .setting(AppSettings::VersionlessSubcommands)
+        .global_setting(AppSettings::ColoredHelp)
+        .global_setting(AppSettings::NextLineHelp)
+        .global_setting(AppSettings::UnifiedHelpMessage)
         .arg(
             Arg::with_name(TOASTFILE_ARG)
                 .short("f")
                 .long(TOASTFILE_ARG)
                 .value_name("PATH")
                 .help("Sets the path to the toastfile")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
         .arg(
             Arg::with_name(CONFIG_FILE_ARG)
@@ -176,35 +445,40 @@ fn settings() -> Result<Settings, Failure> {
                 .long(CONFIG_FILE_ARG)
                 .value_name("PATH")
                 .help("Sets the path of the config file")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
         .arg(
             Arg::with_name(READ_LOCAL_CACHE_ARG)
                 .long(READ_LOCAL_CACHE_ARG)
                 .value_name("BOOL")
                 .help("Sets whether local cache reading is enabled")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
         .arg(
             Arg::with_name(WRITE_LOCAL_CACHE_ARG)
                 .long(WRITE_LOCAL_CACHE_ARG)
                 .value_name("BOOL")
                 .help("Sets whether local cache writing is enabled")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
         .arg(
             Arg::with_name(READ_REMOTE_CACHE_ARG)
                 .long(READ_REMOTE_CACHE_ARG)
                 .value_name("BOOL")
                 .help("Sets whether remote cache reading is enabled")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
         .arg(
             Arg::with_name(WRITE_REMOTE_CACHE_ARG)
                 .long(WRITE_REMOTE_CACHE_ARG)
                 .value_name("BOOL")
                 .help("Sets whether remote cache writing is enabled")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
         .arg(
             Arg::with_name(REPO_ARG)
@@ -212,57 +486,251 @@ fn settings() -> Result<Settings, Failure> {
                 .long(REPO_ARG)
                 .value_name("REPO")
                 .help("Sets the Docker repository")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
         .arg(
-            Arg::with_name(LIST_ARG)
-                .short("l")
-                .long(LIST_ARG)
-                .help("Lists the tasks in the toastfile"),
+            Arg::with_name(COMPLETIONS_ARG)
+                .long(COMPLETIONS_ARG)
+                .value_name("SHELL")
+                .possible_values(completions::SHELL_NAMES)
+                .help("Prints a completion script for the given shell")
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name(SHELL_ARG)
-                .short("s")
-                .long(SHELL_ARG)
-                .help("Drops you into a shell after the tasks are finished"),
+            Arg::with_name(GRAPH_ARG)
+                .long(GRAPH_ARG)
+                .value_name("FORMAT")
+                .possible_values(graph::FORMAT_NAMES)
+                .help("Prints the dependency graph of the tasks instead of running them")
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name(TASKS_ARG)
-                .value_name("TASKS")
-                .multiple(true)
-                .help("Sets the tasks to run"),
+            Arg::with_name(CACHE_STATUS_ARG)
+                .long(CACHE_STATUS_ARG)
+                .requires(GRAPH_ARG)
+                .help("Shows which tasks in the graph are in the local cache"),
         )
-        .get_matches();
+        .arg(all_arg())
+        .arg(rehash_arg());
+
+    if cli != Cli::Completions {
+        // These options predate the subcommands which replaced them. They're hidden, but they
+        // still work [ref:legacy_options].
+        app = app
+            .arg(
+                Arg::with_name(LIST_ARG)
+                    .short("l")
+                    .long(LIST_ARG)
+                    .hidden(true),
+            )
+            .arg(format_arg().requires(LIST_ARG).hidden(true))
+            .arg(Arg::with_name(CHECK_ARG).long(CHECK_ARG).hidden(true))
+            .arg(Arg::with_name(FMT_ARG).long(FMT_ARG).hidden(true))
+            .arg(
+                Arg::with_name(SHELL_ARG)
+                    .short("s")
+                    .long(SHELL_ARG)
+                    .hidden(true),
+            )
+            .arg(
+                Arg::with_name(PRINT_SCHEMA_ARG)
+                    .long(PRINT_SCHEMA_ARG)
+                    .value_name("KIND")
+                    .possible_values(schema::KIND_NAMES)
+                    .takes_value(true)
+                    .hidden(true),
+            )
+            // The completion scripts use this to find the names of the tasks.
+            .arg(
+                Arg::with_name(COMPLETE_TASKS_ARG)
+                    .long(COMPLETE_TASKS_ARG)
+                    .hidden(true),
+            )
+            .arg(tasks_arg());
+    }
+
+    if cli == Cli::Tasks {
+        return app;
+    }
+
+    app.subcommand(
+        SubCommand::with_name(RUN_SUBCOMMAND)
+            .about("Runs tasks (this is the default when no subcommand is given)")
+            .arg(all_arg())
+            .arg(rehash_arg())
+            .arg(tasks_arg()),
+    )
+    .subcommand(
+        SubCommand::with_name(SHELL_SUBCOMMAND)
+            .about("Runs tasks and then drops you into a shell")
+            .arg(rehash_arg())
+            .arg(tasks_arg()),
+    )
+    .subcommand(
+        SubCommand::with_name(LIST_SUBCOMMAND)
+            .about("Lists the tasks in the toastfile")
+            .arg(format_arg()),
+    )
+    .subcommand(
+        SubCommand::with_name(CHECK_SUBCOMMAND)
+            .about("Checks the toastfile for problems without running any tasks"),
+    )
+    .subcommand(
+        SubCommand::with_name(FMT_SUBCOMMAND)
+            .about("Formats the toastfile")
+            .arg(
+                Arg::with_name(CHECK_ARG)
+                    .long(CHECK_ARG)
+                    .help("Fails if the toastfile isn't formatted, rather than formatting it"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name(CACHE_SUBCOMMAND)
+            .about("Manages the local cache")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name(CACHE_CLEAR_SUBCOMMAND).about(
+                "Deletes the images in the local cache and the cached hashes of input files",
+            )),
+    )
+    .subcommand(
+        SubCommand::with_name(INIT_SUBCOMMAND)
+            .about("Writes a starter toastfile for the project in the working directory"),
+    )
+    .subcommand(
+        SubCommand::with_name(SCHEMA_SUBCOMMAND)
+            .about("Prints the JSON Schema for toastfiles or config files")
+            .arg(
+                Arg::with_name(KIND_ARG)
+                    .value_name("KIND")
+                    .possible_values(schema::KIND_NAMES)
+                    .required(true)
+                    .help("Sets the kind of file"),
+            ),
+    )
+}
 
-    // Find the toastfile.
-    let toastfile_path = matches.value_of(TOASTFILE_ARG).map_or_else(
+// Parse the command-line arguments;
+fn settings() -> Result<Settings, Failure> {
+    let args = env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let matches = app(if uses_subcommand(&args) {
+        Cli::Subcommands
+    } else {
+        Cli::Tasks
+    })
+    .get_matches_from(&args);
+
+    // Find the arguments of the innermost subcommand, if there is one. Global options are passed
+    // down to the subcommands, so they can be read from there too.
+    let mut arg_matches = &matches;
+    while let (_, Some(sub_matches)) = arg_matches.subcommand() {
+        arg_matches = sub_matches;
+    }
+
+    // Determine what the user wants to do. Without a subcommand, Toast runs tasks unless one of
+    // the options which predate the subcommands is given [tag:legacy_options].
+    let command = match matches.subcommand_name() {
+        Some(RUN_SUBCOMMAND) => Command::Run,
+        Some(SHELL_SUBCOMMAND) => Command::Shell,
+        Some(LIST_SUBCOMMAND) => Command::List(list_format(arg_matches)),
+        Some(CHECK_SUBCOMMAND) => Command::Check,
+        Some(FMT_SUBCOMMAND) => Command::Format {
+            check: arg_matches.is_present(CHECK_ARG),
+        },
+        Some(CACHE_SUBCOMMAND) => Command::ClearCache, // `clear` is the only cache subcommand
+        Some(INIT_SUBCOMMAND) => Command::Init,
+        Some(SCHEMA_SUBCOMMAND) => Command::Schema(
+            arg_matches
+                .value_of(KIND_ARG)
+                .and_then(schema::Kind::from_name)
+                .unwrap(), // [ref:schema_kind_valid]
+        ),
+        _ => {
+            if let Some(shell) = matches
+                .value_of(COMPLETIONS_ARG)
+                .and_then(completions::Shell::from_name)
+            // [ref:shell_valid]
+            {
+                Command::Completions(shell)
+            } else if matches.is_present(COMPLETE_TASKS_ARG) {
+                Command::CompleteTasks
+            } else if let Some(format) = matches
+                .value_of(GRAPH_ARG)
+                .and_then(graph::Format::from_name)
+            // [ref:graph_format_valid]
+            {
+                Command::Graph {
+                    format,
+                    cache_status: matches.is_present(CACHE_STATUS_ARG),
+                }
+            } else if let Some(kind) = matches
+                .value_of(PRINT_SCHEMA_ARG)
+                .and_then(schema::Kind::from_name)
+            // [ref:schema_kind_valid]
+            {
+                Command::Schema(kind)
+            } else if matches.is_present(LIST_ARG) {
+                Command::List(list_format(&matches))
+            } else if matches.is_present(FMT_ARG) {
+                Command::Format {
+                    check: matches.is_present(CHECK_ARG),
+                }
+            } else if matches.is_present(CHECK_ARG) {
+                Command::Check
+            } else if matches.is_present(SHELL_ARG) {
+                Command::Shell
+            } else {
+                Command::Run
+            }
+        }
+    };
+
+    // Find the toastfile. There's no need to look for one if we're just printing a schema or
+    // clearing the cache, and if we're writing a new one, it goes in the working directory.
+    let toastfile_path = arg_matches.value_of(TOASTFILE_ARG).map_or_else(
         || {
-            let mut candidate_dir =
-                current_dir().map_err(failure::system("Unable to determine working directory."))?;
-            loop {
-                let candidate_path = candidate_dir.join(TOASTFILE_DEFAULT_NAME);
-                if let Ok(metadata) = fs::metadata(&candidate_path) {
-                    if metadata.file_type().is_file() {
-                        return Ok(candidate_path);
-                    }
+            match command {
+                Command::Schema(_) | Command::ClearCache | Command::Completions(_) => {
+                    return Ok(PathBuf::new());
                 }
-                if !candidate_dir.pop() {
-                    return Err(Failure::User(
-                        format!(
-                            "Unable to locate file {}.",
-                            TOASTFILE_DEFAULT_NAME.code_str()
-                        ),
-                        None,
-                    ));
+                Command::Init => {
+                    return Ok(current_dir()
+                        .map_err(failure::system("Unable to determine working directory."))?
+                        .join(TOASTFILE_DEFAULT_NAMES[0]));
                 }
+                Command::Run
+                | Command::Shell
+                | Command::List(_)
+                | Command::Check
+                | Command::Format { .. }
+                | Command::CompleteTasks
+                | Command::Graph { .. } => {}
             }
+
+            locate_toastfile()
         },
         |x| Ok(Path::new(x).to_owned()),
     )?;
 
+    // A task with the same name as a subcommand used to be run by `toast <task>`, which now runs
+    // the subcommand instead [ref:uses_subcommand]. Rather than silently doing something else,
+    // make the user say which one they mean. `toast run <task>` is unambiguous.
+    if let Some(subcommand) = matches.subcommand_name() {
+        if subcommand != RUN_SUBCOMMAND || !arg_matches.is_present(TASKS_ARG) {
+            let shadowing_path = arg_matches
+                .value_of(TOASTFILE_ARG)
+                .map_or_else(|| locate_toastfile().ok(), |x| Some(PathBuf::from(x)));
+            if let Some(shadowing_path) = shadowing_path {
+                check_shadowed_task(&shadowing_path, subcommand)?;
+            }
+        }
+    }
+
     // Read the config file path.
     let default_config_file_path = dirs::config_dir().map(|path| path.join(CONFIG_FILE_XDG_PATH));
-    let config_file_path = matches.value_of(CONFIG_FILE_ARG).map_or_else(
+    let config_file_path = arg_matches.value_of(CONFIG_FILE_ARG).map_or_else(
         || default_config_file_path,
         |path| Some(PathBuf::from(path)),
     );
@@ -297,68 +765,76 @@ fn settings() -> Result<Settings, Failure> {
     )))?;
 
     // Read the local caching switches.
-    let read_local_cache = matches
+    let read_local_cache = arg_matches
         .value_of(READ_LOCAL_CACHE_ARG)
         .map_or(Ok(config.read_local_cache), |s| parse_bool(s))?;
-    let write_local_cache = matches
+    let write_local_cache = arg_matches
         .value_of(WRITE_LOCAL_CACHE_ARG)
         .map_or(Ok(config.write_local_cache), |s| parse_bool(s))?;
 
     // Read the remote caching switches.
-    let read_remote_cache = matches
+    let read_remote_cache = arg_matches
         .value_of(READ_REMOTE_CACHE_ARG)
         .map_or(Ok(config.read_remote_cache), |s| parse_bool(s))?;
-    let write_remote_cache = matches
+    let write_remote_cache = arg_matches
         .value_of(WRITE_REMOTE_CACHE_ARG)
         .map_or(Ok(config.write_remote_cache), |s| parse_bool(s))?;
 
     // Read the Docker repo.
-    let docker_repo = matches
+    let docker_repo = arg_matches
         .value_of(REPO_ARG)
         .unwrap_or(&config.docker_repo)
         .to_owned();
 
-    // Read the list switch.
-    let list = matches.is_present(LIST_ARG);
+    // Compile the patterns for redacting the output of tasks.
+    let redact_patterns = config
+        .redact_patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(failure::user(format!(
+                "Invalid regular expression {} in {}.",
+                pattern.code_str(),
+                "redact_patterns".code_str(),
+            )))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Read the variables to pass through to every task.
+    let passthrough_environment = config.passthrough_environment.clone();
+
+    // Determine where the hash cache lives.
+    let hash_cache_path = dirs::cache_dir().map(|path| path.join(HASH_CACHE_XDG_PATH));
 
-    // Read the shell switch.
-    let spawn_shell = matches.is_present(SHELL_ARG);
+    // Read the rehash switch.
+    let rehash = arg_matches.is_present(REHASH_ARG);
+
+    // Read the all switch.
+    let all = arg_matches.is_present(ALL_ARG);
 
     // Read the list of tasks.
-    let tasks = matches.values_of(TASKS_ARG).map(|tasks| {
+    let tasks = arg_matches.values_of(TASKS_ARG).map(|tasks| {
         tasks
             .map(std::borrow::ToOwned::to_owned)
             .collect::<Vec<_>>()
     });
 
     Ok(Settings {
+        command,
         toastfile_path,
         read_local_cache,
         write_local_cache,
         read_remote_cache,
         write_remote_cache,
         docker_repo,
-        list,
-        spawn_shell,
+        redact_patterns,
+        passthrough_environment,
+        hash_cache_path,
+        rehash,
+        all,
         tasks,
     })
 }
 
-// Parse a toastfile.
-fn parse_toastfile(toastfile_path: &Path) -> Result<toastfile::Toastfile, Failure> {
-    // Read the file from disk.
-    let toastfile_data = fs::read_to_string(toastfile_path).map_err(failure::user(format!(
-        "Unable to read file {}.",
-        toastfile_path.to_string_lossy().code_str(),
-    )))?;
-
-    // Parse it.
-    toastfile::parse(&toastfile_data).map_err(failure::user(format!(
-        "Unable to parse file {}.",
-        toastfile_path.to_string_lossy().code_str()
-    )))
-}
-
 // Determine which tasks the user wants to run.
 fn get_roots<'a>(
     settings: &'a Settings,
@@ -400,11 +876,12 @@ fn get_roots<'a>(
     )
 }
 
-// Fetch all the environment variables used by the tasks in the schedule.
+// Fetch the environment variables used by each task in the schedule. They're kept separate for
+// each task, since tasks expanded from a matrix use different values for the same variables.
 fn fetch_environment(
     schedule: &[&str],
     tasks: &HashMap<String, toastfile::Task>,
-) -> Result<HashMap<String, String>, Failure> {
+) -> Result<HashMap<String, HashMap<String, String>>, Failure> {
     let mut env = HashMap::new();
     let mut violations = HashMap::new();
 
@@ -412,7 +889,7 @@ fn fetch_environment(
         match toastfile::environment(&tasks[*task]) {
             // [ref:tasks_valid]
             Ok(env_for_task) => {
-                env.extend(env_for_task);
+                env.insert((*task).to_owned(), env_for_task);
             }
             Err(vars) => {
                 violations.insert((*task).to_owned(), vars);
@@ -449,15 +926,146 @@ fn fetch_environment(
     Ok(env)
 }
 
+// Fetch the environment variables used by the tasks in a schedule, replace the references to
+// variables in the toastfile with their values, and check that the resulting paths are still
+// valid.
+#[allow(clippy::type_complexity)]
+fn resolve_variables(
+    toastfile: &toastfile::Toastfile,
+    schedule: &[&str],
+) -> Result<
+    (
+        toastfile::Toastfile,
+        HashMap<String, HashMap<String, String>>,
+    ),
+    Failure,
+> {
+    let environment = fetch_environment(schedule, &toastfile.tasks)?;
+    let image_environment = toastfile::image_environment(toastfile).map_err(|vars| {
+        Failure::User(
+            format!(
+                "The image uses variables which are missing from the environment: {}.",
+                format::series(
+                    vars.iter()
+                        .map(|var| format!("{}", var.code_str()))
+                        .collect::<Vec<_>>()
+                        .as_ref()
+                ),
+            ),
+            None,
+        )
+    })?;
+    let toastfile = interpolate::toastfile(toastfile, &image_environment, &environment)?;
+    toastfile::check_paths(&toastfile)?;
+
+    Ok((toastfile, environment))
+}
+
+// Load the hash cache. If the user wants to read all the input files again, the stored hashes are
+// ignored but not discarded, since other projects share the same cache.
+fn load_hash_cache(settings: &Settings) -> HashCache {
+    settings
+        .hash_cache_path
+        .as_ref()
+        .map_or_else(HashCache::default, |path| {
+            hash_cache::load(path, settings.rehash)
+        })
+}
+
+// Save the hash cache for next time. Failing to do so isn't fatal.
+fn save_hash_cache(settings: &Settings, hash_cache: &mut HashCache) {
+    if let Some(path) = &settings.hash_cache_path {
+        if let Err(e) = hash_cache::save(hash_cache, path) {
+            warn!("{}", e);
+        }
+    }
+}
+
+// Determine which tasks in a schedule are in the local cache by computing their cache keys the
+// same way running them would. The tasks after one with `cache: false` are never read from the
+// cache, so they're left out.
+fn local_cache_status(
+    settings: &Settings,
+    toastfile: &toastfile::Toastfile,
+    schedule: &[&str],
+    interrupted: &Arc<AtomicBool>,
+) -> Result<HashMap<String, bool>, Failure> {
+    let (toastfile, environment) = resolve_variables(toastfile, schedule)?;
+    let mut hash_cache = load_hash_cache(settings);
+    let mut toastfile_dir = PathBuf::from(&settings.toastfile_path);
+    toastfile_dir.pop();
+
+    let mut cache_status = HashMap::new();
+    let mut cache_key = toastfile.image.clone();
+    for task_name in schedule {
+        let task = &toastfile.tasks[*task_name]; // [ref:tasks_valid]
+        if !task.cache {
+            break;
+        }
+
+        let input_files_hash = tar::hash(
+            "Reading files\u{2026}",
+            &task.input_paths,
+            &toastfile_dir,
+            &mut hash_cache,
+            interrupted,
+        )?;
+        cache_key = cache::key(
+            &cache_key,
+            task,
+            &input_files_hash,
+            &environment[*task_name],
+        );
+        let image = format!("{}:{}", settings.docker_repo, cache_key);
+        cache_status.insert(
+            (*task_name).to_owned(),
+            docker::image_exists(&image, interrupted)?,
+        );
+    }
+
+    save_hash_cache(settings, &mut hash_cache);
+
+    Ok(cache_status)
+}
+
+// Print the dependency graph of the tasks in a toastfile.
+fn print_graph(
+    settings: &Settings,
+    toastfile: &toastfile::Toastfile,
+    format: graph::Format,
+    cache_status: bool,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<(), Failure> {
+    let roots = get_roots(settings, toastfile)?;
+    let cache_status = if cache_status {
+        Some(local_cache_status(
+            settings,
+            toastfile,
+            &schedule::compute(toastfile, &roots),
+            interrupted,
+        )?)
+    } else {
+        None
+    };
+
+    print!(
+        "{}",
+        graph::render(toastfile, &roots, cache_status.as_ref(), format),
+    );
+
+    Ok(())
+}
+
 // Run some tasks and return the final context and the last attempted task.
 #[allow(clippy::too_many_arguments)]
 fn run_tasks(
     schedule: &[&str],
     settings: &Settings,
     toastfile: &toastfile::Toastfile,
-    environment: &HashMap<String, String>,
+    environment: &HashMap<String, HashMap<String, String>>,
     interrupted: &Arc<AtomicBool>,
     active_containers: &Arc<Mutex<HashSet<String>>>,
+    hash_cache: &mut HashCache,
 ) -> (Result<(), Failure>, runner::Context, Option<String>) {
     // This variable will be `true` as long as we're executing tasks that have `cache: true`. As
     // soon as we encounter a task with `cache: false`, this variable will be permanently set to
@@ -492,9 +1100,10 @@ fn run_tasks(
         info!("Running task {}\u{2026}", task.code_str());
         let (result, new_context) = runner::run(
             settings,
-            &environment,
+            &environment[*task], // [ref:environment_valid]
             &interrupted,
             &active_containers,
+            hash_cache,
             task_data,
             &cache_key,
             caching_enabled,
@@ -537,48 +1146,510 @@ fn entry() -> Result<(), Failure> {
     // Parse the command-line arguments;
     let settings = settings()?;
 
-    // Parse the toastfile.
-    let toastfile = parse_toastfile(&settings.toastfile_path)?;
+    // Handle the subcommands which don't need a toastfile.
+    match settings.command {
+        Command::Schema(kind) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&schema::schema(kind))
+                    .map_err(failure::system("Unable to serialize the schema."))?,
+            );
+            return Ok(());
+        }
+        Command::Init => return init_toastfile(&settings.toastfile_path),
+        Command::ClearCache => return clear_cache(&settings, &interrupted),
+        Command::Completions(shell) => {
+            print!("{}", completion_script(shell));
+            return Ok(());
+        }
+        Command::CompleteTasks => return print_task_names(&settings.toastfile_path),
+        Command::Run
+        | Command::Shell
+        | Command::List(_)
+        | Command::Check
+        | Command::Format { .. }
+        | Command::Graph { .. } => {}
+    }
+
+    // If the toastfile is actually a workspace, run the tasks in its members instead.
+    if let Some(workspace) = workspace::load(&settings.toastfile_path)? {
+        return run_workspace(&settings, &workspace, &interrupted, &active_containers);
+    }
+
+    // The `--all` option only makes sense for workspaces.
+    if settings.all {
+        return Err(Failure::User(
+            format!(
+                "The {} option requires a workspace, but {} is a toastfile.",
+                "--all".code_str(),
+                settings.toastfile_path.to_string_lossy().code_str(),
+            ),
+            None,
+        ));
+    }
+
+    // Parse the toastfile and the toastfiles it includes.
+    let toastfile = include::load(&settings.toastfile_path)?;
+
+    // Run the tasks.
+    run_toastfile(&settings, &toastfile, &interrupted, &active_containers)
+}
+
+// Run the tasks in the members of a workspace. Each member is run as if Toast were invoked on its
+// toastfile, so its paths are relative to its own directory.
+fn run_workspace(
+    settings: &Settings,
+    workspace: &workspace::Workspace,
+    interrupted: &Arc<AtomicBool>,
+    active_containers: &Arc<Mutex<HashSet<String>>>,
+) -> Result<(), Failure> {
+    // Load the toastfile of a member.
+    let load_member = |member: &Path| {
+        let member_dir = workspace::member_dir(&settings.toastfile_path, member);
+        let toastfile_path = find_toastfile(&member_dir)?.ok_or_else(|| {
+            Failure::User(
+                format!(
+                    "Unable to locate {} in {}.",
+                    toastfile_names(),
+                    member_dir.to_string_lossy().code_str(),
+                ),
+                None,
+            )
+        })?;
+        include::load(&toastfile_path).map(|toastfile| (toastfile_path, toastfile))
+    };
 
     // If the user just wants to list all the tasks, do that and quit.
-    if settings.list {
-        info!("Here are all the tasks and the environment variables they can use:");
+    if let Command::List(format) = settings.command {
+        let member_prefix = |member: &Path| {
+            format!(
+                "{}{}",
+                member.to_string_lossy(),
+                workspace::MEMBER_SEPARATOR
+            )
+        };
+
+        match format {
+            listing::Format::Text => {
+                info!("Here are all the tasks and the environment variables they can use:");
+
+                for member in &workspace.members {
+                    let (_, toastfile) = load_member(member)?;
+                    print_tasks(&toastfile, &member_prefix(member));
+                }
+            }
+            listing::Format::Json => {
+                let mut tasks = vec![];
+                for member in &workspace.members {
+                    let (_, toastfile) = load_member(member)?;
+                    tasks.extend(listing::tasks(&toastfile, &member_prefix(member)));
+                }
+                print_tasks_json(&tasks)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // If the user just wants to format the toastfiles, do that and quit.
+    if let Command::Format { check } = settings.command {
+        for member in &workspace.members {
+            let (toastfile_path, _) = load_member(member)?;
+            format_toastfile(&toastfile_path, check)?;
+        }
+
+        return Ok(());
+    }
+
+    // If the user just wants to check the toastfiles, do that and quit.
+    if settings.command == Command::Check {
+        for member in &workspace.members {
+            let (toastfile_path, toastfile) = load_member(member)?;
+            check_toastfile(&toastfile_path, &toastfile)?;
+        }
+
+        return Ok(());
+    }
+
+    // The dependency graph only covers the tasks of a single toastfile.
+    if let Command::Graph { .. } = settings.command {
+        return Err(Failure::User(
+            format!(
+                "The {} option doesn't support workspaces. Use {} to choose the toastfile of a \
+                 member.",
+                "--graph".code_str(),
+                "--file".code_str(),
+            ),
+            None,
+        ));
+    }
+
+    // Determine which tasks to run in which members. With `--all`, the tasks are run in every
+    // member which has them. Without any tasks, the default tasks of every member are run.
+    let mut targets = vec![];
+    match &settings.tasks {
+        Some(tasks) if !settings.all => {
+            for (member, member_tasks) in workspace::group(workspace, tasks)? {
+                targets.push((member, load_member(member)?, Some(member_tasks)));
+            }
+        }
+        tasks => {
+            let mut unused_tasks = tasks.clone().unwrap_or_default();
+            for member in &workspace.members {
+                let (toastfile_path, toastfile) = load_member(member)?;
+                let member_tasks = tasks.as_ref().map(|tasks| {
+                    tasks
+                        .iter()
+                        .filter(|task| toastfile.tasks.contains_key(*task))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                });
+                if member_tasks.as_ref().map_or(false, Vec::is_empty) {
+                    continue;
+                }
+                unused_tasks.retain(|task| !toastfile.tasks.contains_key(task));
+                targets.push((member.as_ref(), (toastfile_path, toastfile), member_tasks));
+            }
+
+            if !unused_tasks.is_empty() {
+                return Err(Failure::User(
+                    format!(
+                        "No member of the workspace has the following tasks: {}.",
+                        format::series(
+                            unused_tasks
+                                .iter()
+                                .map(|task| format!("{}", task.code_str()))
+                                .collect::<Vec<_>>()
+                                .as_ref(),
+                        ),
+                    ),
+                    None,
+                ));
+            }
+        }
+    }
+
+    // A shell can only be spawned for a single member.
+    if settings.command == Command::Shell && targets.len() > 1 {
+        return Err(Failure::User(
+            format!(
+                "The {} subcommand can only be used with tasks from a single member of the \
+                 workspace.",
+                "shell".code_str(),
+            ),
+            None,
+        ));
+    }
 
-        // Sort the tasks.
-        let mut task_names = toastfile.tasks.keys().collect::<Vec<_>>().clone();
-        task_names.sort();
+    // Run the tasks in each member.
+    for (member, (toastfile_path, toastfile), tasks) in targets {
+        info!(
+            "Running tasks in member {}\u{2026}",
+            member.to_string_lossy().code_str(),
+        );
 
-        // Print a summary of each task.
-        for task_name in task_names {
-            // Fetch the task data.
-            let task_data = &toastfile.tasks[task_name];
+        let member_settings = Settings {
+            toastfile_path,
+            tasks,
+            ..settings.clone()
+        };
+        run_toastfile(&member_settings, &toastfile, interrupted, active_containers)?;
+    }
+
+    Ok(())
+}
+
+// Print the tasks in a toastfile, along with the environment variables they can use. The names
+// are prefixed with `prefix`.
+fn print_tasks(toastfile: &toastfile::Toastfile, prefix: &str) {
+    // Sort the tasks.
+    let mut task_names = toastfile.tasks.keys().collect::<Vec<_>>().clone();
+    task_names.sort();
+
+    // Print a summary of each task.
+    for task_name in task_names {
+        // Fetch the task data.
+        let task_data = &toastfile.tasks[task_name];
+
+        // Print the task name and the description if it exists.
+        if let Some(description) = &task_data.description {
+            println!(
+                "* {} \u{2014} {}",
+                format!("{}{}", prefix, task_name).code_str(),
+                description,
+            );
+        } else {
+            println!("* {}", format!("{}{}", prefix, task_name).code_str());
+        }
 
-            // Print the task name and the description if it exists.
-            if let Some(description) = &task_data.description {
-                println!("* {} \u{2014} {}", task_name.code_str(), description);
+        // Print the environment variables that can be passed to the task.
+        for (variable, optional_default) in task_data
+            .environment
+            .iter()
+            .chain(task_data.cache_neutral_environment.iter())
+        {
+            if let Some(default) = optional_default {
+                println!("  {}: {}", variable.code_str(), default.code_str());
             } else {
-                println!("* {}", task_name.code_str());
+                println!("  {}: (no default provided)", variable.code_str());
             }
+        }
+    }
+}
 
-            // Print the environment variables that can be passed to the task.
-            for (variable, optional_default) in &task_data.environment {
-                if let Some(default) = optional_default {
-                    println!("  {}: {}", variable.code_str(), default.code_str());
-                } else {
-                    println!("  {}: (no default provided)", variable.code_str());
-                }
+// Print the tasks in the machine-readable format.
+fn print_tasks_json(tasks: &[listing::Task]) -> Result<(), Failure> {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(tasks)
+            .map_err(failure::system("Unable to serialize the tasks."))?,
+    );
+    Ok(())
+}
+
+// Report the warnings for a toastfile which has already been validated.
+fn check_toastfile(toastfile_path: &Path, toastfile: &toastfile::Toastfile) -> Result<(), Failure> {
+    let toastfile_data = fs::read_to_string(toastfile_path).map_err(failure::user(format!(
+        "Unable to read file {}.",
+        toastfile_path.to_string_lossy().code_str(),
+    )))?;
+    let mut toastfile_dir = PathBuf::from(toastfile_path);
+    toastfile_dir.pop();
+
+    let warnings = lint::lint(toastfile, &toastfile_dir);
+    if warnings.0.is_empty() {
+        info!(
+            "No problems found in {}.",
+            toastfile_path.to_string_lossy().code_str(),
+        );
+    } else {
+        eprintln!(
+            "{}\n",
+            diagnostic::render(
+                &warnings,
+                toastfile_path,
+                &toastfile_data,
+                toastfile::FileFormat::from_path(toastfile_path),
+            ),
+        );
+        warn!(
+            "Found {} in {}.",
+            format::number(warnings.0.len(), "warning"),
+            toastfile_path.to_string_lossy().code_str(),
+        );
+    }
+
+    Ok(())
+}
+
+// Format a toastfile, or with `check`, fail if it isn't formatted.
+fn format_toastfile(toastfile_path: &Path, check: bool) -> Result<(), Failure> {
+    if toastfile::FileFormat::from_path(toastfile_path) != toastfile::FileFormat::Yaml {
+        return Err(Failure::User(
+            format!(
+                "Unable to format {}. Only YAML toastfiles can be formatted.",
+                toastfile_path.to_string_lossy().code_str(),
+            ),
+            None,
+        ));
+    }
+
+    let toastfile_data = fs::read_to_string(toastfile_path).map_err(failure::user(format!(
+        "Unable to read file {}.",
+        toastfile_path.to_string_lossy().code_str(),
+    )))?;
+    let formatted = formatter::format(&toastfile_data)?;
+
+    if formatted == toastfile_data {
+        info!(
+            "{} is already formatted.",
+            toastfile_path.to_string_lossy().code_str(),
+        );
+    } else if check {
+        return Err(Failure::User(
+            format!(
+                "{} is not formatted. Run {} to format it.",
+                toastfile_path.to_string_lossy().code_str(),
+                "toast fmt".code_str(),
+            ),
+            None,
+        ));
+    } else {
+        fs::write(toastfile_path, formatted).map_err(failure::system(format!(
+            "Unable to write file {}.",
+            toastfile_path.to_string_lossy().code_str(),
+        )))?;
+        info!("Formatted {}.", toastfile_path.to_string_lossy().code_str());
+    }
+
+    Ok(())
+}
+
+// Generate a completion script for a shell.
+fn completion_script(shell: completions::Shell) -> String {
+    let task_subcommands = [RUN_SUBCOMMAND, SHELL_SUBCOMMAND];
+    let taskless_subcommands = SUBCOMMANDS
+        .iter()
+        .filter(|subcommand| !task_subcommands.contains(subcommand))
+        .cloned()
+        .collect::<Vec<_>>();
+    let value_options = VALUE_ARGS
+        .iter()
+        .map(|arg| format!("--{}", arg))
+        .chain(VALUE_SHORT_ARGS.iter().map(|arg| format!("-{}", arg)))
+        .collect::<Vec<_>>();
+
+    completions::script(
+        app(Cli::Completions),
+        shell,
+        &completions::Interface {
+            file_options: &["-f".to_owned(), format!("--{}", TOASTFILE_ARG)],
+            value_options: &value_options,
+            task_subcommands: &task_subcommands,
+            taskless_subcommands: &taskless_subcommands,
+            complete_tasks_option: &format!("--{}", COMPLETE_TASKS_ARG),
+        },
+    )
+}
+
+// Print the names of the tasks in a toastfile, including the ones expanded from matrices, one per
+// line. For a workspace, print the tasks of every member.
+fn print_task_names(toastfile_path: &Path) -> Result<(), Failure> {
+    let mut task_names = vec![];
+    if let Some(workspace) = workspace::load(toastfile_path)? {
+        for member in &workspace.members {
+            let member_dir = workspace::member_dir(toastfile_path, member);
+            if let Some(member_toastfile_path) = find_toastfile(&member_dir)? {
+                let toastfile = include::load(&member_toastfile_path)?;
+                task_names.extend(toastfile.tasks.keys().map(|task| {
+                    format!(
+                        "{}{}{}",
+                        member.to_string_lossy(),
+                        workspace::MEMBER_SEPARATOR,
+                        task,
+                    )
+                }));
             }
         }
+    } else {
+        task_names.extend(include::load(toastfile_path)?.tasks.keys().cloned());
+    }
 
-        // The user just wanted to list the tasks. We're done.
+    task_names.sort();
+    for task_name in task_names {
+        println!("{}", task_name);
+    }
+
+    Ok(())
+}
+
+// Write a starter toastfile to `toastfile_path` based on the project in its directory. Toast won't
+// overwrite an existing toastfile, or add one next to a toastfile with a different name.
+fn init_toastfile(toastfile_path: &Path) -> Result<(), Failure> {
+    if toastfile::FileFormat::from_path(toastfile_path) != toastfile::FileFormat::Yaml {
+        return Err(Failure::User(
+            format!(
+                "Unable to write {}. Only YAML toastfiles can be generated.",
+                toastfile_path.to_string_lossy().code_str(),
+            ),
+            None,
+        ));
+    }
+
+    let toastfile_dir = match toastfile_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => PathBuf::from("."),
+    };
+    let is_default_name = toastfile_path.file_name().map_or(false, |name| {
+        TOASTFILE_DEFAULT_NAMES
+            .iter()
+            .any(|default| name == *default)
+    });
+    if is_default_name && !toastfile_path.exists() {
+        if let Some(existing_path) = find_toastfile(&toastfile_dir)? {
+            return Err(Failure::User(
+                format!(
+                    "{} already exists. Toast won't overwrite it.",
+                    existing_path.to_string_lossy().code_str(),
+                ),
+                None,
+            ));
+        }
+    }
+
+    scaffold::init(&toastfile_dir, toastfile_path)?;
+    info!("Wrote {}.", toastfile_path.to_string_lossy().code_str());
+
+    Ok(())
+}
+
+// Delete the images in the local cache, along with the cached hashes of input files.
+fn clear_cache(settings: &Settings, interrupted: &Arc<AtomicBool>) -> Result<(), Failure> {
+    let images = docker::list_images(&settings.docker_repo, interrupted)?;
+    for image in &images {
+        docker::delete_image(image, interrupted)?;
+    }
+
+    if let Some(path) = &settings.hash_cache_path {
+        if path.exists() {
+            fs::remove_file(path).map_err(failure::system(format!(
+                "Unable to delete file {}.",
+                path.to_string_lossy().code_str(),
+            )))?;
+        }
+    }
+
+    info!(
+        "Deleted {} from the local cache.",
+        format::number(images.len(), "image"),
+    );
+
+    Ok(())
+}
+
+// Run the tasks in a toastfile, or list, format, or check them if the user asked for that.
+fn run_toastfile(
+    settings: &Settings,
+    toastfile: &toastfile::Toastfile,
+    interrupted: &Arc<AtomicBool>,
+    active_containers: &Arc<Mutex<HashSet<String>>>,
+) -> Result<(), Failure> {
+    // If the user just wants to list all the tasks, do that and quit.
+    if let Command::List(format) = settings.command {
+        match format {
+            listing::Format::Text => {
+                info!("Here are all the tasks and the environment variables they can use:");
+                print_tasks(toastfile, "");
+            }
+            listing::Format::Json => print_tasks_json(&listing::tasks(toastfile, ""))?,
+        }
         return Ok(());
     }
 
+    // If the user just wants to format the toastfile, do that and quit.
+    if let Command::Format { check } = settings.command {
+        return format_toastfile(&settings.toastfile_path, check);
+    }
+
+    // If the user just wants to check the toastfile, do that and quit.
+    if settings.command == Command::Check {
+        return check_toastfile(&settings.toastfile_path, toastfile);
+    }
+
+    // If the user just wants to see the dependency graph, print it and quit.
+    if let Command::Graph {
+        format,
+        cache_status,
+    } = settings.command
+    {
+        return print_graph(settings, toastfile, format, cache_status, interrupted);
+    }
+
     // Determine which tasks the user wants to run.
-    let root_tasks = get_roots(&settings, &toastfile)?;
+    let root_tasks = get_roots(settings, toastfile)?;
 
     // Compute a schedule of tasks to run.
-    let schedule = schedule::compute(&toastfile, &root_tasks);
+    let schedule = schedule::compute(toastfile, &root_tasks);
     if !schedule.is_empty() {
         info!(
             "Ready to run {}: {}.",
@@ -593,19 +1664,27 @@ fn entry() -> Result<(), Failure> {
         );
     }
 
-    // Fetch all the environment variables used by the tasks in the schedule.
-    let environment = fetch_environment(&schedule, &toastfile.tasks)?;
+    // Fetch the environment variables used by the tasks in the schedule, and substitute them into
+    // the toastfile.
+    let (toastfile, environment) = resolve_variables(toastfile, &schedule)?;
+
+    // Load the hash cache, unless the user wants to read all the input files again.
+    let mut hash_cache = load_hash_cache(settings);
 
     // Execute the schedule.
     let (result, context, last_task) = run_tasks(
         &schedule,
-        &settings,
+        settings,
         &toastfile,
         &environment,
-        &interrupted,
-        &active_containers,
+        interrupted,
+        active_containers,
+        &mut hash_cache,
     );
 
+    // Save the hash cache for next time.
+    save_hash_cache(settings, &mut hash_cache);
+
     // Return early if needed.
     match result {
         Ok(_) | Err(Failure::User(_, _)) => {
@@ -618,7 +1697,7 @@ fn entry() -> Result<(), Failure> {
     };
 
     // Drop the user into a shell if requested.
-    if settings.spawn_shell {
+    if settings.command == Command::Shell {
         // If one of the tasks failed, tell the user now before we drop into a shell.
         if let Err(e) = &result {
             error!("{}", e);
@@ -628,20 +1707,18 @@ fn entry() -> Result<(), Failure> {
         info!("Preparing a shell\u{2026}");
 
         // Determine the environment, location, and user for the shell.
-        let (task_environment, location, user) = if let Some(last_task) = last_task {
+        let (task_environment, location, user) = if let Some(last_task_name) = last_task {
             // Get the data for the last task.
-            let last_task = &toastfile.tasks[&last_task]; // [ref:tasks_valid]
-
-            // Prepare the environment.
-            let mut task_environment = HashMap::<String, String>::new();
-            for variable in last_task.environment.keys() {
-                // [ref:environment_valid]
-                task_environment.insert(variable.to_owned(), environment[variable].clone());
-            }
+            let last_task = &toastfile.tasks[&last_task_name]; // [ref:tasks_valid]
 
             // Use the environment, path, and user from the last task.
             (
-                task_environment,
+                runner::task_environment(
+                    &settings.passthrough_environment,
+                    &environment[&last_task_name],
+                    last_task,
+                )
+                .merge(),
                 last_task.location.clone(),
                 last_task.user.clone(),
             )
@@ -661,7 +1738,7 @@ fn entry() -> Result<(), Failure> {
             &task_environment,
             &location,
             &user,
-            &interrupted,
+            interrupted,
         )?;
     }
 
@@ -0,0 +1,202 @@
+use regex::bytes::Regex;
+use std::io::{self, Read, Write};
+
+// This is what redacted text is replaced with.
+pub const MASK: &[u8] = b"***";
+
+// Output is redacted one line at a time, but a line longer than this is written out in pieces so
+// a task which never prints a newline doesn't cause unbounded buffering.
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+// A redactor masks secret values and any text matching a set of patterns.
+pub struct Redactor {
+    values: Vec<Vec<u8>>,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    // Construct a redactor for the given secret values and patterns. Output is redacted one line
+    // at a time [ref:redact_by_line], so each line of a multiline value is masked separately.
+    pub fn new<'a, I: IntoIterator<Item = &'a str>>(values: I, patterns: &[Regex]) -> Self {
+        let mut values = values
+            .into_iter()
+            .flat_map(str::lines)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.as_bytes().to_owned())
+            .collect::<Vec<_>>();
+
+        // Mask longer values first, in case one value contains another.
+        values.sort_by(|x, y| y.len().cmp(&x.len()).then_with(|| x.cmp(y)));
+        values.dedup();
+
+        Redactor {
+            values,
+            patterns: patterns.to_owned(),
+        }
+    }
+
+    // Determine whether this redactor would leave everything unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty() && self.patterns.is_empty()
+    }
+
+    // Mask all the secret values and pattern matches in some text.
+    pub fn redact(&self, text: &[u8]) -> Vec<u8> {
+        let mut result = text.to_owned();
+
+        for value in &self.values {
+            result = replace(&result, value);
+        }
+
+        for pattern in &self.patterns {
+            result = pattern.replace_all(&result, MASK).into_owned();
+        }
+
+        result
+    }
+}
+
+// Replace every occurrence of `needle` in `haystack` with the mask.
+fn replace(haystack: &[u8], needle: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(haystack.len());
+    let mut i = 0;
+
+    while i < haystack.len() {
+        if haystack[i..].starts_with(needle) {
+            result.extend_from_slice(MASK);
+            i += needle.len();
+        } else {
+            result.push(haystack[i]);
+            i += 1;
+        }
+    }
+
+    result
+}
+
+// Copy data from a reader to a writer, redacting it along the way. Data is redacted one line at a
+// time, so a secret is only caught if it doesn't span a line break. [tag:redact_by_line]
+pub fn copy<R: Read, W: Write>(
+    redactor: &Redactor,
+    mut reader: R,
+    mut writer: W,
+) -> io::Result<()> {
+    let mut buffer = [0; 8 * 1024];
+    let mut pending = Vec::new();
+
+    loop {
+        let bytes_read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buffer[..bytes_read]);
+
+        // Write out all the complete lines. Carriage returns count as line breaks too, since they
+        // are often used to redraw progress indicators.
+        let end = if pending.len() > MAX_LINE_LENGTH {
+            Some(pending.len())
+        } else {
+            pending
+                .iter()
+                .rposition(|&byte| byte == b'\n' || byte == b'\r')
+                .map(|i| i + 1)
+        };
+
+        if let Some(end) = end {
+            let rest = pending.split_off(end);
+            writer.write_all(&redact_lines(redactor, &pending))?;
+            writer.flush()?;
+            pending = rest;
+        }
+    }
+
+    // Write out whatever is left over.
+    writer.write_all(&redactor.redact(&pending))?;
+    writer.flush()
+}
+
+// Redact each line of some text separately.
+fn redact_lines(redactor: &Redactor, text: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(text.len());
+
+    // Each line includes its terminator.
+    let mut start = 0;
+    for (index, &byte) in text.iter().enumerate() {
+        if byte == b'\n' || byte == b'\r' {
+            result.extend_from_slice(&redactor.redact(&text[start..=index]));
+            start = index + 1;
+        }
+    }
+    if start < text.len() {
+        result.extend_from_slice(&redactor.redact(&text[start..]));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redact::{copy, Redactor};
+    use regex::bytes::Regex;
+    use std::io::Read;
+
+    #[test]
+    fn redact_nothing() {
+        let redactor = Redactor::new(vec![], &[]);
+
+        assert!(redactor.is_empty());
+        assert_eq!(redactor.redact(b"foo bar"), b"foo bar".to_vec());
+    }
+
+    #[test]
+    fn redact_values() {
+        let redactor = Redactor::new(vec!["foo", "foobar", "baz"], &[]);
+
+        assert!(!redactor.is_empty());
+        assert_eq!(
+            redactor.redact(b"foobar foo qux baz"),
+            b"*** *** qux ***".to_vec(),
+        );
+    }
+
+    #[test]
+    fn redact_multiline_value() {
+        let redactor = Redactor::new(vec!["foo\nbar\n\n"], &[]);
+
+        assert_eq!(redactor.redact(b"foo bar\n"), b"*** ***\n".to_vec());
+    }
+
+    #[test]
+    fn redact_patterns() {
+        let redactor = Redactor::new(vec![], &[Regex::new("ghp_[a-zA-Z0-9]+").unwrap()]);
+
+        assert!(!redactor.is_empty());
+        assert_eq!(
+            redactor.redact(b"token: ghp_abc123"),
+            b"token: ***".to_vec(),
+        );
+    }
+
+    #[test]
+    fn copy_lines() {
+        let redactor = Redactor::new(vec!["foo"], &[Regex::new("qu+x").unwrap()]);
+        let mut output = vec![];
+
+        copy(&redactor, &b"foo\nbar\r\nbaz\nqux foo"[..], &mut output).unwrap();
+
+        assert_eq!(output, b"***\nbar\r\nbaz\n*** ***".to_vec());
+    }
+
+    #[test]
+    fn copy_split_reads() {
+        let redactor = Redactor::new(vec!["secret"], &[]);
+        let mut output = vec![];
+
+        // The secret is split across two reads, but not across lines.
+        copy(&redactor, (&b"sec"[..]).chain(&b"ret\n"[..]), &mut output).unwrap();
+
+        assert_eq!(output, b"***\n".to_vec());
+    }
+}
@@ -1,14 +1,16 @@
-use crate::{cache, docker, failure, failure::Failure, tar, toastfile::Task};
+use crate::{
+    cache, docker, failure::Failure, hash_cache::HashCache, redact::Redactor, secrets, tar,
+    toastfile::Task,
+};
 use std::{
     collections::{HashMap, HashSet},
-    io::{Seek, SeekFrom},
+    env,
     path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
     },
 };
-use tempfile::tempfile;
 
 // A context is an image that may need to be cleaned up.
 #[derive(Clone)]
@@ -29,6 +31,57 @@ impl Drop for Context {
     }
 }
 
+// The environment variables for a task, split by whether they're part of the cache key. Only the
+// keyed variables may be stored in the container configuration, since `docker commit` copies it
+// into the image, which may be pushed to the remote cache. The unkeyed variables, such as proxy
+// credentials, are only provided to the command while it runs.
+// [tag:unkeyed_environment_not_committed]
+pub struct TaskEnvironment {
+    pub keyed: HashMap<String, String>,
+    pub unkeyed: HashMap<String, String>,
+}
+
+impl TaskEnvironment {
+    // Combine the keyed and unkeyed variables, for when nothing will be committed.
+    pub fn merge(self) -> HashMap<String, String> {
+        let mut environment = self.keyed;
+        environment.extend(self.unkeyed);
+        environment
+    }
+}
+
+// Determine the environment variables for a task. This includes the variables declared by the
+// task and any passthrough variables which are set on the host. Variables declared by the task
+// take precedence.
+pub fn task_environment(
+    passthrough_environment: &[String],
+    environment: &HashMap<String, String>,
+    task: &Task,
+) -> TaskEnvironment {
+    let mut keyed = HashMap::<String, String>::new();
+    let mut unkeyed = HashMap::<String, String>::new();
+
+    for variable in passthrough_environment {
+        if !task.environment.contains_key(variable) {
+            if let Ok(value) = env::var(variable) {
+                unkeyed.insert(variable.to_owned(), value);
+            }
+        }
+    }
+
+    for variable in task.environment.keys() {
+        // [ref:environment_valid]
+        keyed.insert(variable.to_owned(), environment[variable].clone());
+    }
+
+    for variable in task.cache_neutral_environment.keys() {
+        // [ref:environment_valid]
+        unkeyed.insert(variable.to_owned(), environment[variable].clone());
+    }
+
+    TaskEnvironment { keyed, unkeyed }
+}
+
 // Run a task and return the new cache key.
 #[allow(clippy::too_many_arguments)]
 pub fn run(
@@ -36,6 +89,7 @@ pub fn run(
     environment: &HashMap<String, String>,
     interrupted: &Arc<AtomicBool>,
     active_containers: &Arc<Mutex<HashSet<String>>>,
+    hash_cache: &mut HashCache,
     task: &Task,
     previous_cache_key: &str,
     caching_enabled: bool,
@@ -45,51 +99,32 @@ pub fn run(
     let mut toastfile_dir = PathBuf::from(&settings.toastfile_path);
     toastfile_dir.pop();
 
-    // Create a temporary archive for the input file contents.
-    let tar_file = match tempfile() {
-        Ok(tar_file) => tar_file,
-        Err(e) => {
-            return (
-                Err(failure::system("Unable to create temporary file.")(e)),
-                context,
-            )
-        }
-    };
-
-    // Write to the archive.
-    let (mut tar_file, input_files_hash) = match tar::create(
+    // Compute a hash of the input files. The archive itself is only built if the task needs to run.
+    let input_files_hash = match tar::hash(
         "Reading files\u{2026}",
-        tar_file,
         &task.input_paths,
         &toastfile_dir,
-        &task.location,
-        &interrupted,
+        hash_cache,
+        interrupted,
     ) {
-        Ok((tar_file, input_files_hash)) => (tar_file, input_files_hash),
+        Ok(input_files_hash) => input_files_hash,
         Err(e) => return (Err(e), context),
     };
 
-    // Seek back to the beginning of the archive to prepare for copying it into the container.
-    if let Err(e) = tar_file.seek(SeekFrom::Start(0)) {
-        return (
-            Err(failure::system("Unable to seek temporary file.")(e)),
-            context,
-        );
-    };
+    // Construct the environment.
+    let task_environment = task_environment(&settings.passthrough_environment, environment, task);
 
-    // Compute the cache key.
-    let cache_key = cache::key(previous_cache_key, &task, &input_files_hash, &environment);
+    // Compute the cache key. Only the keyed variables go into it.
+    let cache_key = cache::key(
+        previous_cache_key,
+        &task,
+        &input_files_hash,
+        &task_environment.keyed,
+    );
 
     // This is the image we'll look for in the caches.
     let image = format!("{}:{}", settings.docker_repo, cache_key);
 
-    // Construct the environment.
-    let mut task_environment = HashMap::<String, String>::new();
-    for variable in task.environment.keys() {
-        // [ref:environment_valid]
-        task_environment.insert(variable.to_owned(), environment[variable].clone());
-    }
-
     // Check the cache, if applicable.
     let mut cached = false;
     if caching_enabled {
@@ -133,9 +168,11 @@ pub fn run(
             let container = match docker::create_container(
                 &image,
                 &toastfile_dir,
-                &task_environment,
+                &task_environment.keyed,
                 &task.mount_paths,
                 task.mount_readonly,
+                None,
+                None,
                 &task.ports,
                 &task.location,
                 &task.user,
@@ -188,13 +225,50 @@ pub fn run(
             }
         }
 
+        // Read the secrets and write them to a directory to be mounted into the container. The
+        // directory is deleted when we're done.
+        let (secret_values, secrets_dir) = if task.secrets.is_empty() {
+            (HashMap::new(), None)
+        } else {
+            match secrets::fetch(task, &toastfile_dir).and_then(|secret_values| {
+                secrets::write_dir(&secret_values)
+                    .map(|secrets_dir| (secret_values, Some(secrets_dir)))
+            }) {
+                Ok(secrets) => secrets,
+                Err(e) => return (Err(e), context),
+            }
+        };
+
+        // Mask the secrets and anything matching the configured patterns in the task's output.
+        let redactor = Redactor::new(
+            secret_values.values().map(AsRef::as_ref),
+            &settings.redact_patterns,
+        );
+
+        // Write the unkeyed variables to a file to be mounted into the container. The file is
+        // deleted when we're done. [ref:unkeyed_environment_not_committed]
+        let environment_file = if task_environment.unkeyed.is_empty() {
+            None
+        } else {
+            match secrets::write_environment_file(&task_environment.unkeyed) {
+                Ok(environment_file) => Some(environment_file),
+                Err(e) => return (Err(e), context),
+            }
+        };
+
         // Create a container from the image.
         let container = match docker::create_container(
             &context.image,
             &toastfile_dir,
-            &task_environment,
+            &task_environment.keyed,
             &task.mount_paths,
             task.mount_readonly,
+            secrets_dir
+                .as_ref()
+                .map(|secrets_dir| secrets_dir.path.as_ref()),
+            environment_file
+                .as_ref()
+                .map(|environment_file| environment_file.path.as_ref()),
             &task.ports,
             &task.location,
             &task.user,
@@ -230,19 +304,33 @@ pub fn run(
           }
         }}
 
-        // Copy files into the container. If `task.input_paths` is empty, then this will just create
-        // a directory for `task.location`.
-        if let Err(e) = docker::copy_into_container(&container, &mut tar_file, interrupted) {
+        // Copy files into the container by streaming an archive directly to Docker. If
+        // `task.input_paths` is empty, then this will just create a directory for `task.location`.
+        if let Err(e) = docker::copy_into_container(
+            &container,
+            |stdin| {
+                tar::create(
+                    stdin,
+                    &task.input_paths,
+                    &toastfile_dir,
+                    &task.location,
+                    interrupted,
+                )
+                .map(|_| ())
+            },
+            interrupted,
+        ) {
             return (Err(e), context);
         }
 
         // Start the container to run the command.
-        let result = docker::start_container(&container, interrupted).map_err(|e| match e {
-            Failure::Interrupted => e,
-            Failure::System(_, _) | Failure::User(_, _) => {
-                Failure::User("Command failed.".to_owned(), None)
-            }
-        });
+        let result =
+            docker::start_container(&container, &redactor, interrupted).map_err(|e| match e {
+                Failure::Interrupted => e,
+                Failure::System(_, _) | Failure::User(_, _) => {
+                    Failure::User("Command failed.".to_owned(), None)
+                }
+            });
 
         // Copy files from the container, if applicable.
         if result.is_ok() && !task.output_paths.is_empty() {
@@ -291,3 +379,92 @@ pub fn run(
         (result.map(|_| cache_key), new_context)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        runner::task_environment,
+        toastfile::{Task, DEFAULT_LOCATION, DEFAULT_USER},
+    };
+    use std::{collections::HashMap, env, path::Path};
+
+    fn task_with_environment(
+        environment: HashMap<String, Option<String>>,
+        cache_neutral_environment: HashMap<String, Option<String>>,
+    ) -> Task {
+        Task {
+            description: None,
+            extends: None,
+            dependencies: vec![],
+            cache: true,
+            environment,
+            cache_neutral_environment,
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
+            input_paths: vec![],
+            output_paths: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: Path::new(DEFAULT_LOCATION).to_owned(),
+            user: DEFAULT_USER.to_owned(),
+            command: None,
+        }
+    }
+
+    #[test]
+    fn task_environment_unkeyed_not_committed() {
+        // NOTE: The passthrough variables have unique names to avoid having parallel tests
+        // clobbering environment variables used by other threads.
+        env::set_var("runner_passthrough1", "foo");
+
+        let mut declared = HashMap::new();
+        declared.insert("BAR".to_owned(), None);
+        let mut cache_neutral = HashMap::new();
+        cache_neutral.insert("BAZ".to_owned(), None);
+
+        let mut environment = HashMap::new();
+        environment.insert("BAR".to_owned(), "qux".to_owned());
+        environment.insert("BAZ".to_owned(), "corge".to_owned());
+
+        let task_environment = task_environment(
+            &["runner_passthrough1".to_owned()],
+            &environment,
+            &task_with_environment(declared, cache_neutral),
+        );
+
+        let mut expected_keyed = HashMap::new();
+        expected_keyed.insert("BAR".to_owned(), "qux".to_owned());
+
+        let mut expected_unkeyed = HashMap::new();
+        expected_unkeyed.insert("runner_passthrough1".to_owned(), "foo".to_owned());
+        expected_unkeyed.insert("BAZ".to_owned(), "corge".to_owned());
+
+        assert_eq!(task_environment.keyed, expected_keyed);
+        assert_eq!(task_environment.unkeyed, expected_unkeyed);
+    }
+
+    #[test]
+    fn task_environment_declared_overrides_passthrough() {
+        env::set_var("runner_passthrough2", "foo");
+
+        let mut declared = HashMap::new();
+        declared.insert("runner_passthrough2".to_owned(), None);
+
+        let mut environment = HashMap::new();
+        environment.insert("runner_passthrough2".to_owned(), "bar".to_owned());
+
+        let task_environment = task_environment(
+            &["runner_passthrough2".to_owned()],
+            &environment,
+            &task_with_environment(declared, HashMap::new()),
+        );
+
+        assert_eq!(
+            task_environment.keyed.get("runner_passthrough2"),
+            Some(&"bar".to_owned()),
+        );
+        assert!(task_environment.unkeyed.is_empty());
+    }
+}
@@ -0,0 +1,635 @@
+use crate::{failure, failure::Failure, format, format::CodeStr};
+use serde_json::Value;
+use std::{fs, path::Path};
+
+// The base image for new toastfiles
+const IMAGE: &str = "ubuntu:18.04";
+
+// The system packages every project needs
+const PACKAGES: &[&str] = &["build-essential", "curl"];
+
+// The comment above `cache: false` in a new toastfile, explaining why the task isn't cached
+const UNCACHED_COMMENT: &[&str] = &[
+    "This task installs whatever versions of the packages are current, so it isn't cached.",
+    "To cache it and the tasks which depend on it, pin the versions and remove `cache: false`.",
+];
+
+// A task in a new toastfile. An empty `command` means the task has no command.
+struct Task {
+    name: String,
+    description: String,
+    dependencies: Vec<String>,
+    cache: bool,
+    input_paths: Vec<String>,
+    command: Vec<String>,
+}
+
+// A kind of project Toast knows how to build, based on the files in its directory
+struct Project {
+    language: &'static str,
+    install: Vec<String>,
+    prelude: Vec<&'static str>,
+    fetch: Option<(Vec<String>, Vec<String>)>,
+    input_paths: Vec<String>,
+    build: Vec<String>,
+    test: Vec<String>,
+    lint: Vec<String>,
+}
+
+// Convert a list of string slices into owned strings.
+fn owned(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|&line| line.to_owned()).collect()
+}
+
+// Keep the paths which exist in `dir`.
+fn existing(dir: &Path, paths: &[&str]) -> Vec<String> {
+    paths
+        .iter()
+        .filter(|path| dir.join(path).exists())
+        .map(|&path| path.to_owned())
+        .collect()
+}
+
+// List the names of the files and directories in `dir` which satisfy `predicate`, skipping hidden
+// ones. The names are sorted.
+fn entries<P: Fn(&Path) -> bool>(dir: &Path, predicate: P) -> Vec<String> {
+    let mut names = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| predicate(&entry.path()))
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| !name.starts_with('.'))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+// Determine whether a path has the given extension.
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().map_or(false, |actual| actual == extension)
+}
+
+// Determine whether a directory directly contains a file with the given extension.
+fn contains_extension(dir: &Path, extension: &str) -> bool {
+    dir.is_dir() && !entries(dir, |path| has_extension(path, extension)).is_empty()
+}
+
+// Sort a list of paths and remove duplicates.
+fn sorted(mut paths: Vec<String>) -> Vec<String> {
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+// Describe a Rust project.
+fn rust(dir: &Path) -> Project {
+    Project {
+        language: "rust",
+        install: owned(&[
+            "curl https://sh.rustup.rs -sSf | sh -s -- -y",
+            ". $HOME/.cargo/env",
+            "rustup component add clippy rustfmt",
+        ]),
+        prelude: vec![". $HOME/.cargo/env"],
+        fetch: None,
+        input_paths: existing(
+            dir,
+            &[
+                "Cargo.lock",
+                "Cargo.toml",
+                "benches",
+                "build.rs",
+                "examples",
+                "src",
+                "tests",
+            ],
+        ),
+        build: owned(&["cargo build"]),
+        test: owned(&["cargo test"]),
+        lint: owned(&[
+            "cargo clippy --all-targets -- --deny warnings",
+            "cargo fmt --all -- --check",
+        ]),
+    }
+}
+
+// Describe a Node.js project. The tasks run the scripts in `package.json`, if there are any.
+fn node(dir: &Path) -> Project {
+    let package = fs::read_to_string(dir.join("package.json"))
+        .ok()
+        .and_then(|data| serde_json::from_str::<Value>(&data).ok())
+        .unwrap_or(Value::Null);
+    let script = |name: &str, command: &str| {
+        package
+            .get("scripts")
+            .and_then(|scripts| scripts.get(name))
+            .and_then(Value::as_str)
+            .filter(|script| !script.contains("no test specified")) // The `npm init` default
+            .map_or_else(Vec::new, |_| vec![command.to_owned()])
+    };
+    let lockfiles = existing(dir, &["package-lock.json", "yarn.lock"]);
+    let install_command = if lockfiles.is_empty() {
+        "npm install"
+    } else {
+        "npm ci"
+    };
+
+    Project {
+        language: "node",
+        install: owned(&[
+            "curl -sL https://deb.nodesource.com/setup_10.x | bash -",
+            "apt-get install --yes nodejs",
+        ]),
+        prelude: vec![],
+        fetch: Some((
+            sorted(
+                existing(dir, &["package.json"])
+                    .into_iter()
+                    .chain(lockfiles)
+                    .collect(),
+            ),
+            vec![install_command.to_owned()],
+        )),
+        input_paths: existing(
+            dir,
+            &["index.js", "lib", "src", "test", "tests", "tsconfig.json"],
+        ),
+        build: script("build", "npm run build"),
+        test: script("test", "npm test"),
+        lint: script("lint", "npm run lint"),
+    }
+}
+
+// Describe a Go project. The source is in the `.go` files at the top level and in the directories
+// which contain `.go` files.
+fn go(dir: &Path) -> Project {
+    Project {
+        language: "go",
+        install: owned(&[
+            "curl -LSfs https://dl.google.com/go/go1.12.9.linux-amd64.tar.gz | \
+             tar --extract --gzip --directory /usr/local",
+            "ln -s /usr/local/go/bin/go /usr/local/go/bin/gofmt /usr/local/bin",
+        ]),
+        prelude: vec![],
+        fetch: Some((
+            existing(dir, &["go.mod", "go.sum"]),
+            owned(&["go mod download"]),
+        )),
+        input_paths: entries(dir, |path| {
+            has_extension(path, "go") || contains_extension(path, "go")
+        }),
+        build: owned(&["go build ./..."]),
+        test: owned(&["go test ./..."]),
+        lint: owned(&["go vet ./...", "test -z \"$(gofmt -l .)\""]),
+    }
+}
+
+// Describe a Python project. The test and lint tasks use pytest and Flake8 if they're required.
+fn python(dir: &Path) -> Project {
+    let requirements = fs::read_to_string(dir.join("requirements.txt"))
+        .unwrap_or_default()
+        .to_lowercase();
+    let requires = |package: &str| {
+        requirements.lines().any(|line| {
+            line.trim()
+                .split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+                .next()
+                == Some(package)
+        })
+    };
+
+    Project {
+        language: "python",
+        install: owned(&["apt-get install --yes python3 python3-pip"]),
+        prelude: vec![],
+        fetch: Some((
+            owned(&["requirements.txt"]),
+            owned(&["pip3 install --requirement requirements.txt"]),
+        )),
+        input_paths: sorted(
+            existing(
+                dir,
+                &[
+                    "pyproject.toml",
+                    "setup.cfg",
+                    "setup.py",
+                    "src",
+                    "test",
+                    "tests",
+                ],
+            )
+            .into_iter()
+            .chain(entries(dir, |path| {
+                has_extension(path, "py") || path.join("__init__.py").is_file()
+            }))
+            .collect(),
+        ),
+        build: owned(&["python3 -m compileall -q ."]),
+        test: if requires("pytest") {
+            owned(&["python3 -m pytest"])
+        } else {
+            owned(&["python3 -m unittest discover"])
+        },
+        lint: if requires("flake8") {
+            owned(&["python3 -m flake8"])
+        } else {
+            vec![]
+        },
+    }
+}
+
+// Inspect a directory to find out what kinds of projects it contains.
+fn detect(dir: &Path) -> Vec<Project> {
+    let mut projects = vec![];
+    if dir.join("Cargo.toml").is_file() {
+        projects.push(rust(dir));
+    }
+    if dir.join("package.json").is_file() {
+        projects.push(node(dir));
+    }
+    if dir.join("go.mod").is_file() {
+        projects.push(go(dir));
+    }
+    if dir.join("requirements.txt").is_file() {
+        projects.push(python(dir));
+    }
+    projects
+}
+
+// Add `set -euo pipefail` to a multi-line command, like the commands in Toast's own toastfile.
+fn script(lines: Vec<String>) -> Vec<String> {
+    if lines.len() > 1 {
+        vec!["set -euo pipefail".to_owned()]
+            .into_iter()
+            .chain(lines)
+            .collect()
+    } else {
+        lines
+    }
+}
+
+// Compute the tasks for a directory, along with the default task. If there's more than one project,
+// the tasks for each get the language as a suffix, and the `build`, `test`, and `lint` tasks depend
+// on those.
+fn tasks(dir: &Path) -> (Vec<Task>, String) {
+    let projects = detect(dir);
+    let mut tasks = vec![Task {
+        name: "install_packages".to_owned(),
+        description: "Install system packages.".to_owned(),
+        dependencies: vec![],
+        cache: false,
+        input_paths: vec![],
+        command: script(vec![
+            "apt-get update".to_owned(),
+            format!("apt-get install --yes {}", PACKAGES.join(" ")),
+        ]),
+    }];
+
+    // If we don't recognize the project, leave a placeholder for the user to fill in.
+    if projects.is_empty() {
+        tasks.push(Task {
+            name: "build".to_owned(),
+            description: "Build the project.".to_owned(),
+            dependencies: vec!["install_packages".to_owned()],
+            cache: true,
+            input_paths: vec![],
+            command: vec!["echo 'Add the command to build the project here.'".to_owned()],
+        });
+        return (tasks, "build".to_owned());
+    }
+
+    let suffix = |project: &Project, name: &str| {
+        if projects.len() == 1 {
+            name.to_owned()
+        } else {
+            format!("{}_{}", name, project.language)
+        }
+    };
+    let mut aggregates = vec![("build", vec![]), ("test", vec![]), ("lint", vec![])];
+
+    for project in &projects {
+        let with_prelude = |lines: &[String]| {
+            script(
+                project
+                    .prelude
+                    .iter()
+                    .map(|&line| line.to_owned())
+                    .chain(lines.iter().cloned())
+                    .collect(),
+            )
+        };
+
+        // Install the toolchain and the dependencies of the project.
+        let install_name = format!("install_{}", project.language);
+        tasks.push(Task {
+            name: install_name.clone(),
+            description: format!("Install the {} toolchain.", title(project.language)),
+            dependencies: vec!["install_packages".to_owned()],
+            cache: true,
+            input_paths: vec![],
+            command: script(project.install.clone()),
+        });
+        let mut source_dependency = install_name.clone();
+        if let Some((input_paths, command)) = &project.fetch {
+            source_dependency = format!("{}_packages", install_name);
+            tasks.push(Task {
+                name: source_dependency.clone(),
+                description: format!("Install the {} packages.", title(project.language)),
+                dependencies: vec![install_name],
+                cache: true,
+                input_paths: input_paths.clone(),
+                command: with_prelude(command),
+            });
+        }
+
+        // Build the project, then test and lint what was built.
+        let build_name = suffix(project, "build");
+        tasks.push(Task {
+            name: build_name.clone(),
+            description: format!("Build the {} project.", title(project.language)),
+            dependencies: vec![source_dependency],
+            cache: true,
+            input_paths: project.input_paths.clone(),
+            command: with_prelude(&project.build),
+        });
+        aggregates[0].1.push(build_name.clone());
+        for (index, (name, description, command)) in [
+            ("test", "Run the tests for", &project.test),
+            ("lint", "Run the linters for", &project.lint),
+        ]
+        .iter()
+        .enumerate()
+        {
+            if !command.is_empty() {
+                let task_name = suffix(project, name);
+                tasks.push(Task {
+                    name: task_name.clone(),
+                    description: format!(
+                        "{} the {} project.",
+                        description,
+                        title(project.language),
+                    ),
+                    dependencies: vec![build_name.clone()],
+                    cache: true,
+                    input_paths: vec![],
+                    command: with_prelude(command),
+                });
+                aggregates[index + 1].1.push(task_name);
+            }
+        }
+    }
+
+    // With more than one project, add tasks which run the corresponding tasks of every project.
+    if projects.len() > 1 {
+        for (name, dependencies) in aggregates {
+            if !dependencies.is_empty() {
+                tasks.push(Task {
+                    name: name.to_owned(),
+                    description: format!("Run the {} tasks for every project.", name),
+                    dependencies,
+                    cache: true,
+                    input_paths: vec![],
+                    command: vec![],
+                });
+            }
+        }
+    }
+
+    // Add a task which does everything, like `build-test-lint` in Toast's own toastfile.
+    let steps = ["build", "test", "lint"]
+        .iter()
+        .filter(|&&name| tasks.iter().any(|task| task.name == name))
+        .map(|&name| name.to_owned())
+        .collect::<Vec<_>>();
+    if steps.len() == 1 {
+        return (tasks, "build".to_owned());
+    }
+    let default = steps.join("-");
+    tasks.push(Task {
+        name: default.clone(),
+        description: format!(
+            "B{} the {}.",
+            &format::series(&steps)[1..],
+            if projects.len() == 1 {
+                "project"
+            } else {
+                "projects"
+            },
+        ),
+        dependencies: steps,
+        cache: true,
+        input_paths: vec![],
+        command: vec![],
+    });
+
+    (tasks, default)
+}
+
+// The display name of a language
+fn title(language: &str) -> &str {
+    match language {
+        "rust" => "Rust",
+        "node" => "Node.js",
+        "go" => "Go",
+        "python" => "Python",
+        _ => language,
+    }
+}
+
+// Quote a YAML scalar unless it's obviously safe to leave it plain.
+fn scalar(value: &str) -> String {
+    if value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '/' || c == '-' || c == '_')
+    {
+        value.to_owned()
+    } else {
+        Value::String(value.to_owned()).to_string()
+    }
+}
+
+// Render a list as a YAML block sequence, sorted like the formatter does.
+fn render_list(output: &mut String, key: &str, items: &[String]) {
+    if !items.is_empty() {
+        output.push_str(&format!("    {}:\n", key));
+        for item in sorted(items.to_vec()) {
+            output.push_str(&format!("      - {}\n", scalar(&item)));
+        }
+    }
+}
+
+// Generate a starter toastfile for the project in a directory. The fields of each task are in the
+// order `toast fmt` uses.
+pub fn generate(dir: &Path) -> String {
+    let (tasks, default) = tasks(dir);
+
+    let mut output = format!("image: {}\ndefault: {}\ntasks:\n", IMAGE, default);
+    for (index, task) in tasks.iter().enumerate() {
+        if index > 0 {
+            output.push('\n');
+        }
+        output.push_str(&format!("  {}:\n", task.name));
+        output.push_str(&format!("    description: {}\n", task.description));
+        render_list(&mut output, "dependencies", &task.dependencies);
+        if !task.cache {
+            for line in UNCACHED_COMMENT {
+                output.push_str(&format!("    # {}\n", line));
+            }
+            output.push_str("    cache: false\n");
+        }
+        render_list(&mut output, "input_paths", &task.input_paths);
+        match task.command.as_slice() {
+            [] => {}
+            [line] => output.push_str(&format!("    command: {}\n", line)),
+            lines => {
+                output.push_str("    command: |\n");
+                for line in lines {
+                    output.push_str(&format!("      {}\n", line));
+                }
+            }
+        }
+    }
+
+    output
+}
+
+// Write a starter toastfile for the project in `dir` to `path`, unless there's a file there
+// already.
+pub fn init(dir: &Path, path: &Path) -> Result<(), Failure> {
+    if path.exists() {
+        return Err(Failure::User(
+            format!(
+                "{} already exists. Toast won't overwrite it.",
+                path.to_string_lossy().code_str(),
+            ),
+            None,
+        ));
+    }
+
+    fs::write(path, generate(dir)).map_err(failure::system(format!(
+        "Unable to write file {}.",
+        path.to_string_lossy().code_str(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        formatter::format,
+        lint::lint,
+        scaffold::{generate, init},
+        toastfile::parse,
+    };
+    use std::{fs, path::Path};
+    use tempfile::tempdir;
+
+    #[test]
+    fn generate_empty() {
+        let dir = tempdir().unwrap();
+        let toastfile = parse(&generate(dir.path())).unwrap();
+
+        assert_eq!(toastfile.default, Some("build".to_owned()));
+        assert!(toastfile.tasks.contains_key("install_packages"));
+        assert!(toastfile.tasks.contains_key("build"));
+    }
+
+    #[test]
+    fn generate_rust() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        let toastfile = parse(&generate(dir.path())).unwrap();
+
+        assert_eq!(toastfile.default, Some("build-test-lint".to_owned()));
+        assert_eq!(
+            toastfile.tasks["install_rust"].dependencies,
+            vec!["install_packages".to_owned()],
+        );
+        assert_eq!(
+            toastfile.tasks["build"].input_paths,
+            vec![
+                Path::new("Cargo.toml").to_owned(),
+                Path::new("src").to_owned()
+            ],
+        );
+        assert_eq!(
+            toastfile.tasks["lint"].dependencies,
+            vec!["build".to_owned()],
+        );
+    }
+
+    #[test]
+    fn generate_node_scripts() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{ "scripts": { "test": "echo \"Error: no test specified\" && exit 1", "lint": "eslint ." } }"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("package-lock.json"), "{}").unwrap();
+        let toastfile = parse(&generate(dir.path())).unwrap();
+
+        assert_eq!(
+            toastfile.tasks["install_node_packages"].command,
+            Some("npm ci".to_owned()),
+        );
+        assert!(!toastfile.tasks.contains_key("test"));
+        assert_eq!(
+            toastfile.tasks["lint"].command,
+            Some("npm run lint".to_owned())
+        );
+    }
+
+    #[test]
+    fn generate_several_projects() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("go.mod"), "module example.com/foo\n").unwrap();
+        fs::write(dir.path().join("main.go"), "package main\n").unwrap();
+        fs::write(dir.path().join("requirements.txt"), "pytest==5.1.1\n").unwrap();
+        let toastfile = parse(&generate(dir.path())).unwrap();
+
+        assert_eq!(
+            toastfile.tasks["test"].dependencies,
+            vec!["test_go".to_owned(), "test_python".to_owned()],
+        );
+        assert_eq!(
+            toastfile.tasks["test_python"].command,
+            Some("python3 -m pytest".to_owned()),
+        );
+        assert!(toastfile.tasks.contains_key("lint_go"));
+        assert!(!toastfile.tasks.contains_key("lint_python"));
+    }
+
+    #[test]
+    fn generate_formatted() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        let toastfile = generate(dir.path());
+
+        assert_eq!(format(&toastfile).unwrap(), toastfile);
+    }
+
+    #[test]
+    fn generate_lint_clean() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        let toastfile = parse(&generate(dir.path())).unwrap();
+
+        assert!(lint(&toastfile, dir.path()).0.is_empty());
+        assert!(!toastfile.tasks["install_packages"].cache);
+    }
+
+    #[test]
+    fn init_refuses_to_overwrite() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("toast.yml");
+        fs::write(&path, "image: encom:os-12\n").unwrap();
+
+        assert!(init(dir.path(), &path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "image: encom:os-12\n");
+    }
+}
@@ -84,9 +84,14 @@ mod tests {
     fn task_with_dependencies(dependencies: Vec<String>) -> Task {
         Task {
             description: None,
+            extends: None,
             dependencies,
             cache: true,
             environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
             input_paths: vec![],
             output_paths: vec![],
             mount_paths: vec![],
@@ -107,6 +112,11 @@ mod tests {
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks: HashMap::new(),
         };
 
@@ -124,6 +134,11 @@ mod tests {
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -149,6 +164,11 @@ mod tests {
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -178,6 +198,11 @@ mod tests {
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -197,6 +222,11 @@ mod tests {
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -227,12 +257,22 @@ mod tests {
         let toastfile1 = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks: tasks1,
         };
 
         let toastfile2 = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks: tasks2,
         };
 
@@ -252,6 +292,11 @@ mod tests {
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -284,12 +329,22 @@ mod tests {
         let toastfile1 = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks: tasks1,
         };
 
         let toastfile2 = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks: tasks2,
         };
 
@@ -309,6 +364,11 @@ mod tests {
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -0,0 +1,519 @@
+use crate::{config, toastfile};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+// The kinds of files which have a schema
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Toastfile,
+    Config,
+}
+
+// The names of the kinds, as accepted on the command line. Each one is recognized by `from_name`.
+// [tag:schema_kind_valid]
+pub const KIND_NAMES: &[&str] = &["toastfile", "config"];
+
+impl Kind {
+    // Look up a kind by its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "toastfile" => Some(Kind::Toastfile),
+            "config" => Some(Kind::Config),
+            _ => None,
+        }
+    }
+}
+
+// The descriptions of the top-level fields of a toastfile
+const TOASTFILE_FIELDS: &[(&str, &str)] = &[
+    ("image", "Docker image name with optional tag or digest"),
+    (
+        "default",
+        "Name of the default task to run, or null to run all tasks by default",
+    ),
+    ("include", "Other toastfiles to include tasks from"),
+    ("env_file", "Path to an environment file for all tasks"),
+    (
+        "environment",
+        "Map from environment variable to optional default for all tasks",
+    ),
+    ("defaults", "Default values for task fields"),
+    ("templates", "Map from template name to task template"),
+    ("tasks", "Map from task name to task"),
+];
+
+// The descriptions of the fields of a task, in the order the formatter puts them in
+pub const TASK_FIELDS: &[(&str, &str)] = &[
+    (
+        "extends",
+        "Name of a task or template to inherit fields from",
+    ),
+    ("description", "A description of the task for `toast list`"),
+    ("dependencies", "Names of dependencies"),
+    ("cache", "Whether a task can be cached"),
+    (
+        "environment",
+        "Map from environment variable to optional default",
+    ),
+    (
+        "cache_neutral_environment",
+        "Like `environment`, but doesn't affect caching",
+    ),
+    ("env_file", "Path to a file of environment variables"),
+    ("matrix", "Map from variable to values to run the task with"),
+    ("secrets", "Map from secret name to optional source"),
+    ("input_paths", "Paths to copy into the container"),
+    ("output_paths", "Paths to copy out of the container"),
+    ("mount_paths", "Paths to mount into the container"),
+    (
+        "mount_readonly",
+        "Whether to mount the `mount_paths` as readonly",
+    ),
+    ("ports", "Port mappings to publish"),
+    ("location", "Path in the container for running this task"),
+    (
+        "user",
+        "Name of the user in the container for running this task",
+    ),
+    ("command", "Shell command to run in the container"),
+];
+
+// The descriptions of the fields of a configuration file
+const CONFIG_FIELDS: &[(&str, &str)] = &[
+    ("docker_repo", "Docker repository"),
+    (
+        "read_local_cache",
+        "Whether Toast should read from local cache",
+    ),
+    (
+        "write_local_cache",
+        "Whether Toast should write to local cache",
+    ),
+    (
+        "read_remote_cache",
+        "Whether Toast should read from remote cache",
+    ),
+    (
+        "write_remote_cache",
+        "Whether Toast should write to remote cache",
+    ),
+    (
+        "redact_patterns",
+        "Regular expressions to mask in the output of tasks",
+    ),
+    (
+        "passthrough_environment",
+        "Host environment variables to forward to every task",
+    ),
+];
+
+// Produce the JSON Schema for a kind of file.
+pub fn schema(kind: Kind) -> Value {
+    match kind {
+        Kind::Toastfile => toastfile_schema(),
+        Kind::Config => config_schema(),
+    }
+}
+
+// The value of a string, number, or Boolean in YAML can be read as a string.
+fn scalar() -> Value {
+    json!({ "type": ["string", "number", "boolean"] })
+}
+
+// A map from names to values of the given schema
+fn map(values: Value) -> Value {
+    json!({ "type": "object", "additionalProperties": values })
+}
+
+// A list of values of the given schema
+fn list(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+// The given schema, or `null`
+fn nullable(schema: Value) -> Value {
+    json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+// A map from environment variable to optional default
+fn environment() -> Value {
+    map(json!({ "type": ["string", "number", "boolean", "null"] }))
+}
+
+// Serialize a value with its fields filled in by their defaults, so the schema uses the same
+// defaults as deserialization.
+fn defaults<T: Serialize>(value: &T) -> Map<String, Value> {
+    match serde_json::to_value(value) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(), // Structs always serialize to objects.
+    }
+}
+
+// Build the schema for an object from the schemas of its fields. The descriptions come from
+// `descriptions`, the defaults come from `defaults`, and the fields in `required` have no default.
+fn object(
+    descriptions: &[(&str, &str)],
+    mut properties: Map<String, Value>,
+    defaults: &Map<String, Value>,
+    required: &[&str],
+) -> Value {
+    for (field, description) in descriptions {
+        if let Some(Value::Object(property)) = properties.get_mut(*field) {
+            property.insert("description".to_owned(), json!(description));
+            if !required.contains(field) {
+                if let Some(default) = defaults.get(*field) {
+                    property.insert("default".to_owned(), default.clone());
+                }
+            }
+        }
+    }
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+// The schema for a task
+fn task_schema() -> Value {
+    let mut properties = Map::new();
+    properties.insert("extends".to_owned(), json!({ "type": ["string", "null"] }));
+    properties.insert(
+        "description".to_owned(),
+        json!({ "type": ["string", "null"] }),
+    );
+    properties.insert("dependencies".to_owned(), list(json!({ "type": "string" })));
+    properties.insert("cache".to_owned(), json!({ "type": "boolean" }));
+    properties.insert("environment".to_owned(), environment());
+    properties.insert("cache_neutral_environment".to_owned(), environment());
+    properties.insert("env_file".to_owned(), json!({ "type": ["string", "null"] }));
+    properties.insert("matrix".to_owned(), map(list(scalar())));
+    properties.insert(
+        "secrets".to_owned(),
+        map(nullable(json!({
+            "type": "object",
+            "properties": {
+                "env": {
+                    "type": ["string", "null"],
+                    "description": "Environment variable on the host to read the secret from",
+                },
+                "file": {
+                    "type": ["string", "null"],
+                    "description":
+                        "Path to a file on the host to read the secret from, relative to the \
+                         toastfile",
+                },
+            },
+            "additionalProperties": false,
+        }))),
+    );
+    properties.insert("input_paths".to_owned(), list(json!({ "type": "string" })));
+    properties.insert(
+        "output_paths".to_owned(),
+        list(json!({
+            "anyOf": [
+                { "type": "string" },
+                {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to copy out of the container",
+                        },
+                        "mirror": {
+                            "type": "boolean",
+                            "description":
+                                "Whether to delete files on the host which are not in the \
+                                 container",
+                            "default": false,
+                        },
+                    },
+                    "required": ["path"],
+                    "additionalProperties": false,
+                },
+            ],
+        })),
+    );
+    properties.insert("mount_paths".to_owned(), list(json!({ "type": "string" })));
+    properties.insert("mount_readonly".to_owned(), json!({ "type": "boolean" }));
+    properties.insert("ports".to_owned(), list(json!({ "type": "string" })));
+    properties.insert("location".to_owned(), json!({ "type": "string" }));
+    properties.insert("user".to_owned(), json!({ "type": "string" }));
+    properties.insert("command".to_owned(), json!({ "type": ["string", "null"] }));
+
+    // A task with every field missing has the default values.
+    let task: toastfile::Task = serde_yaml::from_str("{}").unwrap(); // Every field is optional.
+
+    object(TASK_FIELDS, properties, &defaults(&task), &[])
+}
+
+// The schema for a toastfile
+fn toastfile_schema() -> Value {
+    let task_reference = json!({ "$ref": "#/definitions/task" });
+
+    let mut properties = Map::new();
+    properties.insert("image".to_owned(), json!({ "type": "string" }));
+    properties.insert("default".to_owned(), json!({ "type": ["string", "null"] }));
+    properties.insert(
+        "include".to_owned(),
+        list(json!({
+            "anyOf": [
+                { "type": "string" },
+                {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description":
+                                "Path to another toastfile, relative to the including toastfile",
+                        },
+                        "namespace": {
+                            "type": ["string", "null"],
+                            "description": "Prefix for the names of the included tasks",
+                            "default": null,
+                        },
+                    },
+                    "required": ["path"],
+                    "additionalProperties": false,
+                },
+            ],
+        })),
+    );
+    properties.insert("env_file".to_owned(), json!({ "type": ["string", "null"] }));
+    properties.insert("environment".to_owned(), environment());
+    properties.insert("defaults".to_owned(), nullable(task_reference.clone()));
+    properties.insert("templates".to_owned(), map(task_reference.clone()));
+    properties.insert("tasks".to_owned(), map(task_reference));
+
+    // A toastfile with only the required fields has the default values for the rest.
+    let toastfile: toastfile::Toastfile = serde_yaml::from_str("{ image: '', tasks: {} }").unwrap(); // Only these are required.
+
+    let mut schema = object(
+        TOASTFILE_FIELDS,
+        properties,
+        &defaults(&toastfile),
+        &["image", "tasks"],
+    );
+    schema["$schema"] = json!("http://json-schema.org/draft-07/schema#");
+    schema["title"] = json!("Toastfile");
+    schema["definitions"] = json!({ "task": task_schema() });
+    schema
+}
+
+// The schema for a configuration file
+fn config_schema() -> Value {
+    let mut properties = Map::new();
+    properties.insert("docker_repo".to_owned(), json!({ "type": "string" }));
+    properties.insert("read_local_cache".to_owned(), json!({ "type": "boolean" }));
+    properties.insert("write_local_cache".to_owned(), json!({ "type": "boolean" }));
+    properties.insert("read_remote_cache".to_owned(), json!({ "type": "boolean" }));
+    properties.insert(
+        "write_remote_cache".to_owned(),
+        json!({ "type": "boolean" }),
+    );
+    properties.insert(
+        "redact_patterns".to_owned(),
+        list(json!({ "type": "string" })),
+    );
+    properties.insert(
+        "passthrough_environment".to_owned(),
+        list(json!({ "type": "string" })),
+    );
+
+    // The empty configuration has the default values.
+    let config = config::parse(config::EMPTY_CONFIG).unwrap(); // It's valid.
+
+    let mut schema = object(CONFIG_FIELDS, properties, &defaults(&config), &[]);
+    schema["$schema"] = json!("http://json-schema.org/draft-07/schema#");
+    schema["title"] = json!("Toast configuration");
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config,
+        schema::{
+            defaults, schema, task_schema, Kind, CONFIG_FIELDS, TASK_FIELDS, TOASTFILE_FIELDS,
+        },
+        toastfile,
+    };
+    use serde_json::{json, Value};
+    use std::{
+        collections::HashSet,
+        fs::{read_dir, read_to_string},
+        path::Path,
+    };
+
+    // Check a value against the subset of JSON Schema used by the schemas above. Errors are
+    // described by where in the value they occurred.
+    fn validate(root: &Value, schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/definitions/");
+            return validate(root, &root["definitions"][name], value, path);
+        }
+
+        if let Some(alternatives) = schema["anyOf"].as_array() {
+            return if alternatives
+                .iter()
+                .any(|alternative| validate(root, alternative, value, path).is_ok())
+            {
+                Ok(())
+            } else {
+                Err(format!("{} doesn't match any of the alternatives.", path))
+            };
+        }
+
+        let types = match &schema["type"] {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        let actual_type = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        if !types.is_empty() && !types.contains(&actual_type) {
+            return Err(format!("{} has the wrong type ({}).", path, actual_type));
+        }
+
+        if let Value::Array(items) = value {
+            for (i, item) in items.iter().enumerate() {
+                validate(root, &schema["items"], item, &format!("{}[{}]", path, i))?;
+            }
+        }
+
+        if let Value::Object(fields) = value {
+            for required in schema["required"].as_array().unwrap_or(&vec![]) {
+                if !fields.contains_key(required.as_str().unwrap()) {
+                    return Err(format!("{} is missing {}.", path, required));
+                }
+            }
+
+            for (key, field) in fields {
+                let field_path = format!("{}.{}", path, key);
+                match schema["properties"].get(key) {
+                    Some(property) => validate(root, property, field, &field_path)?,
+                    None => match &schema["additionalProperties"] {
+                        Value::Bool(false) => {
+                            return Err(format!("{} is not allowed.", field_path));
+                        }
+                        Value::Object(_) => {
+                            validate(root, &schema["additionalProperties"], field, &field_path)?;
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Validate a toastfile on disk against the schema.
+    fn validate_toastfile(path: &Path) {
+        let schema = schema(Kind::Toastfile);
+        let value: Value = serde_yaml::from_str(&read_to_string(path).unwrap()).unwrap();
+
+        if let Err(error) = validate(&schema, &schema, &value, "toastfile") {
+            panic!("{} doesn't match the schema: {}", path.display(), error);
+        }
+    }
+
+    // The names of the fields of a struct, according to how it serializes
+    fn field_names(fields: &serde_json::Map<String, Value>) -> HashSet<&str> {
+        fields.keys().map(AsRef::as_ref).collect()
+    }
+
+    // The names in a table of descriptions
+    fn described_names<'a>(descriptions: &[(&'a str, &str)]) -> HashSet<&'a str> {
+        descriptions.iter().map(|(name, _)| *name).collect()
+    }
+
+    #[test]
+    fn schema_task_fields() {
+        let task: toastfile::Task = serde_yaml::from_str("{}").unwrap();
+        let task_schema = task_schema();
+        let properties = task_schema["properties"].as_object().unwrap();
+
+        assert_eq!(field_names(&defaults(&task)), described_names(TASK_FIELDS));
+        assert_eq!(field_names(properties), described_names(TASK_FIELDS));
+    }
+
+    #[test]
+    fn schema_toastfile_fields() {
+        let toastfile: toastfile::Toastfile =
+            serde_yaml::from_str("{ image: '', tasks: {} }").unwrap();
+        let schema = schema(Kind::Toastfile);
+        let properties = schema["properties"].as_object().unwrap();
+
+        assert_eq!(
+            field_names(&defaults(&toastfile)),
+            described_names(TOASTFILE_FIELDS),
+        );
+        assert_eq!(field_names(properties), described_names(TOASTFILE_FIELDS));
+    }
+
+    #[test]
+    fn schema_config_fields() {
+        let config = config::parse(config::EMPTY_CONFIG).unwrap();
+        let schema = schema(Kind::Config);
+        let properties = schema["properties"].as_object().unwrap();
+
+        assert_eq!(
+            field_names(&defaults(&config)),
+            described_names(CONFIG_FIELDS),
+        );
+        assert_eq!(field_names(properties), described_names(CONFIG_FIELDS));
+    }
+
+    #[test]
+    fn schema_defaults() {
+        let schema = schema(Kind::Toastfile);
+        let task = &schema["definitions"]["task"]["properties"];
+
+        assert_eq!(task["cache"]["default"], json!(true));
+        assert_eq!(task["location"]["default"], json!("/scratch"));
+        assert_eq!(task["user"]["default"], json!("root"));
+        assert_eq!(schema["properties"]["image"].get("default"), None);
+    }
+
+    #[test]
+    fn schema_rejects_unknown_fields() {
+        let schema = schema(Kind::Toastfile);
+        let value = json!({ "image": "encom:os-12", "tasks": { "foo": { "comand": "ls" } } });
+
+        assert!(validate(&schema, &schema, &value, "toastfile").is_err());
+    }
+
+    #[test]
+    fn schema_validates_repository_toastfile() {
+        validate_toastfile(&Path::new(env!("CARGO_MANIFEST_DIR")).join("toast.yml"));
+    }
+
+    #[test]
+    fn schema_validates_integration_test_toastfiles() {
+        let integration_tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("integration-tests");
+
+        for entry in read_dir(integration_tests).unwrap() {
+            let path = entry.unwrap().path().join("toast.yml");
+            if path.is_file() {
+                validate_toastfile(&path);
+            }
+        }
+    }
+
+    #[test]
+    fn schema_validates_config() {
+        let schema = schema(Kind::Config);
+        let value = json!({ "docker_repo": "toast", "redact_patterns": ["ghp_.*"] });
+
+        assert!(validate(&schema, &schema, &value, "config").is_ok());
+    }
+}
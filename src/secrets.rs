@@ -0,0 +1,361 @@
+use crate::{
+    failure,
+    failure::Failure,
+    format,
+    format::CodeStr,
+    toastfile::{Secret, Task},
+};
+use std::{
+    collections::HashMap,
+    env,
+    fs::{create_dir, read_to_string, set_permissions, write, Permissions},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+use tempfile::{tempdir, TempDir};
+
+// The path in the container where secrets are made available as files
+pub const SECRETS_LOCATION: &str = "/run/secrets";
+
+// The path in the container of a script which sets the environment variables that aren't part of
+// the cache key [ref:unkeyed_environment_not_committed]
+pub const ENVIRONMENT_FILE_LOCATION: &str = "/run/toast-environment";
+
+// A directory on the host containing the secrets for a task. The directory is deleted when this
+// value is dropped.
+pub struct SecretsDir {
+    _parent: TempDir,
+    pub path: PathBuf,
+}
+
+// A file on the host containing a script which sets some environment variables. The file is
+// deleted when this value is dropped.
+pub struct EnvironmentFile {
+    _parent: TempDir,
+    pub path: PathBuf,
+}
+
+// Read the values of the secrets for a task from the host. Relative file paths are relative to
+// `source_dir`.
+pub fn fetch(task: &Task, source_dir: &Path) -> Result<HashMap<String, String>, Failure> {
+    let mut violations = vec![];
+    let mut unreadable = vec![];
+    let mut result = HashMap::new();
+
+    for (name, source) in &task.secrets {
+        match source {
+            Some(Secret {
+                file: Some(file), ..
+            }) => {
+                let path = source_dir.join(file);
+                match read_to_string(&path) {
+                    Ok(value) => {
+                        result.insert(name.clone(), value);
+                    }
+                    Err(error) => unreadable.push(format!(
+                        "Unable to read file {} for secret {}. Reason: {}",
+                        path.to_string_lossy().code_str(),
+                        name.code_str(),
+                        error,
+                    )),
+                }
+            }
+            Some(Secret { env: Some(var), .. }) => {
+                if let Ok(value) = env::var(var) {
+                    result.insert(name.clone(), value);
+                } else {
+                    violations.push(var.clone());
+                }
+            }
+            Some(Secret {
+                env: None,
+                file: None,
+            })
+            | None => {
+                if let Ok(value) = env::var(name) {
+                    result.insert(name.clone(), value);
+                } else {
+                    violations.push(name.clone());
+                }
+            }
+        }
+    }
+
+    // Report every missing or unreadable secret at once, rather than one at a time.
+    let mut problems = vec![];
+    if !violations.is_empty() {
+        violations.sort();
+        problems.push(format!(
+            "The following secrets are missing from the environment: {}.",
+            format::series(
+                violations
+                    .iter()
+                    .map(|var| format!("{}", var.code_str()))
+                    .collect::<Vec<_>>()
+                    .as_ref()
+            ),
+        ));
+    }
+    unreadable.sort();
+    problems.extend(unreadable);
+
+    if problems.is_empty() {
+        Ok(result)
+    } else {
+        Err(Failure::User(problems.join("\n"), None))
+    }
+}
+
+// Write secrets to a new directory on the host, one file per secret, so the directory can be
+// mounted into a container at `SECRETS_LOCATION`. The directory is placed inside a private
+// temporary directory, so other users on the host can't read it. Within the container, the files
+// are readable by any user, since the task might not run as root.
+pub fn write_dir(secrets: &HashMap<String, String>) -> Result<SecretsDir, Failure> {
+    // Create the directories.
+    let parent = tempdir().map_err(failure::system("Unable to create temporary directory."))?;
+    let path = parent.path().join("secrets");
+    create_dir(&path).map_err(failure::system(format!(
+        "Unable to create directory {}.",
+        path.to_string_lossy().code_str(),
+    )))?;
+    set_permissions(&path, Permissions::from_mode(0o755)).map_err(failure::system(format!(
+        "Unable to set permissions for directory {}.",
+        path.to_string_lossy().code_str(),
+    )))?;
+
+    // Write the secrets. Secret names are valid file names [ref:secret_name_file_name].
+    for (name, value) in secrets {
+        let file = path.join(name);
+        write(&file, value).map_err(failure::system(format!(
+            "Unable to write file {}.",
+            file.to_string_lossy().code_str(),
+        )))?;
+        set_permissions(&file, Permissions::from_mode(0o444)).map_err(failure::system(format!(
+            "Unable to set permissions for file {}.",
+            file.to_string_lossy().code_str(),
+        )))?;
+    }
+
+    Ok(SecretsDir {
+        _parent: parent,
+        path,
+    })
+}
+
+// Render a shell script which exports some environment variables. The variables are sorted so the
+// script is deterministic.
+pub fn environment_script(environment: &HashMap<String, String>) -> String {
+    let mut variables = environment.keys().collect::<Vec<_>>();
+    variables.sort();
+
+    variables
+        .into_iter()
+        .map(|variable| {
+            format!(
+                "export {}='{}'\n",
+                variable,
+                environment[variable].replace('\'', "'\\''"),
+            )
+        })
+        .collect()
+}
+
+// Write a script which exports some environment variables to a new file on the host, so the file
+// can be mounted into a container at `ENVIRONMENT_FILE_LOCATION`. Like the secrets directory, the
+// file is placed inside a private temporary directory.
+pub fn write_environment_file(
+    environment: &HashMap<String, String>,
+) -> Result<EnvironmentFile, Failure> {
+    let parent = tempdir().map_err(failure::system("Unable to create temporary directory."))?;
+    let path = parent.path().join("environment");
+    write(&path, environment_script(environment)).map_err(failure::system(format!(
+        "Unable to write file {}.",
+        path.to_string_lossy().code_str(),
+    )))?;
+    set_permissions(&path, Permissions::from_mode(0o444)).map_err(failure::system(format!(
+        "Unable to set permissions for file {}.",
+        path.to_string_lossy().code_str(),
+    )))?;
+
+    Ok(EnvironmentFile {
+        _parent: parent,
+        path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        secrets::{environment_script, fetch, write_dir, write_environment_file},
+        toastfile::{Secret, Task, DEFAULT_LOCATION, DEFAULT_USER},
+    };
+    use std::{
+        collections::HashMap,
+        env,
+        fs::{read_to_string, write},
+        path::Path,
+    };
+    use tempfile::tempdir;
+
+    fn task_with_secrets(secrets: HashMap<String, Option<Secret>>) -> Task {
+        Task {
+            description: None,
+            extends: None,
+            dependencies: vec![],
+            cache: true,
+            environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets,
+            input_paths: vec![],
+            output_paths: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: Path::new(DEFAULT_LOCATION).to_owned(),
+            user: DEFAULT_USER.to_owned(),
+            command: None,
+        }
+    }
+
+    #[test]
+    fn fetch_env_same_name() {
+        // NOTE: We add an index to the test secret ("secret1", "secret2", ...) to avoid having
+        // parallel tests clobbering environment variables used by other threads.
+        let mut secrets = HashMap::new();
+        secrets.insert("secret1".to_owned(), None);
+
+        let mut expected = HashMap::new();
+        expected.insert("secret1".to_owned(), "foo".to_owned());
+
+        env::set_var("secret1", "foo");
+        assert_eq!(
+            fetch(&task_with_secrets(secrets), Path::new(".")).unwrap(),
+            expected,
+        );
+    }
+
+    #[test]
+    fn fetch_env_different_name() {
+        let mut secrets = HashMap::new();
+        secrets.insert(
+            "foo".to_owned(),
+            Some(Secret {
+                env: Some("secret2".to_owned()),
+                file: None,
+            }),
+        );
+
+        let mut expected = HashMap::new();
+        expected.insert("foo".to_owned(), "bar".to_owned());
+
+        env::set_var("secret2", "bar");
+        assert_eq!(
+            fetch(&task_with_secrets(secrets), Path::new(".")).unwrap(),
+            expected,
+        );
+    }
+
+    #[test]
+    fn fetch_env_missing() {
+        let mut secrets = HashMap::new();
+        secrets.insert("secret3".to_owned(), None);
+
+        env::remove_var("secret3");
+        let result = fetch(&task_with_secrets(secrets), Path::new("."));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("secret3"));
+    }
+
+    #[test]
+    fn fetch_file() {
+        let dir = tempdir().unwrap();
+        write(dir.path().join("bar"), "baz").unwrap();
+
+        let mut secrets = HashMap::new();
+        secrets.insert(
+            "foo".to_owned(),
+            Some(Secret {
+                env: None,
+                file: Some(Path::new("bar").to_owned()),
+            }),
+        );
+
+        let mut expected = HashMap::new();
+        expected.insert("foo".to_owned(), "baz".to_owned());
+
+        assert_eq!(
+            fetch(&task_with_secrets(secrets), dir.path()).unwrap(),
+            expected,
+        );
+    }
+
+    #[test]
+    fn fetch_all_missing() {
+        let dir = tempdir().unwrap();
+
+        let mut secrets = HashMap::new();
+        secrets.insert("secret4".to_owned(), None);
+        secrets.insert(
+            "foo".to_owned(),
+            Some(Secret {
+                env: None,
+                file: Some(Path::new("bar").to_owned()),
+            }),
+        );
+        secrets.insert(
+            "baz".to_owned(),
+            Some(Secret {
+                env: None,
+                file: Some(Path::new("qux").to_owned()),
+            }),
+        );
+
+        env::remove_var("secret4");
+        let message = fetch(&task_with_secrets(secrets), dir.path())
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("secret4"));
+        assert!(message.contains("bar"));
+        assert!(message.contains("qux"));
+    }
+
+    #[test]
+    fn write_dir_contents() {
+        let mut secrets = HashMap::new();
+        secrets.insert("foo".to_owned(), "bar".to_owned());
+
+        let secrets_dir = write_dir(&secrets).unwrap();
+        let path = secrets_dir.path.clone();
+        assert_eq!(read_to_string(path.join("foo")).unwrap(), "bar");
+
+        drop(secrets_dir);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn environment_script_quoting() {
+        let mut environment = HashMap::new();
+        environment.insert("FOO".to_owned(), "it's $HOME".to_owned());
+        environment.insert("BAR".to_owned(), "baz".to_owned());
+
+        assert_eq!(
+            environment_script(&environment),
+            "export BAR='baz'\nexport FOO='it'\\''s $HOME'\n",
+        );
+    }
+
+    #[test]
+    fn write_environment_file_contents() {
+        let mut environment = HashMap::new();
+        environment.insert("FOO".to_owned(), "bar".to_owned());
+
+        let environment_file = write_environment_file(&environment).unwrap();
+        let path = environment_file.path.clone();
+        assert_eq!(read_to_string(&path).unwrap(), "export FOO='bar'\n");
+
+        drop(environment_file);
+        assert!(!path.exists());
+    }
+}
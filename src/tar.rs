@@ -1,17 +1,30 @@
-use crate::{cache, failure, failure::Failure, format::CodeStr, spinner::spin};
+use crate::{
+    cache, failure, failure::Failure, format::CodeStr, hash_cache::HashCache, spinner::spin,
+};
+use crossbeam::{channel::unbounded, scope};
 use std::{
-    fs::File,
-    io::{empty, Read, Seek, SeekFrom, Write},
+    collections::HashSet,
+    fs::{
+        create_dir_all, hard_link, remove_dir, remove_dir_all, remove_file, rename,
+        set_permissions, symlink_metadata, File, Metadata, Permissions,
+    },
+    io,
+    io::{empty, Read, Write},
     os::unix::fs::PermissionsExt,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
 };
-use tar::{Builder, EntryType, Header};
+use tar::{Archive, Builder, EntryType, Header};
+use tempfile::Builder as TempBuilder;
 use walkdir::WalkDir;
 
+// The maximum number of threads for reading input files. Reading is mostly limited by the disk, so
+// this doesn't need to match the number of CPUs.
+const HASH_WORKERS: usize = 8;
+
 // Add a file or directory to a tar archive.
 pub fn append<R: Read, W: Write>(
     builder: &mut Builder<W>,
@@ -51,18 +64,33 @@ pub fn append<R: Read, W: Write>(
     Ok(())
 }
 
-// Construct a tar archive and return a hash of its contents.
-pub fn create<W: Write>(
-    spinner_message: &str,
-    writer: W,
+// A file or directory found by traversing the input paths
+pub struct InputEntry {
+    // The path relative to the source directory
+    pub relative_path: PathBuf,
+
+    // The canonical path on the host
+    pub absolute_path: PathBuf,
+
+    // The filesystem metadata for the entry
+    pub metadata: Metadata,
+}
+
+impl InputEntry {
+    // Determine whether the entry is a file with the executable bit set.
+    pub fn executable(&self) -> bool {
+        let mode = self.metadata.permissions().mode();
+        mode & 0o1 > 0 || mode & 0o10 > 0 || mode & 0o100 > 0
+    }
+}
+
+// Traverse the input paths and return the files and directories found. Note that Toast ignores
+// symbolic links. [ref:symlinks]
+pub fn traverse(
     input_paths: &[PathBuf],
     source_dir: &Path,
-    destination_dir: &Path,
     interrupted: &Arc<AtomicBool>,
-) -> Result<(W, String), Failure> {
-    // Render a spinner animation in the terminal.
-    let _guard = spin(spinner_message);
-
+) -> Result<Vec<InputEntry>, Failure> {
     // Canonicalize the source directory such that other paths can be relativized with respect to
     // it.
     let source_dir = source_dir.canonicalize().map_err(failure::system(format!(
@@ -70,25 +98,10 @@ pub fn create<W: Write>(
         source_dir.to_string_lossy().code_str(),
     )))?;
 
-    // This vector will store all the hashes of the contents and metadata of all the files in the
-    // archive. In the end, we will sort this vector and then take the hash of the whole thing.
-    let mut file_hashes = vec![];
-
-    // This builder will be responsible for writing to the tar file.
-    let mut builder = Builder::new(writer);
-    builder.follow_symlinks(false); // [tag:symlinks]
-
-    // Add `destination_dir` to the archive.
-    append(
-        &mut builder,
-        &destination_dir,
-        empty(),
-        0,
-        EntryType::Directory,
-        true,
-    )?;
+    // This vector will accumulate the entries.
+    let mut entries = vec![];
 
-    // Add each path to the archive.
+    // Traverse each path.
     for relative_input_path in input_paths {
         // Compute the absolute input path.
         let absolute_input_path = source_dir.join(relative_input_path);
@@ -106,24 +119,28 @@ pub fn create<W: Write>(
                 &absolute_input_path.to_string_lossy().code_str(),
             )))?;
 
+            // Skip anything that isn't a file or a directory.
+            if !entry.file_type().is_file() && !entry.file_type().is_dir() {
+                continue;
+            }
+
             // Fetch the metadata for this entry.
-            let entry_metadata = entry.metadata().map_err(failure::system(format!(
+            let metadata = entry.metadata().map_err(failure::system(format!(
                 "Unable to fetch filesystem metadata for {}.",
                 &absolute_input_path.to_string_lossy().code_str(),
             )))?;
 
             // Fetch the host path.
-            let absolute_host_path =
-                entry
-                    .path()
-                    .canonicalize()
-                    .map_err(failure::system(format!(
-                        "Unable to canonicalize path {}.",
-                        &entry.path().to_string_lossy().code_str(),
-                    )))?;
+            let absolute_path = entry
+                .path()
+                .canonicalize()
+                .map_err(failure::system(format!(
+                    "Unable to canonicalize path {}.",
+                    &entry.path().to_string_lossy().code_str(),
+                )))?;
 
             // Relativize the host path.
-            let relative_path = absolute_host_path
+            let relative_path = absolute_path
                 .strip_prefix(&source_dir)
                 .map_err(failure::system(format!(
                     "Unable to relativize path {} with respect to {}.",
@@ -132,71 +149,913 @@ pub fn create<W: Write>(
                 )))?
                 .to_owned();
 
-            // Check the type of the entry. Note that Toast ignores symbolic links. [ref:symlinks]
-            if entry.file_type().is_file() {
-                // Determine if the file has the executable bit set.
-                let mode = entry_metadata.permissions().mode();
-                let executable = mode & 0o1 > 0 || mode & 0o10 > 0 || mode & 0o100 > 0;
-
-                // It's a file. Open it so we can compute the hash of its contents.
-                let mut file =
-                    File::open(&absolute_host_path).map_err(failure::system(format!(
-                        "Unable to open file {}.",
-                        &absolute_host_path.to_string_lossy().code_str(),
-                    )))?;
+            entries.push(InputEntry {
+                relative_path,
+                absolute_path,
+                metadata,
+            });
+        }
+    }
 
-                // Compute the hash of the file contents and metadata.
-                file_hashes.push(cache::extend(
-                    &cache::extend(
-                        &cache::hash_str(&relative_path.to_string_lossy()),
-                        &cache::hash_read(&mut file)?,
-                    ),
-                    if executable { "+x" } else { "-x" },
-                ));
+    Ok(entries)
+}
 
-                // Jump back to the beginning of the file so the tar builder can read it.
-                file.seek(SeekFrom::Start(0))
-                    .map_err(failure::system(format!(
-                        "Unable to seek file {}.",
-                        &absolute_host_path.to_string_lossy().code_str(),
-                    )))?;
+// Compute a hash of the contents and metadata of the input paths without building an archive. Files
+// which haven't changed according to the hash cache are not read.
+pub fn hash(
+    spinner_message: &str,
+    input_paths: &[PathBuf],
+    source_dir: &Path,
+    hash_cache: &mut HashCache,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<String, Failure> {
+    // Render a spinner animation in the terminal.
+    let _guard = spin(spinner_message);
+
+    // This vector will store all the hashes of the contents and metadata of all the files. In the
+    // end, we will sort this vector and then take the hash of the whole thing.
+    let mut file_hashes = vec![];
+
+    // Traverse the input paths.
+    let entries = traverse(input_paths, source_dir, interrupted)?;
 
-                // Add the file to the archive and return.
-                append(
-                    &mut builder,
-                    &destination_dir.join(&relative_path),
-                    file,
-                    entry_metadata.len(),
-                    EntryType::Regular,
-                    executable,
-                )?;
-            } else if entry.file_type().is_dir() {
-                // It's a directory. Only its name is relevant for the cache key.
-                file_hashes.push(cache::hash_str(&relative_path.to_string_lossy()));
-
-                // Add the directory to the archive.
-                append(
-                    &mut builder,
-                    &destination_dir.join(&relative_path),
-                    empty(),
-                    0,
-                    EntryType::Directory,
-                    true,
-                )?;
+    // This vector will store the files which need to be read because the hash cache doesn't know
+    // their contents.
+    let mut pending = vec![];
+
+    // Hash each entry, or defer it if it needs to be read.
+    for entry in &entries {
+        // If the user wants to stop the operation, quit now.
+        if interrupted.load(Ordering::SeqCst) {
+            return Err(Failure::Interrupted);
+        }
+
+        // Check the type of the entry.
+        if entry.metadata.is_file() {
+            // It's a file. Check if the hash cache already knows its contents.
+            if let Some(contents_hash) = hash_cache.get(&entry.absolute_path, &entry.metadata) {
+                file_hashes.push(file_hash(entry, &contents_hash));
+            } else {
+                pending.push(entry);
             }
+        } else {
+            // It's a directory. Only its name is relevant for the cache key.
+            file_hashes.push(cache::hash_str(&entry.relative_path.to_string_lossy()));
         }
     }
 
+    // Read the remaining files in parallel.
+    for (entry, contents_hash) in pending.iter().zip(hash_files(&pending, interrupted)?) {
+        hash_cache.insert(&entry.absolute_path, &entry.metadata, &contents_hash);
+        file_hashes.push(file_hash(entry, &contents_hash));
+    }
+
     // Sort the file hashes to ensure the directory traversal order doesn't matter.
     file_hashes.sort();
 
-    // Return the tar file and the hash of its contents.
-    Ok((
+    // Return the hash of the contents.
+    Ok(file_hashes
+        .iter()
+        .fold(cache::hash_str(""), |acc, x| cache::extend(&acc, x)))
+}
+
+// Compute the hash of the contents and metadata of a file, given the hash of its contents.
+fn file_hash(entry: &InputEntry, contents_hash: &str) -> String {
+    cache::extend(
+        &cache::extend(
+            &cache::hash_str(&entry.relative_path.to_string_lossy()),
+            contents_hash,
+        ),
+        if entry.executable() { "+x" } else { "-x" },
+    )
+}
+
+// Compute the hashes of the contents of some files using a pool of worker threads. The hashes are
+// returned in the same order as the files.
+fn hash_files(
+    files: &[&InputEntry],
+    interrupted: &Arc<AtomicBool>,
+) -> Result<Vec<String>, Failure> {
+    // Don't start more workers than there are files.
+    let workers = HASH_WORKERS.min(files.len());
+
+    // Fill a queue with the indices of the files to be hashed. The `unwrap` is safe since the
+    // receiver hasn't been dropped.
+    let (work_sender, work_receiver) = unbounded::<usize>();
+    for index in 0..files.len() {
+        work_sender.send(index).unwrap();
+    }
+    drop(work_sender);
+
+    // The workers send their results back on this channel. `Failure` can't be sent between threads,
+    // so errors are sent as messages.
+    let (result_sender, result_receiver) = unbounded::<(usize, Result<String, String>)>();
+
+    // This will be set to `true` if any worker fails, so the others can stop early.
+    let failed = AtomicBool::new(false);
+
+    // Start the workers and wait for them to finish.
+    scope(|scope| {
+        for _ in 0..workers {
+            let work_receiver = work_receiver.clone();
+            let result_sender = result_sender.clone();
+            let failed = &failed;
+
+            scope.spawn(move |_| {
+                for index in work_receiver.iter() {
+                    // If the user wants to stop the operation or another worker failed, quit now.
+                    if interrupted.load(Ordering::SeqCst) || failed.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    // Hash the file.
+                    let result = hash_file(&files[index].absolute_path, interrupted).map_err(|e| {
+                        failed.store(true, Ordering::SeqCst);
+                        e.to_string()
+                    });
+
+                    // Report the result. The `unwrap` is safe since the receiver hasn't been
+                    // dropped.
+                    result_sender.send((index, result)).unwrap();
+                }
+            });
+        }
+    })
+    .map_err(|_| Failure::System("A thread for hashing files panicked.".to_owned(), None))?;
+    drop(result_sender);
+
+    // If the user wants to stop the operation, quit now.
+    if interrupted.load(Ordering::SeqCst) {
+        return Err(Failure::Interrupted);
+    }
+
+    // Collect the results.
+    let mut hashes = vec![String::new(); files.len()];
+    for (index, result) in result_receiver.iter() {
+        hashes[index] = result.map_err(|e| Failure::System(e, None))?;
+    }
+
+    Ok(hashes)
+}
+
+// Compute the hash of the contents of a file. This stops early if the user interrupts the program.
+fn hash_file(path: &Path, interrupted: &Arc<AtomicBool>) -> Result<String, Failure> {
+    // Open the file.
+    let file = File::open(path).map_err(failure::system(format!(
+        "Unable to open file {}.",
+        path.to_string_lossy().code_str(),
+    )))?;
+
+    // Hash its contents.
+    cache::hash_read(&mut InterruptibleReader { file, interrupted }).map_err(|e| {
+        if interrupted.load(Ordering::SeqCst) {
+            Failure::Interrupted
+        } else {
+            e
+        }
+    })
+}
+
+// A reader which fails as soon as the user interrupts the program
+struct InterruptibleReader<'a> {
+    file: File,
+    interrupted: &'a Arc<AtomicBool>,
+}
+
+impl<'a> Read for InterruptibleReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Note that an error of kind `ErrorKind::Interrupted` would cause `io::copy` to retry, so we
+        // use a generic error instead. It's never shown, since `hash_file` reports the interruption.
+        if self.interrupted.load(Ordering::SeqCst) {
+            Err(io::Error::from(io::ErrorKind::Other))
+        } else {
+            self.file.read(buf)
+        }
+    }
+}
+
+// Write a tar archive of the input paths, placed in the destination directory, to a writer. This
+// streams the archive, so the input files are never stored on disk twice.
+pub fn create<W: Write>(
+    writer: W,
+    input_paths: &[PathBuf],
+    source_dir: &Path,
+    destination_dir: &Path,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<W, Failure> {
+    // This builder will be responsible for writing to the tar archive.
+    let mut builder = Builder::new(writer);
+    builder.follow_symlinks(false); // [tag:symlinks]
+
+    // Add `destination_dir` to the archive.
+    append(
+        &mut builder,
+        destination_dir,
+        empty(),
+        0,
+        EntryType::Directory,
+        true,
+    )?;
+
+    // Add each entry to the archive.
+    for entry in traverse(input_paths, source_dir, interrupted)? {
+        // If the user wants to stop the operation, quit now.
+        if interrupted.load(Ordering::SeqCst) {
+            return Err(Failure::Interrupted);
+        }
+
+        // Check the type of the entry.
+        if entry.metadata.is_file() {
+            // It's a file. Open it so the tar builder can read it.
+            let file = File::open(&entry.absolute_path).map_err(failure::system(format!(
+                "Unable to open file {}.",
+                &entry.absolute_path.to_string_lossy().code_str(),
+            )))?;
+
+            // Add the file to the archive.
+            append(
+                &mut builder,
+                &destination_dir.join(&entry.relative_path),
+                file,
+                entry.metadata.len(),
+                EntryType::Regular,
+                entry.executable(),
+            )?;
+        } else {
+            // It's a directory. Add it to the archive.
+            append(
+                &mut builder,
+                &destination_dir.join(&entry.relative_path),
+                empty(),
+                0,
+                EntryType::Directory,
+                true,
+            )?;
+        }
+    }
+
+    // Finish the archive and return the writer.
+    builder
+        .into_inner()
+        .map_err(failure::system("Error writing tar archive."))
+}
+
+// Extract a tar archive produced by `docker container cp` to a destination path on the host. The
+// archive has a single top-level entry (the file or directory being copied), which is placed at the
+// destination. Symbolic links, permissions, and the modification times of files are preserved.
+// Each file is unpacked next to its final location and then renamed into place, so existing
+// outputs are replaced atomically even if the system temporary directory is on a different
+// filesystem. Nothing is written through a symbolic link under the destination. If `mirror` is
+// `true`, anything under the destination which isn't in the archive is deleted.
+pub fn extract<R: Read>(
+    reader: R,
+    destination: &Path,
+    mirror: bool,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<(), Failure> {
+    // This reader will be responsible for reading from the tar stream.
+    let mut archive = Archive::new(reader);
+
+    // This set will store the host paths of all the entries in the archive.
+    let mut extracted = HashSet::new();
+
+    // Directory permissions are applied at the end, in case some directory is read-only.
+    let mut directory_modes = vec![];
+
+    // Extract each entry in the archive.
+    for entry in archive
+        .entries()
+        .map_err(failure::system("Unable to read tar archive."))?
+    {
+        // If the user wants to stop the operation, quit now.
+        if interrupted.load(Ordering::SeqCst) {
+            return Err(Failure::Interrupted);
+        }
+
+        // Unwrap the entry.
+        let mut entry = entry.map_err(failure::system("Unable to read tar archive."))?;
+
+        // Figure out where this entry should go.
+        let entry_path = entry
+            .path()
+            .map_err(failure::system("Unable to read tar archive."))?
+            .to_path_buf();
+        let target = map_path(&entry_path, destination)?;
+        check_parents(&target, destination, &entry_path)?;
+        extracted.insert(target.clone());
+
+        // Check the type of the entry.
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            // It's a directory. If something other than a directory is in the way, remove it.
+            if symlink_metadata(&target)
+                .map(|metadata| !metadata.is_dir())
+                .unwrap_or(false)
+            {
+                remove_file(&target).map_err(failure::system(format!(
+                    "Unable to remove file {}.",
+                    target.to_string_lossy().code_str(),
+                )))?;
+            }
+
+            // Create the directory.
+            create_dir_all(&target).map_err(failure::system(format!(
+                "Unable to create directory {}.",
+                target.to_string_lossy().code_str(),
+            )))?;
+
+            // Remember the permissions for later.
+            if let Ok(mode) = entry.header().mode() {
+                directory_modes.push((target, mode));
+            }
+        } else {
+            // Make sure the parent directory exists. The `unwrap` is safe because `target` is never
+            // the root of the filesystem.
+            let parent = target.parent().unwrap();
+            create_dir_all(parent).map_err(failure::system(format!(
+                "Unable to create directory {}.",
+                parent.to_string_lossy().code_str(),
+            )))?;
+
+            // Unpack the entry into a temporary directory next to the target, so the final
+            // `rename` doesn't cross filesystems. The temporary directory is deleted when it goes
+            // out of scope.
+            let temp_dir = TempBuilder::new()
+                .prefix(".toast-")
+                .tempdir_in(parent)
+                .map_err(failure::system(format!(
+                    "Unable to create temporary directory in {}.",
+                    parent.to_string_lossy().code_str(),
+                )))?;
+            let intermediate = temp_dir.path().join("data");
+
+            // Hard links refer to other paths in the archive, so they need to be remapped.
+            // Everything else can be unpacked directly.
+            if entry_type.is_hard_link() {
+                let link_name = entry
+                    .link_name()
+                    .map_err(failure::system("Unable to read tar archive."))?
+                    .ok_or_else(|| {
+                        Failure::System(
+                            format!(
+                                "Hard link {} has no target.",
+                                entry_path.to_string_lossy().code_str()
+                            ),
+                            None,
+                        )
+                    })?
+                    .to_path_buf();
+                let link_target = map_path(&link_name, destination)?;
+                check_parents(&link_target, destination, &link_name)?;
+                hard_link(&link_target, &intermediate).map_err(failure::system(format!(
+                    "Unable to link {} to {}.",
+                    intermediate.to_string_lossy().code_str(),
+                    link_target.to_string_lossy().code_str(),
+                )))?;
+            } else {
+                entry
+                    .unpack(&intermediate)
+                    .map_err(failure::system(format!(
+                        "Unable to extract {}.",
+                        entry_path.to_string_lossy().code_str(),
+                    )))?;
+            }
+
+            // If a directory is in the way, remove it. Files and symbolic links are replaced
+            // atomically by the `rename` below.
+            if symlink_metadata(&target)
+                .map(|metadata| metadata.is_dir())
+                .unwrap_or(false)
+            {
+                remove_dir_all(&target).map_err(failure::system(format!(
+                    "Unable to remove directory {}.",
+                    target.to_string_lossy().code_str(),
+                )))?;
+            }
+
+            // Move the entry into place.
+            rename(&intermediate, &target).map_err(failure::system(format!(
+                "Unable to move file {} to destination {}.",
+                intermediate.to_string_lossy().code_str(),
+                target.to_string_lossy().code_str(),
+            )))?;
+        }
+    }
+
+    // Delete anything that wasn't in the archive, if applicable. Children are visited before their
+    // parents, so directories are empty by the time we get to them.
+    if mirror {
+        for entry in WalkDir::new(destination).contents_first(true) {
+            // If the user wants to stop the operation, quit now.
+            if interrupted.load(Ordering::SeqCst) {
+                return Err(Failure::Interrupted);
+            }
+
+            // Unwrap the entry.
+            let entry = entry.map_err(failure::system(format!(
+                "Unable to traverse path {}.",
+                destination.to_string_lossy().code_str(),
+            )))?;
+
+            // Skip the entry if it came from the archive.
+            let path = entry.path();
+            if extracted.contains(path) {
+                continue;
+            }
+
+            // Delete the entry.
+            if entry.file_type().is_dir() {
+                remove_dir(path).map_err(failure::system(format!(
+                    "Unable to remove directory {}.",
+                    path.to_string_lossy().code_str(),
+                )))?;
+            } else {
+                remove_file(path).map_err(failure::system(format!(
+                    "Unable to remove file {}.",
+                    path.to_string_lossy().code_str(),
+                )))?;
+            }
+        }
+    }
+
+    // Apply the directory permissions, innermost directories first.
+    for (path, mode) in directory_modes.into_iter().rev() {
+        set_permissions(&path, Permissions::from_mode(mode & 0o7777)).map_err(failure::system(
+            format!(
+                "Unable to set permissions for directory {}.",
+                path.to_string_lossy().code_str(),
+            ),
+        ))?;
+    }
+
+    // Everything succeeded.
+    Ok(())
+}
+
+// Map a path from an archive produced by `docker container cp` to the corresponding path on the
+// host. The first component of the archive path is the name of the file or directory that was
+// copied, which corresponds to `destination`.
+fn map_path(archive_path: &Path, destination: &Path) -> Result<PathBuf, Failure> {
+    let mut target = destination.to_owned();
+
+    for component in archive_path.components().skip(1) {
+        match component {
+            Component::Normal(part) => target.push(part),
+            Component::CurDir => {}
+            Component::Prefix(_) | Component::RootDir | Component::ParentDir => {
+                return Err(Failure::System(
+                    format!(
+                        "Refusing to extract {} from the container.",
+                        archive_path.to_string_lossy().code_str()
+                    ),
+                    None,
+                ));
+            }
+        }
+    }
+
+    Ok(target)
+}
+
+// Check that none of the directories containing a path produced by `map_path`, from `destination`
+// down, are symbolic links. Otherwise, an archive with a symbolic link followed by an entry under
+// it could write anywhere on the host.
+fn check_parents(target: &Path, destination: &Path, archive_path: &Path) -> Result<(), Failure> {
+    // If the target is the destination itself, it has no parents under the destination.
+    if target == destination {
+        return Ok(());
+    }
+
+    // The `unwrap` is safe because `map_path` only appends components to `destination`.
+    let mut components = target
+        .strip_prefix(destination)
+        .unwrap()
+        .components()
+        .collect::<Vec<_>>();
+    components.pop();
+
+    let mut parents = vec![destination.to_owned()];
+    for component in components {
+        let parent = parents[parents.len() - 1].join(component);
+        parents.push(parent);
+    }
+
+    for parent in parents {
+        if symlink_metadata(&parent)
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false)
+        {
+            return Err(Failure::System(
+                format!(
+                    "Refusing to extract {} through the symbolic link {}.",
+                    archive_path.to_string_lossy().code_str(),
+                    parent.to_string_lossy().code_str(),
+                ),
+                None,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cache,
+        failure::Failure,
+        hash_cache::HashCache,
+        tar::{create, extract, hash, map_path},
+    };
+    use std::{
+        fs::{create_dir, read_link, read_to_string, symlink_metadata, write},
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+        sync::{atomic::AtomicBool, Arc},
+    };
+    use tar::{Archive, Builder, EntryType, Header};
+    use tempfile::tempdir;
+
+    fn archive() -> Vec<u8> {
+        let mut builder = Builder::new(vec![]);
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        builder.append_data(&mut header, "foo", &[][..]).unwrap();
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Regular);
+        header.set_mode(0o755);
+        header.set_size(3);
+        builder
+            .append_data(&mut header, "foo/bar", &b"baz"[..])
+            .unwrap();
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_mode(0o777);
+        header.set_size(0);
+        header.set_link_name("bar").unwrap();
         builder
-            .into_inner()
-            .map_err(failure::system("Error writing tar archive."))?,
-        file_hashes
+            .append_data(&mut header, "foo/qux", &[][..])
+            .unwrap();
+
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn hash_pure() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("foo")).unwrap();
+        write(dir.path().join("foo").join("bar"), "baz").unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        assert_eq!(
+            hash(
+                "",
+                &[PathBuf::from("foo")],
+                dir.path(),
+                &mut HashCache::default(),
+                &interrupted,
+            )
+            .unwrap(),
+            hash(
+                "",
+                &[PathBuf::from("foo")],
+                dir.path(),
+                &mut HashCache::default(),
+                &interrupted,
+            )
+            .unwrap(),
+        );
+    }
+
+    #[test]
+    fn hash_contents() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("foo")).unwrap();
+        write(dir.path().join("foo").join("bar"), "baz").unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        let hash1 = hash(
+            "",
+            &[PathBuf::from("foo")],
+            dir.path(),
+            &mut HashCache::default(),
+            &interrupted,
+        )
+        .unwrap();
+        write(dir.path().join("foo").join("bar"), "qux").unwrap();
+        let hash2 = hash(
+            "",
+            &[PathBuf::from("foo")],
+            dir.path(),
+            &mut HashCache::default(),
+            &interrupted,
+        )
+        .unwrap();
+
+        assert_ne!(hash1, hash2);
+    }
+
+    #[test]
+    fn hash_cached() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("foo")).unwrap();
+        write(dir.path().join("foo").join("bar"), "baz").unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let mut hash_cache = HashCache::default();
+
+        let hash1 = hash(
+            "",
+            &[PathBuf::from("foo")],
+            dir.path(),
+            &mut hash_cache,
+            &interrupted,
+        )
+        .unwrap();
+        let hash2 = hash(
+            "",
+            &[PathBuf::from("foo")],
+            dir.path(),
+            &mut hash_cache,
+            &interrupted,
+        )
+        .unwrap();
+
+        assert_eq!(hash1, hash2);
+    }
+
+    #[test]
+    fn hash_many_files() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("foo")).unwrap();
+        for i in 0..100 {
+            write(dir.path().join("foo").join(i.to_string()), i.to_string()).unwrap();
+        }
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let mut hash_cache = HashCache::default();
+
+        let hash1 = hash(
+            "",
+            &[PathBuf::from("foo")],
+            dir.path(),
+            &mut hash_cache,
+            &interrupted,
+        )
+        .unwrap();
+        let hash2 = hash(
+            "",
+            &[PathBuf::from("foo")],
+            dir.path(),
+            &mut hash_cache,
+            &interrupted,
+        )
+        .unwrap();
+
+        // Compute the expected hash one file at a time, the way it was done before files were
+        // read in parallel.
+        let mut file_hashes = vec![cache::hash_str("foo")];
+        for i in 0..100 {
+            file_hashes.push(cache::extend(
+                &cache::extend(
+                    &cache::hash_str(&format!("foo/{}", i)),
+                    &cache::hash_str(&i.to_string()),
+                ),
+                "-x",
+            ));
+        }
+        file_hashes.sort();
+        let expected = file_hashes
             .iter()
-            .fold(cache::hash_str(""), |acc, x| cache::extend(&acc, x)),
-    ))
+            .fold(cache::hash_str(""), |acc, x| cache::extend(&acc, x));
+
+        assert_eq!(hash1, expected);
+        assert_eq!(hash2, expected);
+    }
+
+    #[test]
+    fn hash_interrupted() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("foo")).unwrap();
+        write(dir.path().join("foo").join("bar"), "baz").unwrap();
+
+        match hash(
+            "",
+            &[PathBuf::from("foo")],
+            dir.path(),
+            &mut HashCache::default(),
+            &Arc::new(AtomicBool::new(true)),
+        ) {
+            Err(Failure::Interrupted) => {}
+            _ => panic!("The operation wasn't interrupted."),
+        }
+    }
+
+    #[test]
+    fn create_contents() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("foo")).unwrap();
+        write(dir.path().join("foo").join("bar"), "baz").unwrap();
+
+        let data = create(
+            vec![],
+            &[PathBuf::from("foo")],
+            dir.path(),
+            Path::new("/scratch"),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        let mut paths = Archive::new(&data[..])
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("scratch"),
+                PathBuf::from("scratch/foo"),
+                PathBuf::from("scratch/foo/bar"),
+            ],
+        );
+    }
+
+    #[test]
+    fn map_path_root() {
+        assert_eq!(
+            map_path(Path::new("foo"), Path::new("/bar")).unwrap(),
+            Path::new("/bar"),
+        );
+    }
+
+    #[test]
+    fn map_path_nested() {
+        assert_eq!(
+            map_path(Path::new("foo/baz/qux"), Path::new("/bar")).unwrap(),
+            Path::new("/bar/baz/qux"),
+        );
+    }
+
+    #[test]
+    fn map_path_parent() {
+        assert!(map_path(Path::new("foo/../qux"), Path::new("/bar")).is_err());
+    }
+
+    #[test]
+    fn extract_new() {
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("corge");
+
+        extract(
+            &archive()[..],
+            &destination,
+            false,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(read_to_string(destination.join("bar")).unwrap(), "baz");
+        assert_eq!(
+            symlink_metadata(destination.join("bar"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o755,
+        );
+        assert_eq!(
+            read_link(destination.join("qux")).unwrap(),
+            Path::new("bar"),
+        );
+    }
+
+    #[test]
+    fn extract_existing() {
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("corge");
+        create_dir(&destination).unwrap();
+        write(destination.join("bar"), "grault").unwrap();
+        create_dir(destination.join("qux")).unwrap();
+
+        extract(
+            &archive()[..],
+            &destination,
+            false,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(read_to_string(destination.join("bar")).unwrap(), "baz");
+        assert_eq!(
+            read_link(destination.join("qux")).unwrap(),
+            Path::new("bar"),
+        );
+    }
+
+    #[test]
+    fn extract_mirror() {
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("corge");
+        create_dir(&destination).unwrap();
+        write(destination.join("grault"), "garply").unwrap();
+        create_dir(destination.join("waldo")).unwrap();
+        write(destination.join("waldo").join("fred"), "plugh").unwrap();
+
+        extract(
+            &archive()[..],
+            &destination,
+            true,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(read_to_string(destination.join("bar")).unwrap(), "baz");
+        assert!(!destination.join("grault").exists());
+        assert!(!destination.join("waldo").exists());
+    }
+
+    #[test]
+    fn extract_no_mirror() {
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("corge");
+        create_dir(&destination).unwrap();
+        write(destination.join("grault"), "garply").unwrap();
+
+        extract(
+            &archive()[..],
+            &destination,
+            false,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(read_to_string(destination.join("bar")).unwrap(), "baz");
+        assert_eq!(
+            read_to_string(destination.join("grault")).unwrap(),
+            "garply"
+        );
+    }
+
+    fn symlink_archive(root: &str, link_name: &Path) -> Vec<u8> {
+        let mut builder = Builder::new(vec![]);
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_mode(0o777);
+        header.set_size(0);
+        header.set_link_name(link_name).unwrap();
+        builder.append_data(&mut header, root, &[][..]).unwrap();
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(3);
+        builder
+            .append_data(&mut header, format!("{}/bar", root), &b"baz"[..])
+            .unwrap();
+
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn extract_through_symlink() {
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("corge");
+        let outside = dir.path().join("grault");
+        create_dir(&outside).unwrap();
+
+        assert!(extract(
+            &symlink_archive("foo/qux", &outside)[..],
+            &destination,
+            false,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .is_err());
+
+        assert!(!outside.join("bar").exists());
+    }
+
+    #[test]
+    fn extract_through_symlink_root() {
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("corge");
+        let outside = dir.path().join("grault");
+        create_dir(&outside).unwrap();
+
+        assert!(extract(
+            &symlink_archive("foo", &outside)[..],
+            &destination,
+            false,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .is_err());
+
+        assert!(!outside.join("bar").exists());
+    }
 }
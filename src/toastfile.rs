@@ -1,9 +1,15 @@
-use crate::{failure::Failure, format, format::CodeStr};
-use serde::{Deserialize, Serialize};
+use crate::{
+    diagnostic::{Diagnostic, Diagnostics, Position, Severity},
+    format,
+    format::CodeStr,
+    interpolate,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_yaml::Value;
 use std::{
     collections::{HashMap, HashSet},
     env,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 // The default location for commands and files copied into the container
@@ -16,6 +22,8 @@ pub const DEFAULT_USER: &str = "root";
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Task {
+    pub extends: Option<String>,
+
     pub description: Option<String>,
 
     #[serde(default)]
@@ -27,11 +35,22 @@ pub struct Task {
     #[serde(default)]
     pub environment: HashMap<String, Option<String>>,
 
+    #[serde(default)]
+    pub cache_neutral_environment: HashMap<String, Option<String>>,
+
+    pub env_file: Option<PathBuf>,
+
+    #[serde(default)]
+    pub matrix: HashMap<String, Vec<String>>,
+
+    #[serde(default)]
+    pub secrets: HashMap<String, Option<Secret>>,
+
     #[serde(default)]
     pub input_paths: Vec<PathBuf>,
 
     #[serde(default)]
-    pub output_paths: Vec<PathBuf>,
+    pub output_paths: Vec<OutputPath>,
 
     #[serde(default)]
     pub mount_paths: Vec<PathBuf>,
@@ -67,43 +86,711 @@ fn default_task_user() -> String {
     DEFAULT_USER.to_owned()
 }
 
-// This struct represents a toastfile.
+// This struct represents the source of a secret. If neither `env` nor `file` is given, the secret is
+// read from the host environment variable with the same name as the secret.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Secret {
+    pub env: Option<String>,
+    pub file: Option<PathBuf>,
+}
+
+// This struct represents an output path. In a toastfile, it can be written either as a plain path
+// or as a map with a `path` and additional options.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(from = "OutputPathSpec")]
+pub struct OutputPath {
+    pub path: PathBuf,
+
+    // If this is `true`, the path on the host is made to match the path in the container exactly,
+    // i.e., files which are not in the container are deleted from the host.
+    pub mirror: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OutputPathSpec {
+    Path(PathBuf),
+    Options(OutputPathOptions),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutputPathOptions {
+    path: PathBuf,
+
+    #[serde(default = "default_output_path_mirror")]
+    mirror: bool,
+}
+
+fn default_output_path_mirror() -> bool {
+    false
+}
+
+impl From<OutputPathSpec> for OutputPath {
+    fn from(spec: OutputPathSpec) -> Self {
+        match spec {
+            OutputPathSpec::Path(path) => OutputPath {
+                path,
+                mirror: default_output_path_mirror(),
+            },
+            OutputPathSpec::Options(options) => OutputPath {
+                path: options.path,
+                mirror: options.mirror,
+            },
+        }
+    }
+}
+
+// This struct represents another toastfile to include. In a toastfile, it can be written either as
+// a plain path or as a map with a `path` and additional options.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(from = "IncludeSpec")]
+pub struct Include {
+    pub path: PathBuf,
+
+    // If this is given, the names of the included tasks are prefixed with it and a `.`.
+    pub namespace: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IncludeSpec {
+    Path(PathBuf),
+    Options(IncludeOptions),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IncludeOptions {
+    path: PathBuf,
+    namespace: Option<String>,
+}
+
+impl From<IncludeSpec> for Include {
+    fn from(spec: IncludeSpec) -> Self {
+        match spec {
+            IncludeSpec::Path(path) => Include {
+                path,
+                namespace: None,
+            },
+            IncludeSpec::Options(options) => Include {
+                path: options.path,
+                namespace: options.namespace,
+            },
+        }
+    }
+}
+
+// This struct represents a toastfile. The bases of the tasks, the `defaults`, and the
+// `environment` have already been merged into the `tasks` [ref:toastfile_defaults_merged]. The
+// `templates` are left as they were written.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Toastfile {
     pub image: String,
     pub default: Option<String>,
+
+    #[serde(default)]
+    pub include: Vec<Include>,
+
+    pub env_file: Option<PathBuf>,
+
+    #[serde(default)]
+    pub environment: HashMap<String, Option<String>>,
+
+    pub defaults: Option<Task>,
+
+    #[serde(default)]
+    pub templates: HashMap<String, Task>,
+
     pub tasks: HashMap<String, Task>,
 }
 
-// Parse config data.
-pub fn parse(toastfile_data: &str) -> Result<Toastfile, Failure> {
-    // Deserialize the data.
-    let toastfile: Toastfile =
-        serde_yaml::from_str(toastfile_data).map_err(|e| Failure::User(format!("{}", e), None))?;
+// The languages a toastfile can be written in
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl FileFormat {
+    // Determine the language of a toastfile from the extension of its path. Anything other than
+    // `.json` or `.toml` is considered YAML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => FileFormat::Json,
+            Some("toml") => FileFormat::Toml,
+            _ => FileFormat::Yaml,
+        }
+    }
+}
 
-    // Make sure the environment variables are valid.
-    check_environment(&toastfile)?;
+// Deserialize data written in any of the supported languages. On failure, the diagnostic has the
+// line and column of the problem, if the parser reports them.
+pub fn deserialize<T: DeserializeOwned>(
+    data: &str,
+    file_format: FileFormat,
+) -> Result<T, Diagnostics> {
+    let (message, line_column) = match file_format {
+        FileFormat::Yaml => match serde_yaml::from_str(data) {
+            Ok(value) => return Ok(value),
+            Err(error) => (
+                error.to_string(),
+                error
+                    .location()
+                    .map(|location| (location.line(), location.column())),
+            ),
+        },
+        FileFormat::Json => match serde_json::from_str(data) {
+            Ok(value) => return Ok(value),
+            Err(error) => (
+                error.to_string(),
+                Some((error.line(), error.column().max(1))).filter(|(line, _)| *line > 0),
+            ),
+        },
+        FileFormat::Toml => match toml::from_str(data) {
+            Ok(value) => return Ok(value),
+            Err(error) => (
+                error.to_string(),
+                error
+                    .line_col()
+                    .map(|(line, column)| (line + 1, column + 1)),
+            ),
+        },
+    };
 
-    // Make sure the paths are valid.
-    check_paths(&toastfile)?;
+    Err(Diagnostics(vec![Diagnostic {
+        severity: Severity::Error,
+        message,
+        path: vec![],
+        position: line_column.map(|(line, column)| Position {
+            line,
+            column,
+            length: 1,
+        }),
+    }]))
+}
 
-    // Make sure caching is disabled when appropriate.
-    check_caching(&toastfile)?;
+// Parse a YAML toastfile which doesn't include any other toastfiles. Toastfiles on disk are loaded
+// with `include::load` instead.
+#[cfg(test)]
+pub fn parse(toastfile_data: &str) -> Result<Toastfile, crate::failure::Failure> {
+    // Parse the toastfile.
+    let (toastfile, mut diagnostics) = parse_partial(toastfile_data, FileFormat::Yaml)?;
 
-    // Make sure the dependencies are valid.
-    check_dependencies(&toastfile)?;
+    // Make sure the references to variables and the dependencies are valid.
+    diagnostics.extend(check_interpolation(&toastfile));
+    diagnostics.extend(check_dependencies(&toastfile));
+    diagnostics.into_result()?;
 
     // Return the toastfile.
     Ok(toastfile)
 }
 
+// Parse a toastfile without checking the references to variables or the dependencies. The
+// environment files haven't been read yet, and tasks may depend on tasks from the toastfiles they
+// include. Once the environment files have been applied, the caller must call
+// `check_interpolation`, and once the included tasks have been merged in, the caller must call
+// `check_dependencies`. [ref:includes_merged] The checks run in phases, since each phase relies on
+// the previous ones having passed. Within a phase, all the problems are reported at once. The
+// problems found by the last phase don't prevent the rest of the toastfile from being loaded, so
+// they're returned along with the toastfile, to be reported together with those found by the
+// caller.
+pub fn parse_partial(
+    toastfile_data: &str,
+    file_format: FileFormat,
+) -> Result<(Toastfile, Diagnostics), Diagnostics> {
+    // Deserialize the data.
+    let mut toastfile: Toastfile = deserialize(toastfile_data, file_format)?;
+
+    // Determine which fields were written explicitly, as opposed to being filled in with their
+    // default values during deserialization. Only the keys are used, since `Value` doesn't
+    // preserve the original text of scalars like `3.10`.
+    let value: Value = deserialize(toastfile_data, file_format)?;
+    let explicit_fields = explicit_fields(&value);
+
+    // Make sure the `defaults` and the bases of the tasks and templates are valid.
+    let mut diagnostics = Diagnostics::default();
+    diagnostics.extend(check_defaults(&explicit_fields));
+    diagnostics.extend(check_extends(&toastfile));
+    diagnostics.into_result()?;
+
+    // Merge the bases, the `defaults`, and the `environment` into each task.
+    // [tag:toastfile_defaults_merged]
+    let task_fields = apply_extends(&mut toastfile, &explicit_fields);
+    apply_defaults(&mut toastfile, &task_fields);
+
+    // Make sure the matrices are valid, and expand them into concrete tasks.
+    check_matrix(&toastfile)?;
+    apply_matrix(&mut toastfile);
+
+    // Make sure the environment variables, the secrets, the included toastfiles, and the paths are
+    // valid, and that caching is disabled when appropriate.
+    let mut diagnostics = Diagnostics::default();
+    diagnostics.extend(check_environment(&toastfile));
+    diagnostics.extend(check_secrets(&toastfile));
+    diagnostics.extend(check_paths(&toastfile));
+    diagnostics.extend(check_caching(&toastfile));
+
+    // The caller loads the included toastfiles next, so problems with them can't wait.
+    if let Err(include_diagnostics) = check_includes(&toastfile) {
+        diagnostics.0.extend(include_diagnostics.0);
+        diagnostics.sort();
+        return Err(diagnostics);
+    }
+
+    // Return the toastfile along with any problems found by the last phase.
+    Ok((toastfile, diagnostics))
+}
+
+// The names of the fields which are written explicitly in the `defaults` (under the key `None`)
+// and in each template and task (under its name). Template names and task names don't overlap due
+// to [ref:extends_valid].
+type ExplicitFields = HashMap<Option<String>, HashSet<String>>;
+
+// Find the fields which are written explicitly in a toastfile.
+fn explicit_fields(toastfile: &Value) -> ExplicitFields {
+    // Gather the names of the keys of a mapping.
+    let keys = |mapping: &Value| {
+        mapping
+            .as_mapping()
+            .map(|mapping| {
+                mapping
+                    .iter()
+                    .filter_map(|(key, _)| key.as_str().map(ToOwned::to_owned))
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut result = HashMap::new();
+    if let Some(defaults) = toastfile.get("defaults") {
+        result.insert(None, keys(defaults));
+    }
+    for section in &["templates", "tasks"] {
+        if let Some(tasks) = toastfile.get(*section).and_then(Value::as_mapping) {
+            for (name, task) in tasks.iter() {
+                if let Some(name) = name.as_str() {
+                    result.insert(Some(name.to_owned()), keys(task));
+                }
+            }
+        }
+    }
+    result
+}
+
+// Check that the `defaults` don't include fields which only make sense for individual tasks.
+fn check_defaults(explicit_fields: &ExplicitFields) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    if let Some(fields) = explicit_fields.get(&None) {
+        for field in &["command", "dependencies", "extends"] {
+            if fields.contains(*field) {
+                diagnostics.0.push(Diagnostic::new(
+                    format!(
+                        "The {} can't include {}.",
+                        "defaults".code_str(),
+                        field.code_str(),
+                    ),
+                    &["defaults", field],
+                ));
+            }
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+// Merge the base of each task into it, recursively. Fields which the task doesn't specify are
+// taken from the base. Maps are merged one entry at a time, with the task's own entries taking
+// precedence. Lists are appended to those of the base, skipping duplicates. The bases are valid
+// and acyclic due to [ref:extends_valid].
+fn apply_extends(
+    toastfile: &mut Toastfile,
+    explicit_fields: &ExplicitFields,
+) -> HashMap<String, HashSet<String>> {
+    let mut resolved = HashMap::new();
+    let mut tasks = HashMap::new();
+    let mut task_fields = HashMap::new();
+    for name in toastfile.tasks.keys() {
+        let (task, fields) = resolve_extends(name, toastfile, explicit_fields, &mut resolved);
+        tasks.insert(name.clone(), task);
+        task_fields.insert(name.clone(), fields);
+    }
+    toastfile.tasks = tasks;
+    task_fields
+}
+
+// Compute the fully merged version of a task or template, along with the names of the fields
+// specified explicitly by it or any of its bases. The results are memoized.
+fn resolve_extends(
+    name: &str,
+    toastfile: &Toastfile,
+    explicit_fields: &ExplicitFields,
+    resolved: &mut HashMap<String, (Task, HashSet<String>)>,
+) -> (Task, HashSet<String>) {
+    if let Some(result) = resolved.get(name) {
+        return result.clone();
+    }
+
+    // The task or template exists due to [ref:extends_valid].
+    let task = toastfile
+        .tasks
+        .get(name)
+        .or_else(|| toastfile.templates.get(name))
+        .unwrap();
+    let fields = explicit_fields
+        .get(&Some(name.to_owned()))
+        .cloned()
+        .unwrap_or_default();
+
+    let result = match &task.extends {
+        Some(base) => {
+            let (base_task, base_fields) =
+                resolve_extends(base, toastfile, explicit_fields, resolved);
+            (
+                merge_task(&base_task, task, &fields, true),
+                base_fields.union(&fields).cloned().collect(),
+            )
+        }
+        None => (task.clone(), fields),
+    };
+
+    resolved.insert(name.to_owned(), result.clone());
+    result
+}
+
+// Merge the `defaults` and the `environment` of a toastfile into each of its tasks. A default is
+// only used if neither the task nor any of its bases specifies the field, according to
+// `task_fields`. The `environment` is merged one variable at a time, and the task's own variables
+// take precedence.
+fn apply_defaults(toastfile: &mut Toastfile, task_fields: &HashMap<String, HashSet<String>>) {
+    for (name, task) in &mut toastfile.tasks {
+        if let Some(defaults) = &toastfile.defaults {
+            *task = merge_task(defaults, task, &task_fields[name], false);
+        }
+
+        for (variable, default) in &toastfile.environment {
+            if !task.environment.contains_key(variable)
+                && !task.cache_neutral_environment.contains_key(variable)
+            {
+                task.environment.insert(variable.clone(), default.clone());
+            }
+        }
+    }
+}
+
+// Compute the name of a task expanded from a matrix, e.g., `test[postgres=12,python=3.8]`.
+// `variables` must be sorted by variable name.
+pub fn matrix_task_name(name: &str, variables: &[(&str, &str)]) -> String {
+    format!(
+        "{}[{}]",
+        name,
+        variables
+            .iter()
+            .map(|(variable, value)| format!("{}={}", variable, value))
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+}
+
+// Compute all the combinations of values for a matrix, with the variables in sorted order.
+fn matrix_combinations(matrix: &HashMap<String, Vec<String>>) -> Vec<Vec<(&str, &str)>> {
+    let mut variables = matrix.keys().collect::<Vec<_>>();
+    variables.sort();
+
+    let mut combinations = vec![vec![]];
+    for variable in variables {
+        combinations = combinations
+            .iter()
+            .flat_map(|combination: &Vec<(&str, &str)>| {
+                matrix[variable].iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((variable.as_ref(), value.as_ref()));
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+// Check that each variable in a matrix has at least one value, that the variables can be used in
+// the environment, and that the expanded tasks don't have the same names as other tasks.
+// [tag:matrix_valid]
+fn check_matrix(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    for (name, task) in &toastfile.tasks {
+        for (variable, values) in &task.matrix {
+            if values.is_empty() {
+                diagnostics.0.push(Diagnostic::new(
+                    format!(
+                        "Matrix variable {} of task {} has no values.",
+                        variable.code_str(),
+                        name.code_str(),
+                    ),
+                    &["tasks", name, "matrix", variable],
+                ));
+            }
+
+            if task.cache_neutral_environment.contains_key(variable) {
+                diagnostics.0.push(Diagnostic::new(
+                    format!(
+                        "Matrix variable {} of task {} is also in {}.",
+                        variable.code_str(),
+                        name.code_str(),
+                        "cache_neutral_environment".code_str(),
+                    ),
+                    &["tasks", name, "matrix", variable],
+                ));
+            }
+        }
+
+        if task.matrix.is_empty() {
+            continue;
+        }
+
+        for combination in matrix_combinations(&task.matrix) {
+            let expanded_name = matrix_task_name(name, &combination);
+            if toastfile.tasks.contains_key(&expanded_name) {
+                diagnostics.0.push(Diagnostic::new(
+                    format!(
+                        "Task {} has the same name as one expanded from the matrix of task {}.",
+                        expanded_name.code_str(),
+                        name.code_str(),
+                    ),
+                    &["tasks", &expanded_name],
+                ));
+            }
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+// Expand each task with a matrix into one task per combination of values. The values are added to
+// the `environment` of each expanded task, and the matrix of each expanded task has exactly one
+// value per variable [tag:matrix_expanded]. The original task is replaced by one which depends on
+// all the expanded tasks, so it can still be run or depended on as a whole.
+fn apply_matrix(toastfile: &mut Toastfile) {
+    let mut expanded_tasks = HashMap::new();
+
+    for (name, task) in &mut toastfile.tasks {
+        if task.matrix.is_empty() {
+            continue;
+        }
+
+        let mut dependencies = vec![];
+        for combination in matrix_combinations(&task.matrix) {
+            let mut expanded_task = task.clone();
+            expanded_task.matrix = HashMap::new();
+            for (variable, value) in &combination {
+                expanded_task
+                    .environment
+                    .insert((*variable).to_owned(), Some((*value).to_owned()));
+                expanded_task
+                    .matrix
+                    .insert((*variable).to_owned(), vec![(*value).to_owned()]);
+            }
+
+            let expanded_name = matrix_task_name(name, &combination);
+            dependencies.push(expanded_name.clone());
+            expanded_tasks.insert(expanded_name, expanded_task);
+        }
+
+        *task = Task {
+            extends: None,
+            description: task.description.clone(),
+            dependencies,
+            cache: default_task_cache(),
+            environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
+            input_paths: vec![],
+            output_paths: vec![],
+            mount_paths: vec![],
+            mount_readonly: default_task_mount_readonly(),
+            ports: vec![],
+            location: default_task_location(),
+            user: default_task_user(),
+            command: None,
+        };
+    }
+
+    toastfile.tasks.extend(expanded_tasks);
+}
+
+// The name of the task in the toastfile which a task comes from. This is the task itself, unless
+// it was expanded from a matrix [ref:matrix_expanded]. Problems with an expanded task are reported
+// under this name, so they're only reported once. [tag:matrix_source_name]
+pub fn source_name<'a>(name: &'a str, task: &Task) -> &'a str {
+    if task.matrix.is_empty() {
+        name
+    } else {
+        name.find('[').map_or(name, |index| &name[..index])
+    }
+}
+
+// Merge a task into a base. Fields which aren't in `fields` are taken from the base. If `combine`
+// is `true`, maps are merged one entry at a time and lists are appended, skipping duplicates.
+// Otherwise, the fields in `fields` replace those of the base entirely.
+fn merge_task(base: &Task, task: &Task, fields: &HashSet<String>, combine: bool) -> Task {
+    // Pick the value of a field which can't be combined.
+    fn pick<T: Clone>(fields: &HashSet<String>, field: &str, base: &T, task: &T) -> T {
+        if fields.contains(field) {
+            task.clone()
+        } else {
+            base.clone()
+        }
+    }
+
+    // Merge a map field.
+    fn map<V: Clone>(
+        fields: &HashSet<String>,
+        combine: bool,
+        field: &str,
+        base: &HashMap<String, V>,
+        task: &HashMap<String, V>,
+    ) -> HashMap<String, V> {
+        if !fields.contains(field) {
+            base.clone()
+        } else if combine {
+            let mut result = base.clone();
+            result.extend(task.iter().map(|(key, value)| (key.clone(), value.clone())));
+            result
+        } else {
+            task.clone()
+        }
+    }
+
+    // Merge a list field.
+    fn list<T: Clone + PartialEq>(
+        fields: &HashSet<String>,
+        combine: bool,
+        field: &str,
+        base: &[T],
+        task: &[T],
+    ) -> Vec<T> {
+        if !fields.contains(field) {
+            base.to_owned()
+        } else if combine {
+            let mut result = base.to_owned();
+            for item in task {
+                if !result.contains(item) {
+                    result.push(item.clone());
+                }
+            }
+            result
+        } else {
+            task.to_owned()
+        }
+    }
+
+    Task {
+        extends: pick(fields, "extends", &base.extends, &task.extends),
+        description: pick(fields, "description", &base.description, &task.description),
+        dependencies: list(
+            fields,
+            combine,
+            "dependencies",
+            &base.dependencies,
+            &task.dependencies,
+        ),
+        cache: pick(fields, "cache", &base.cache, &task.cache),
+        environment: map(
+            fields,
+            combine,
+            "environment",
+            &base.environment,
+            &task.environment,
+        ),
+        cache_neutral_environment: map(
+            fields,
+            combine,
+            "cache_neutral_environment",
+            &base.cache_neutral_environment,
+            &task.cache_neutral_environment,
+        ),
+        env_file: pick(fields, "env_file", &base.env_file, &task.env_file),
+        matrix: map(fields, combine, "matrix", &base.matrix, &task.matrix),
+        secrets: map(fields, combine, "secrets", &base.secrets, &task.secrets),
+        input_paths: list(
+            fields,
+            combine,
+            "input_paths",
+            &base.input_paths,
+            &task.input_paths,
+        ),
+        output_paths: list(
+            fields,
+            combine,
+            "output_paths",
+            &base.output_paths,
+            &task.output_paths,
+        ),
+        mount_paths: list(
+            fields,
+            combine,
+            "mount_paths",
+            &base.mount_paths,
+            &task.mount_paths,
+        ),
+        mount_readonly: pick(
+            fields,
+            "mount_readonly",
+            &base.mount_readonly,
+            &task.mount_readonly,
+        ),
+        ports: list(fields, combine, "ports", &base.ports, &task.ports),
+        location: pick(fields, "location", &base.location, &task.location),
+        user: pick(fields, "user", &base.user, &task.user),
+        command: pick(fields, "command", &base.command, &task.command),
+    }
+}
+
 // Fetch the variables for a task from the environment.
-pub fn environment<'a>(task: &'a Task) -> Result<HashMap<String, String>, Vec<&'a str>> {
+pub fn environment(task: &Task) -> Result<HashMap<String, String>, Vec<&str>> {
+    let mut result = fetch_variables(
+        task.environment
+            .iter()
+            .chain(task.cache_neutral_environment.iter()),
+    )?;
+
+    // The values of matrix variables can't be overridden [ref:matrix_expanded].
+    for (variable, values) in &task.matrix {
+        if let [value] = values.as_slice() {
+            result.insert(variable.clone(), value.clone());
+        }
+    }
+
+    Ok(result)
+}
+
+// Fetch the variables from the top-level `environment` which are referenced by the `image`. The
+// references are valid due to [ref:interpolation_valid].
+pub fn image_environment(toastfile: &Toastfile) -> Result<HashMap<String, String>, Vec<&str>> {
+    let variables = interpolate::references(&toastfile.image).unwrap_or_default();
+    fetch_variables(
+        toastfile
+            .environment
+            .iter()
+            .filter(|(variable, _)| variables.contains(&variable.as_str())),
+    )
+}
+
+// Fetch variables from the environment, using the given defaults for those which aren't set.
+fn fetch_variables<'a, I: Iterator<Item = (&'a String, &'a Option<String>)>>(
+    variables: I,
+) -> Result<HashMap<String, String>, Vec<&'a str>> {
     let mut violations = vec![];
     let mut result = HashMap::new();
 
-    for (arg, default) in &task.environment {
+    for (arg, default) in variables {
         let maybe_var = env::var(arg);
         if let Some(default) = default {
             result.insert(arg.clone(), maybe_var.unwrap_or_else(|_| default.clone()));
@@ -121,124 +808,323 @@ pub fn environment<'a>(task: &'a Task) -> Result<HashMap<String, String>, Vec<&'
     }
 }
 
-// Check that environment variable names don't have `=` in them. [tag:env_var_equals]
-fn check_environment(toastfile: &Toastfile) -> Result<(), Failure> {
+// Check that environment variable names don't have `=` in them [tag:env_var_equals] and that no
+// variable is both cached and cache-neutral.
+fn check_environment(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
     for (name, task) in &toastfile.tasks {
-        for variable in task.environment.keys() {
-            if variable.contains('=') {
-                return Err(Failure::User(
+        // [ref:matrix_source_name]
+        let name = source_name(name, task);
+        for (section, variables) in &[
+            ("environment", &task.environment),
+            ("cache_neutral_environment", &task.cache_neutral_environment),
+        ] {
+            for variable in variables.keys() {
+                if variable.contains('=') {
+                    diagnostics.0.push(Diagnostic::new(
+                        format!(
+                            "Environment variable {} of task {} contains {}.",
+                            variable.code_str(),
+                            name.code_str(),
+                            "=".code_str(),
+                        ),
+                        &["tasks", name, section, variable],
+                    ));
+                }
+            }
+        }
+
+        for variable in task.cache_neutral_environment.keys() {
+            if task.environment.contains_key(variable) {
+                diagnostics.0.push(Diagnostic::new(
                     format!(
-                        "Environment variable {} of task {} contains {}.",
+                        "Environment variable {} of task {} is in both {} and {}.",
                         variable.code_str(),
                         name.code_str(),
-                        "=".code_str(),
+                        "environment".code_str(),
+                        "cache_neutral_environment".code_str(),
+                    ),
+                    &["tasks", name, "cache_neutral_environment", variable],
+                ));
+            }
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+// Check that the fields which support interpolation only refer to declared variables. The `image`
+// can refer to variables in the top-level `environment`, and tasks can refer to their own
+// variables. [tag:interpolation_valid]
+pub fn check_interpolation(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+    let mut check = |field: &str, declared: &dyn Fn(&str) -> bool, context: &str, path: &[&str]| {
+        match interpolate::references(field) {
+            Ok(variables) => {
+                for variable in variables {
+                    if !declared(variable) {
+                        diagnostics.0.push(Diagnostic::new(
+                            format!(
+                                "{} {} refers to the undeclared variable {}.",
+                                context,
+                                field.code_str(),
+                                variable.code_str(),
+                            ),
+                            path,
+                        ));
+                    }
+                }
+            }
+            Err(error) => diagnostics
+                .0
+                .push(Diagnostic::new(format!("{} {}", context, error), path)),
+        }
+    };
+
+    check(
+        &toastfile.image,
+        &|variable| toastfile.environment.contains_key(variable),
+        "In the image,",
+        &["image"],
+    );
+
+    for (name, task) in &toastfile.tasks {
+        // [ref:matrix_source_name]
+        let name = source_name(name, task);
+        let context = format!("In task {},", name.code_str());
+        for (field, value) in interpolate::task_fields(task) {
+            check(
+                &value,
+                &|variable| {
+                    task.environment.contains_key(variable)
+                        || task.cache_neutral_environment.contains_key(variable)
+                },
+                &context,
+                &["tasks", name, field],
+            );
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+// Check that secret names can be used as file names and that each secret has at most one source.
+fn check_secrets(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    for (name, task) in &toastfile.tasks {
+        // [ref:matrix_source_name]
+        let name = source_name(name, task);
+        for (secret, source) in &task.secrets {
+            // [tag:secret_name_file_name]
+            if secret.is_empty() || secret == "." || secret == ".." || secret.contains('/') {
+                diagnostics.0.push(Diagnostic::new(
+                    format!(
+                        "Secret {} of task {} is not a valid file name.",
+                        secret.code_str(),
+                        name.code_str(),
                     ),
-                    None,
+                    &["tasks", name, "secrets", secret],
                 ));
             }
+
+            if let Some(source) = source {
+                if source.env.is_some() && source.file.is_some() {
+                    diagnostics.0.push(Diagnostic::new(
+                        format!(
+                            "Secret {} of task {} has both {} and {}.",
+                            secret.code_str(),
+                            name.code_str(),
+                            "env".code_str(),
+                            "file".code_str(),
+                        ),
+                        &["tasks", name, "secrets", secret],
+                    ));
+                }
+
+                if let Some(file) = &source.file {
+                    if file.is_absolute() {
+                        diagnostics.0.push(Diagnostic::new(
+                            format!(
+                                "Secret {} of task {} has an absolute {}: {}.",
+                                secret.code_str(),
+                                name.code_str(),
+                                "file".code_str(),
+                                file.to_string_lossy().code_str(),
+                            ),
+                            &["tasks", name, "secrets", secret, "file"],
+                        ));
+                    }
+                }
+            }
         }
     }
 
-    Ok(())
+    diagnostics.into_result()
 }
 
 // Check that paths that should be relative are, and likewise for paths that should be absolute.
-fn check_paths(toastfile: &Toastfile) -> Result<(), Failure> {
+// This is also run again after variables have been interpolated, since a value could change
+// whether a path is absolute.
+pub fn check_paths(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    // Check the toastfile's `env_file`.
+    if let Some(path) = &toastfile.env_file {
+        if path.is_absolute() {
+            diagnostics.0.push(Diagnostic::new(
+                format!(
+                    "The toastfile has an absolute {}: {}.",
+                    "env_file".code_str(),
+                    path.to_string_lossy().code_str()
+                ),
+                &["env_file"],
+            ));
+        }
+    }
+
     for (name, task) in &toastfile.tasks {
+        // [ref:matrix_source_name]
+        let name = source_name(name, task);
+
+        // Check `env_file`.
+        if let Some(path) = &task.env_file {
+            if path.is_absolute() {
+                diagnostics.0.push(Diagnostic::new(
+                    format!(
+                        "Task {} has an absolute {}: {}.",
+                        name.code_str(),
+                        "env_file".code_str(),
+                        path.to_string_lossy().code_str()
+                    ),
+                    &["tasks", name, "env_file"],
+                ));
+            }
+        }
+
         // Check `input_paths`.
-        for path in &task.input_paths {
+        for (index, path) in task.input_paths.iter().enumerate() {
             if path.is_absolute() {
-                return Err(Failure::User(
+                diagnostics.0.push(Diagnostic::new(
                     format!(
                         "Task {} has an absolute {}: {}.",
                         name.code_str(),
                         "input_path".code_str(),
                         path.to_string_lossy().code_str()
                     ),
-                    None,
+                    &["tasks", name, "input_paths", &index.to_string()],
                 ));
             }
         }
 
         // Check `output_paths`.
-        for path in &task.output_paths {
+        for (index, output_path) in task.output_paths.iter().enumerate() {
+            let path = &output_path.path;
             if path.is_absolute() {
-                return Err(Failure::User(
+                diagnostics.0.push(Diagnostic::new(
                     format!(
                         "Task {} has an absolute {}: {}.",
                         name.code_str(),
                         "output_path".code_str(),
                         path.to_string_lossy().code_str()
                     ),
-                    None,
+                    &["tasks", name, "output_paths", &index.to_string()],
+                ));
+            }
+
+            // Mirroring deletes files on the host, so it must be confined to a proper subdirectory
+            // of the directory containing the toastfile. Once `..` is ruled out, a path names such a
+            // subdirectory exactly when it ends with a normal component.
+            // [tag:mirror_inside_toastfile_dir]
+            if output_path.mirror
+                && (path
+                    .components()
+                    .any(|component| component == Component::ParentDir)
+                    || path.file_name().is_none())
+            {
+                diagnostics.0.push(Diagnostic::new(
+                    format!(
+                        "Task {} mirrors {} {}, which is not inside the directory containing the \
+                         toastfile.",
+                        name.code_str(),
+                        "output_path".code_str(),
+                        path.to_string_lossy().code_str()
+                    ),
+                    &["tasks", name, "output_paths", &index.to_string()],
                 ));
             }
         }
 
         // Check `mount_paths`.
-        for path in &task.mount_paths {
+        for (index, path) in task.mount_paths.iter().enumerate() {
             if path.is_absolute() {
-                return Err(Failure::User(
+                diagnostics.0.push(Diagnostic::new(
                     format!(
                         "Task {} has an absolute {}: {}.",
                         name.code_str(),
                         "mount_path".code_str(),
                         path.to_string_lossy().code_str()
                     ),
-                    None,
+                    &["tasks", name, "mount_paths", &index.to_string()],
                 ));
             }
 
             // [tag:mount_path_comma]
             if path.to_string_lossy().contains(',') {
-                return Err(Failure::User(
+                diagnostics.0.push(Diagnostic::new(
                     format!(
                         "Mount path {} of task {} has a {}.",
                         path.to_string_lossy().code_str(),
                         name.code_str(),
                         ",".code_str()
                     ),
-                    None,
+                    &["tasks", name, "mount_paths", &index.to_string()],
                 ));
             }
         }
 
         // Check `location`.
         if task.location.is_relative() {
-            return Err(Failure::User(
+            diagnostics.0.push(Diagnostic::new(
                 format!(
                     "Task {} has a relative {}: {}.",
                     name.code_str(),
                     "location".code_str(),
                     task.location.to_string_lossy().code_str()
                 ),
-                None,
+                &["tasks", name, "location"],
             ));
         }
     }
 
-    Ok(())
+    diagnostics.into_result()
 }
 
 // Check that caching is disabled when appropriate.
-fn check_caching(toastfile: &Toastfile) -> Result<(), Failure> {
+fn check_caching(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
     for (name, task) in &toastfile.tasks {
+        // [ref:matrix_source_name]
+        let name = source_name(name, task);
+
         // If a task exposes ports, then caching should be disabled.
         if !&task.ports.is_empty() && task.cache {
-            return Err(Failure::User(
+            diagnostics.0.push(Diagnostic::new(
                 format!(
                     "Task {} exposes ports but does not disable caching. \
                      To fix this, set {} for this task.",
                     name.code_str(),
                     "cache: false".code_str(),
                 ),
-                None,
+                &["tasks", name, "ports"],
             ));
         }
 
         // If a task has any mount paths, then caching should be disabled.
         if !task.mount_paths.is_empty() && task.cache {
-            return Err(Failure::User(
+            diagnostics.0.push(Diagnostic::new(
                 format!(
                     "Task {} has {} but does not disable caching. \
                      To fix this, set {} for this task.",
@@ -246,83 +1132,171 @@ fn check_caching(toastfile: &Toastfile) -> Result<(), Failure> {
                     "mount_paths".code_str(),
                     "cache: false".code_str(),
                 ),
-                None,
+                &["tasks", name, "mount_paths"],
             ));
         }
     }
 
-    Ok(())
+    diagnostics.into_result()
 }
 
-// Check that all dependencies exist and form a DAG (no cycles). [tag:tasks_dag]
-fn check_dependencies<'a>(toastfile: &'a Toastfile) -> Result<(), Failure> {
-    // Check the default task. [tag:valid_default]
-    let valid_default = toastfile
-        .default
-        .as_ref()
-        .map_or(true, |default| toastfile.tasks.contains_key(default));
-
-    // Map from task to vector of invalid dependencies.
-    let mut violations: HashMap<String, Vec<String>> = HashMap::new();
+// Check that templates don't have the same names as tasks, that the bases of all tasks and
+// templates exist, and that nothing extends itself, directly or indirectly. [tag:extends_valid]
+fn check_extends(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
 
-    // Scan for invalid dependencies. [tag:task_valid]
-    for task in toastfile.tasks.keys() {
-        // [ref:task_valid]
-        for dependency in &toastfile.tasks[task].dependencies {
-            if !toastfile.tasks.contains_key(dependency) {
-                violations
-                    .entry(task.to_owned())
-                    .or_insert_with(|| vec![])
-                    .push(dependency.to_owned());
-            }
+    // Tasks and templates share a namespace.
+    for template in toastfile.templates.keys() {
+        if toastfile.tasks.contains_key(template) {
+            diagnostics.0.push(Diagnostic::new(
+                format!(
+                    "Template {} has the same name as a task.",
+                    template.code_str(),
+                ),
+                &["templates", template],
+            ));
         }
     }
 
-    // If there were any invalid dependencies, report them.
-    if !violations.is_empty() {
-        let violations_series = format::series(
-            violations
+    // Map from task or template to its base, along with the section it's in
+    let bases = toastfile
+        .tasks
+        .iter()
+        .map(|(name, task)| ("tasks", name, task))
+        .chain(
+            toastfile
+                .templates
                 .iter()
-                .map(|(task, dependencies)| {
-                    format!(
-                        "{} ({})",
-                        task.code_str(),
-                        format::series(
-                            dependencies
-                                .iter()
-                                .map(|task| format!("{}", task.code_str()))
-                                .collect::<Vec<_>>()
-                                .as_ref()
-                        )
-                    )
-                })
-                .collect::<Vec<_>>()
-                .as_ref(),
-        );
+                .map(|(name, task)| ("templates", name, task)),
+        )
+        .filter_map(|(section, name, task)| {
+            task.extends
+                .as_ref()
+                .map(|base| (name.as_ref(), (section, base.as_ref())))
+        })
+        .collect::<HashMap<&str, (&str, &str)>>();
+
+    // Check that the bases exist.
+    for (name, (section, base)) in &bases {
+        if !toastfile.tasks.contains_key(*base) && !toastfile.templates.contains_key(*base) {
+            diagnostics.0.push(Diagnostic::new(
+                format!(
+                    "{} extends the nonexistent base {}.",
+                    name.code_str(),
+                    base.code_str(),
+                ),
+                &[section, name, "extends"],
+            ));
+        }
+    }
+
+    // Check that there are no cycles. Each task or template has at most one base, so it suffices
+    // to follow the chain of bases from each one. Each cycle is reported once, at its first member
+    // in sorted order.
+    let mut names = bases.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        let mut chain = vec![name];
+        while let Some((_, base)) = bases.get(chain[chain.len() - 1]) {
+            if let Some(position) = chain.iter().position(|ancestor| ancestor == base) {
+                let cycle = &chain[position..];
+                if cycle.iter().min() == Some(&name) {
+                    diagnostics.0.push(Diagnostic::new(
+                        if cycle.len() == 1 {
+                            format!("{} extends itself.", cycle[0].code_str())
+                        } else {
+                            format!(
+                                "{} form a cycle of bases.",
+                                format::series(
+                                    &cycle
+                                        .iter()
+                                        .map(|name| format!("{}", name.code_str()))
+                                        .collect::<Vec<_>>()
+                                ),
+                            )
+                        },
+                        &[bases[name].0, name, "extends"],
+                    ));
+                }
+                break;
+            }
+            chain.push(base);
+        }
+    }
 
-        if valid_default {
-            return Err(Failure::User(
+    diagnostics.into_result()
+}
+
+// Check that the included toastfiles are given by relative paths and have nonempty namespaces.
+fn check_includes(toastfile: &Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    for (index, include) in toastfile.include.iter().enumerate() {
+        if include.path.is_absolute() {
+            diagnostics.0.push(Diagnostic::new(
                 format!(
-                    "The following tasks have invalid dependencies: {}.",
-                    violations_series
+                    "The toastfile includes an absolute path: {}.",
+                    include.path.to_string_lossy().code_str(),
                 ),
-                None,
+                &["include", &index.to_string()],
+            ));
+        }
+
+        if let Some(namespace) = &include.namespace {
+            if namespace.trim().is_empty() {
+                diagnostics.0.push(Diagnostic::new(
+                    format!(
+                        "The toastfile includes {} with an empty namespace.",
+                        include.path.to_string_lossy().code_str(),
+                    ),
+                    &["include", &index.to_string(), "namespace"],
+                ));
+            }
+        }
+    }
+
+    diagnostics.into_result()
+}
+
+// Check that all dependencies exist and form a DAG (no cycles). [tag:tasks_dag]
+pub fn check_dependencies<'a>(toastfile: &'a Toastfile) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    // Check the default task. [tag:valid_default]
+    if let Some(default) = &toastfile.default {
+        if !toastfile.tasks.contains_key(default) {
+            diagnostics.0.push(Diagnostic::new(
+                format!("The default task {} does not exist.", default.code_str()),
+                &["default"],
             ));
-        } else {
-            return Err(Failure::User(format!(
-        "The default task {} does not exist, and the following tasks have invalid dependencies: {}.",
-        toastfile.default.as_ref().unwrap().code_str(), // [ref:valid_default]
-        violations_series
-      ), None));
         }
-    } else if !valid_default {
-        return Err(Failure::User(
-            format!(
-                "The default task {} does not exist.",
-                toastfile.default.as_ref().unwrap().code_str() // [ref:valid_default]
-            ),
-            None,
-        ));
+    }
+
+    // Scan for invalid dependencies. [tag:task_valid]
+    let mut valid_dependencies = true;
+    for (task, task_data) in &toastfile.tasks {
+        // [ref:matrix_source_name]
+        let task = source_name(task, task_data);
+
+        // [ref:task_valid]
+        for (index, dependency) in task_data.dependencies.iter().enumerate() {
+            if !toastfile.tasks.contains_key(dependency) {
+                valid_dependencies = false;
+                diagnostics.0.push(Diagnostic::new(
+                    format!(
+                        "Task {} depends on the nonexistent task {}.",
+                        task.code_str(),
+                        dependency.code_str(),
+                    ),
+                    &["tasks", task, "dependencies", &index.to_string()],
+                ));
+            }
+        }
+    }
+
+    // The cycle check below relies on the dependencies existing.
+    if !valid_dependencies {
+        return diagnostics.into_result();
     }
 
     // Check that the dependencies aren't cyclic.
@@ -346,7 +1320,8 @@ fn check_dependencies<'a>(toastfile: &'a Toastfile) -> Result<(), Failure> {
                 ancestors_set.remove(task_to_remove);
             }
 
-            // If this task is an ancestor of itself, we have a cycle. Return an error.
+            // If this task is an ancestor of itself, we have a cycle. Report it, and stop looking,
+            // since the same cycle would be found again from the other tasks in it.
             if ancestors_set.contains(task) {
                 let mut cycle_iter = ancestors_stack.iter();
                 cycle_iter.find(|&&x| x == task);
@@ -379,10 +1354,11 @@ fn check_dependencies<'a>(toastfile: &'a Toastfile) -> Result<(), Failure> {
                         )
                     )
                 };
-                return Err(Failure::User(
+                diagnostics.0.push(Diagnostic::new(
                     format!("The dependencies are cyclic. {}", error_message),
-                    None,
+                    &["tasks", cycle[0], "dependencies"],
                 ));
+                return diagnostics.into_result();
             }
 
             // If we've never seen this task before, add its dependencies to the frontier.
@@ -399,15 +1375,15 @@ fn check_dependencies<'a>(toastfile: &'a Toastfile) -> Result<(), Failure> {
         }
     }
 
-    // No violations
-    Ok(())
+    diagnostics.into_result()
 }
 
 #[cfg(test)]
 mod tests {
     use crate::toastfile::{
-        check_caching, check_dependencies, check_environment, check_paths, environment, parse,
-        Task, Toastfile, DEFAULT_LOCATION, DEFAULT_USER,
+        check_caching, check_dependencies, check_environment, check_paths, check_secrets,
+        environment, parse, parse_partial, FileFormat, OutputPath, Secret, Task, Toastfile,
+        DEFAULT_LOCATION, DEFAULT_USER,
     };
     use std::{collections::HashMap, env, path::Path};
 
@@ -422,6 +1398,11 @@ tasks: {}
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks: HashMap::new(),
         };
 
@@ -442,9 +1423,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -459,6 +1445,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -480,9 +1471,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -497,6 +1493,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: Some("foo".to_owned()),
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -533,6 +1534,12 @@ tasks:
       SPAM: null
       HAM: null
       EGGS: null
+    secrets:
+      FOO: null
+      BAR:
+        env: BAZ
+      QUX:
+        file: quux
     input_paths:
       - qux
       - quux
@@ -540,7 +1547,8 @@ tasks:
     output_paths:
       - corge
       - grault
-      - garply
+      - path: garply
+        mirror: true
     mount_paths:
       - wibble
       - wobble
@@ -561,14 +1569,36 @@ tasks:
         environment.insert("HAM".to_owned(), None);
         environment.insert("EGGS".to_owned(), None);
 
+        let mut secrets = HashMap::new();
+        secrets.insert("FOO".to_owned(), None);
+        secrets.insert(
+            "BAR".to_owned(),
+            Some(Secret {
+                env: Some("BAZ".to_owned()),
+                file: None,
+            }),
+        );
+        secrets.insert(
+            "QUX".to_owned(),
+            Some(Secret {
+                env: None,
+                file: Some(Path::new("quux").to_owned()),
+            }),
+        );
+
         let mut tasks = HashMap::new();
         tasks.insert(
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -583,18 +1613,32 @@ tasks:
             "bar".to_owned(),
             Task {
                 description: Some("Reticulate splines.".to_owned()),
+                extends: None,
                 dependencies: vec!["foo".to_owned()],
                 cache: false,
                 environment,
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets,
                 input_paths: vec![
                     Path::new("qux").to_owned(),
                     Path::new("quux").to_owned(),
                     Path::new("quuz").to_owned(),
                 ],
                 output_paths: vec![
-                    Path::new("corge").to_owned(),
-                    Path::new("grault").to_owned(),
-                    Path::new("garply").to_owned(),
+                    OutputPath {
+                        path: Path::new("corge").to_owned(),
+                        mirror: false,
+                    },
+                    OutputPath {
+                        path: Path::new("grault").to_owned(),
+                        mirror: false,
+                    },
+                    OutputPath {
+                        path: Path::new("garply").to_owned(),
+                        mirror: true,
+                    },
                 ],
                 mount_paths: vec![
                     Path::new("wibble").to_owned(),
@@ -612,6 +1656,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -619,124 +1668,1061 @@ tasks:
     }
 
     #[test]
-    fn environment_empty() {
-        let task = Task {
-            description: None,
-            dependencies: vec![],
-            cache: true,
-            environment: HashMap::new(),
-            input_paths: vec![],
-            output_paths: vec![],
-            mount_paths: vec![],
-            mount_readonly: false,
-            ports: vec![],
-            location: Path::new(DEFAULT_LOCATION).to_owned(),
-            user: DEFAULT_USER.to_owned(),
-            command: None,
-        };
+    fn parse_defaults() {
+        let input = r#"
+image: encom:os-12
+environment:
+  FOO: '1.0'
+  BAR: global
+  BAZ: global
+defaults:
+  cache: false
+  user: flynn
+  location: /grid
+  ports:
+    - 8080:80
+tasks:
+  foo: {}
+  bar:
+    user: clu
+    environment:
+      BAR: task
+    cache_neutral_environment:
+      BAZ: null
+    "#
+        .trim();
 
-        assert_eq!(environment(&task), Ok(HashMap::new()));
+        let toastfile = parse(input).unwrap();
+
+        let foo = &toastfile.tasks["foo"];
+        assert_eq!(foo.user, "flynn");
+        assert_eq!(foo.location, Path::new("/grid"));
+        assert_eq!(foo.ports, vec!["8080:80".to_owned()]);
+        assert_eq!(foo.environment["FOO"], Some("1.0".to_owned()));
+        assert_eq!(foo.environment["BAR"], Some("global".to_owned()));
+        assert_eq!(foo.environment["BAZ"], Some("global".to_owned()));
+
+        let bar = &toastfile.tasks["bar"];
+        assert_eq!(bar.user, "clu");
+        assert_eq!(bar.location, Path::new("/grid"));
+        assert_eq!(bar.environment["FOO"], Some("1.0".to_owned()));
+        assert_eq!(bar.environment["BAR"], Some("task".to_owned()));
+        assert!(!bar.environment.contains_key("BAZ"));
+        assert_eq!(bar.cache_neutral_environment["BAZ"], None);
     }
 
     #[test]
-    fn environment_default_overridden() {
-        // NOTE: We add an index to the test arg ("foo1", "foo2", ...) to avoid having parallel
-        // tests clobbering environment variables used by other threads.
-        let mut env_map = HashMap::new();
-        env_map.insert("foo1".to_owned(), Some("bar".to_owned()));
+    fn parse_defaults_scalars() {
+        let input = r#"
+image: encom:os-12
+environment:
+  FOO: 3.10
+defaults:
+  environment:
+    BAR: 0x10
+tasks:
+  foo: {}
+  bar:
+    extends: foo
+    "#
+        .trim();
 
-        let task = Task {
-            description: None,
-            dependencies: vec![],
-            cache: true,
-            environment: env_map,
-            input_paths: vec![],
-            output_paths: vec![],
-            mount_paths: vec![],
-            mount_readonly: false,
-            ports: vec![],
-            location: Path::new(DEFAULT_LOCATION).to_owned(),
-            user: DEFAULT_USER.to_owned(),
-            command: None,
-        };
+        let toastfile = parse(input).unwrap();
+
+        let bar = &toastfile.tasks["bar"];
+        assert_eq!(bar.environment["FOO"], Some("3.10".to_owned()));
+        assert_eq!(bar.environment["BAR"], Some("0x10".to_owned()));
+    }
+
+    #[test]
+    fn parse_defaults_after_extends() {
+        let input = r#"
+image: encom:os-12
+defaults:
+  user: flynn
+templates:
+  base:
+    user: clu
+tasks:
+  foo:
+    extends: base
+  bar:
+    extends: foo
+    "#
+        .trim();
+
+        let toastfile = parse(input).unwrap();
+
+        assert_eq!(toastfile.tasks["foo"].user, "clu");
+        assert_eq!(toastfile.tasks["bar"].user, "clu");
+    }
+
+    #[test]
+    fn parse_defaults_command() {
+        let input = r#"
+image: encom:os-12
+defaults:
+  command: echo hello
+tasks:
+  foo: {}
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("command"));
+    }
+
+    #[test]
+    fn parse_defaults_unknown_field() {
+        let input = r#"
+image: encom:os-12
+defaults:
+  bogus: true
+tasks:
+  foo: {}
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("bogus"));
+    }
+
+    #[test]
+    fn parse_extends() {
+        let input = r#"
+image: encom:os-12
+templates:
+  base:
+    user: flynn
+    dependencies:
+      - foo
+    environment:
+      FOO: base
+      BAR: base
+    input_paths:
+      - a
+      - b
+  test:
+    extends: base
+    description: Run the tests.
+    environment:
+      BAR: test
+    input_paths:
+      - b
+      - c
+    command: make test
+tasks:
+  foo: {}
+  test-unit:
+    extends: test
+    environment:
+      SUITE: unit
+  test-e2e:
+    extends: test-unit
+    user: clu
+    environment:
+      SUITE: e2e
+    "#
+        .trim();
+
+        let toastfile = parse(input).unwrap();
+
+        let unit = &toastfile.tasks["test-unit"];
+        assert_eq!(unit.extends, Some("test".to_owned()));
+        assert_eq!(unit.description, Some("Run the tests.".to_owned()));
+        assert_eq!(unit.user, "flynn");
+        assert_eq!(unit.dependencies, vec!["foo".to_owned()]);
+        assert_eq!(unit.environment["FOO"], Some("base".to_owned()));
+        assert_eq!(unit.environment["BAR"], Some("test".to_owned()));
+        assert_eq!(unit.environment["SUITE"], Some("unit".to_owned()));
+        assert_eq!(
+            unit.input_paths,
+            vec![
+                Path::new("a").to_owned(),
+                Path::new("b").to_owned(),
+                Path::new("c").to_owned(),
+            ],
+        );
+        assert_eq!(unit.command, Some("make test".to_owned()));
+
+        let e2e = &toastfile.tasks["test-e2e"];
+        assert_eq!(e2e.user, "clu");
+        assert_eq!(e2e.environment["SUITE"], Some("e2e".to_owned()));
+        assert_eq!(e2e.command, Some("make test".to_owned()));
+
+        assert!(!toastfile.tasks.contains_key("base"));
+        assert!(toastfile.templates.contains_key("base"));
+    }
+
+    #[test]
+    fn parse_extends_scalars() {
+        let input = r#"
+image: encom:os-12
+templates:
+  base:
+    environment:
+      FOO: 3.10
+tasks:
+  foo:
+    extends: base
+    environment:
+      BAR: 0x10
+    "#
+        .trim();
+
+        let toastfile = parse(input).unwrap();
+
+        let foo = &toastfile.tasks["foo"];
+        assert_eq!(foo.environment["FOO"], Some("3.10".to_owned()));
+        assert_eq!(foo.environment["BAR"], Some("0x10".to_owned()));
+    }
+
+    #[test]
+    fn parse_extends_missing() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    extends: bar
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("bar"));
+    }
+
+    #[test]
+    fn parse_extends_itself() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    extends: foo
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("extends itself"));
+    }
+
+    #[test]
+    fn parse_extends_cycle() {
+        let input = r#"
+image: encom:os-12
+templates:
+  bar:
+    extends: baz
+  baz:
+    extends: foo
+tasks:
+  foo:
+    extends: bar
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("cycle"));
+    }
+
+    #[test]
+    fn parse_template_task_conflict() {
+        let input = r#"
+image: encom:os-12
+templates:
+  foo: {}
+tasks:
+  foo: {}
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("foo"));
+    }
+
+    #[test]
+    fn parse_matrix() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  test:
+    description: Run the tests.
+    matrix:
+      python:
+        - 3.7
+        - 3.10
+      postgres:
+        - 12
+    environment:
+      python: 2.7
+      FOO: bar
+    command: make test
+  report:
+    dependencies:
+      - test
+    "#
+        .trim();
+
+        let toastfile = parse(input).unwrap();
+
+        let test = &toastfile.tasks["test"];
+        assert_eq!(test.description, Some("Run the tests.".to_owned()));
+        assert_eq!(
+            test.dependencies,
+            vec![
+                "test[postgres=12,python=3.7]".to_owned(),
+                "test[postgres=12,python=3.10]".to_owned(),
+            ],
+        );
+        assert_eq!(test.command, None);
+
+        let test_3_10 = &toastfile.tasks["test[postgres=12,python=3.10]"];
+        assert_eq!(test_3_10.environment["python"], Some("3.10".to_owned()));
+        assert_eq!(test_3_10.environment["postgres"], Some("12".to_owned()));
+        assert_eq!(test_3_10.environment["FOO"], Some("bar".to_owned()));
+        assert_eq!(test_3_10.command, Some("make test".to_owned()));
+
+        assert_eq!(toastfile.tasks.len(), 4);
+    }
+
+    #[test]
+    fn parse_matrix_diagnostics() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  test:
+    matrix:
+      python:
+        - 3.8
+        - 3.9
+    input_paths:
+      - ${SRC}
+    location: src
+    "#
+        .trim();
+
+        let error = parse(input).unwrap_err().to_string();
+        assert_eq!(error.matches("undeclared variable").count(), 1);
+        assert_eq!(error.matches("location").count(), 1);
+        assert!(!error.contains("python=3.8"));
+    }
+
+    #[test]
+    fn parse_matrix_empty() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  test:
+    matrix:
+      python: []
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("python"));
+    }
+
+    #[test]
+    fn parse_matrix_conflict() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  test:
+    matrix:
+      python:
+        - 3.8
+  test[python=3.8]: {}
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("test[python=3.8]"));
+    }
+
+    #[test]
+    fn file_format_from_path() {
+        assert_eq!(
+            FileFormat::from_path(Path::new("toast.yml")),
+            FileFormat::Yaml
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("toast.yaml")),
+            FileFormat::Yaml
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("toast.json")),
+            FileFormat::Json
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("toast.toml")),
+            FileFormat::Toml
+        );
+    }
+
+    #[test]
+    fn parse_json() {
+        let json_input = r#"
+{
+  "image": "encom:os-12",
+  "defaults": { "location": "/src" },
+  "tasks": {
+    "foo": { "environment": { "PYTHON": "3.10" }, "command": "echo foo" },
+    "bar": { "dependencies": ["foo"], "user": "flynn" }
+  }
+}
+    "#
+        .trim();
+
+        let yaml_input = r#"
+image: encom:os-12
+defaults:
+  location: /src
+tasks:
+  foo:
+    environment:
+      PYTHON: '3.10'
+    command: echo foo
+  bar:
+    dependencies:
+      - foo
+    user: flynn
+    "#
+        .trim();
+
+        assert_eq!(
+            parse_partial(json_input, FileFormat::Json).unwrap().0,
+            parse(yaml_input).unwrap(),
+        );
+    }
+
+    #[test]
+    fn parse_toml() {
+        let toml_input = r#"
+image = "encom:os-12"
+
+[defaults]
+location = "/src"
+
+[tasks.foo]
+command = "echo foo"
+
+[tasks.foo.environment]
+PYTHON = "3.10"
+
+[tasks.bar]
+dependencies = ["foo"]
+user = "flynn"
+    "#
+        .trim();
+
+        let yaml_input = r#"
+image: encom:os-12
+defaults:
+  location: /src
+tasks:
+  foo:
+    environment:
+      PYTHON: '3.10'
+    command: echo foo
+  bar:
+    dependencies:
+      - foo
+    user: flynn
+    "#
+        .trim();
+
+        assert_eq!(
+            parse_partial(toml_input, FileFormat::Toml).unwrap().0,
+            parse(yaml_input).unwrap(),
+        );
+    }
+
+    #[test]
+    fn parse_toml_invalid() {
+        let input = r#"
+image = "encom:os-12"
+
+[defaults]
+command = "echo foo"
+
+[tasks.foo]
+    "#
+        .trim();
+
+        let result = parse_partial(input, FileFormat::Toml);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("command"));
+    }
+
+    #[test]
+    fn parse_multiple_errors() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    input_paths:
+      - /bar
+    location: baz
+  qux:
+    ports:
+      - 3000:80
+        "#
+        .trim();
+
+        let (_, diagnostics) = parse_partial(input, FileFormat::Yaml).unwrap();
+        let diagnostics = diagnostics.into_result().unwrap_err();
+        assert_eq!(diagnostics.0.len(), 3);
+        assert_eq!(
+            diagnostics.0[0].path,
+            vec![
+                "tasks".to_owned(),
+                "foo".to_owned(),
+                "input_paths".to_owned(),
+                "0".to_owned(),
+            ],
+        );
+        assert!(diagnostics.to_string().contains("/bar"));
+        assert!(diagnostics.to_string().contains("baz"));
+        assert!(diagnostics.to_string().contains("qux"));
+    }
+
+    #[test]
+    fn parse_error_position() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    bogus: bar
+        "#
+        .trim();
+
+        let diagnostics = parse_partial(input, FileFormat::Yaml).unwrap_err();
+        assert_eq!(diagnostics.0.len(), 1);
+        assert_eq!(
+            diagnostics.0[0].position.map(|position| position.line),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn parse_interpolation() {
+        let input = r#"
+image: encom:${OS_VERSION}
+environment:
+  OS_VERSION: os-12
+tasks:
+  foo:
+    environment:
+      SRC: src
+    cache_neutral_environment:
+      USER: flynn
+    input_paths:
+      - ${SRC}/main.rs
+    location: /home/${USER}
+    user: ${USER}
+    "#
+        .trim();
+
+        assert!(parse(input).is_ok());
+    }
+
+    #[test]
+    fn parse_interpolation_undeclared() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    input_paths:
+      - ${SRC}/main.rs
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("SRC"));
+    }
+
+    #[test]
+    fn parse_interpolation_image_undeclared() {
+        let input = r#"
+image: encom:${OS_VERSION}
+tasks:
+  foo:
+    environment:
+      OS_VERSION: os-12
+    "#
+        .trim();
+
+        let result = parse(input);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("OS_VERSION"));
+    }
+
+    #[test]
+    fn parse_interpolation_unterminated() {
+        let input = r#"
+image: encom:os-12
+tasks:
+  foo:
+    user: ${USER
+    "#
+        .trim();
+
+        assert!(parse(input).is_err());
+    }
+
+    #[test]
+    fn environment_empty() {
+        let task = Task {
+            description: None,
+            extends: None,
+            dependencies: vec![],
+            cache: true,
+            environment: HashMap::new(),
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
+            input_paths: vec![],
+            output_paths: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: Path::new(DEFAULT_LOCATION).to_owned(),
+            user: DEFAULT_USER.to_owned(),
+            command: None,
+        };
+
+        assert_eq!(environment(&task), Ok(HashMap::new()));
+    }
+
+    #[test]
+    fn environment_default_overridden() {
+        // NOTE: We add an index to the test arg ("foo1", "foo2", ...) to avoid having parallel
+        // tests clobbering environment variables used by other threads.
+        let mut env_map = HashMap::new();
+        env_map.insert("foo1".to_owned(), Some("bar".to_owned()));
+
+        let task = Task {
+            description: None,
+            extends: None,
+            dependencies: vec![],
+            cache: true,
+            environment: env_map,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
+            input_paths: vec![],
+            output_paths: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: Path::new(DEFAULT_LOCATION).to_owned(),
+            user: DEFAULT_USER.to_owned(),
+            command: None,
+        };
+
+        let mut expected = HashMap::new();
+        expected.insert("foo1".to_owned(), "baz".to_owned());
+
+        env::set_var("foo1", "baz");
+        assert_eq!(env::var("foo1"), Ok("baz".to_owned()));
+        assert_eq!(environment(&task), Ok(expected));
+    }
+
+    #[test]
+    fn environment_default_not_overridden() {
+        // NOTE: We add an index to the test arg ("foo1", "foo2", ...) to avoid having parallel
+        // tests clobbering environment variables used by other threads.
+        let mut env_map = HashMap::new();
+        env_map.insert("foo2".to_owned(), Some("bar".to_owned()));
+
+        let task = Task {
+            description: None,
+            extends: None,
+            dependencies: vec![],
+            cache: true,
+            environment: env_map,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
+            input_paths: vec![],
+            output_paths: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: Path::new(DEFAULT_LOCATION).to_owned(),
+            user: DEFAULT_USER.to_owned(),
+            command: None,
+        };
+
+        let mut expected = HashMap::new();
+        expected.insert("foo2".to_owned(), "bar".to_owned());
+
+        env::remove_var("foo2");
+        assert!(env::var("foo2").is_err());
+        assert_eq!(environment(&task), Ok(expected));
+    }
+
+    #[test]
+    fn environment_missing() {
+        // NOTE: We add an index to the test arg ("foo1", "foo2", ...) to avoid having parallel
+        // tests clobbering environment variables used by other threads.
+        let mut env_map = HashMap::new();
+        env_map.insert("foo3".to_owned(), None);
+
+        let task = Task {
+            description: None,
+            extends: None,
+            dependencies: vec![],
+            cache: true,
+            environment: env_map,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
+            input_paths: vec![],
+            output_paths: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: Path::new(DEFAULT_LOCATION).to_owned(),
+            user: DEFAULT_USER.to_owned(),
+            command: None,
+        };
+
+        env::remove_var("foo3");
+        assert!(env::var("foo3").is_err());
+        let result = environment(&task);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err()[0].to_owned(), "foo3");
+    }
+
+    #[test]
+    fn environment_cache_neutral() {
+        // NOTE: We add an index to the test arg ("foo1", "foo2", ...) to avoid having parallel
+        // tests clobbering environment variables used by other threads.
+        let mut env_map = HashMap::new();
+        env_map.insert("foo4".to_owned(), None);
+
+        let task = Task {
+            description: None,
+            extends: None,
+            dependencies: vec![],
+            cache: true,
+            environment: HashMap::new(),
+            cache_neutral_environment: env_map,
+            env_file: None,
+            matrix: HashMap::new(),
+            secrets: HashMap::new(),
+            input_paths: vec![],
+            output_paths: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: Path::new(DEFAULT_LOCATION).to_owned(),
+            user: DEFAULT_USER.to_owned(),
+            command: None,
+        };
+
+        let mut expected = HashMap::new();
+        expected.insert("foo4".to_owned(), "bar".to_owned());
+
+        env::set_var("foo4", "bar");
+        assert_eq!(environment(&task), Ok(expected));
+    }
+
+    #[test]
+    fn environment_matrix() {
+        // NOTE: We add an index to the test arg ("foo1", "foo2", ...) to avoid having parallel
+        // tests clobbering environment variables used by other threads.
+        let mut env_map = HashMap::new();
+        env_map.insert("foo5".to_owned(), Some("bar".to_owned()));
+        let mut matrix = HashMap::new();
+        matrix.insert("foo5".to_owned(), vec!["bar".to_owned()]);
+
+        let task = Task {
+            extends: None,
+            description: None,
+            dependencies: vec![],
+            cache: true,
+            environment: env_map,
+            cache_neutral_environment: HashMap::new(),
+            env_file: None,
+            matrix,
+            secrets: HashMap::new(),
+            input_paths: vec![],
+            output_paths: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: Path::new(DEFAULT_LOCATION).to_owned(),
+            user: DEFAULT_USER.to_owned(),
+            command: None,
+        };
+
+        let mut expected = HashMap::new();
+        expected.insert("foo5".to_owned(), "bar".to_owned());
+
+        env::set_var("foo5", "baz");
+        assert_eq!(environment(&task), Ok(expected));
+    }
+
+    #[test]
+    fn check_environment_empty() {
+        let mut tasks = HashMap::new();
+        tasks.insert(
+            "foo".to_owned(),
+            Task {
+                description: None,
+                extends: None,
+                dependencies: vec![],
+                cache: true,
+                environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
+                input_paths: vec![],
+                output_paths: vec![],
+                mount_paths: vec![],
+                mount_readonly: false,
+                ports: vec![],
+                location: Path::new(DEFAULT_LOCATION).to_owned(),
+                user: DEFAULT_USER.to_owned(),
+                command: None,
+            },
+        );
+
+        let toastfile = Toastfile {
+            image: "encom:os-12".to_owned(),
+            default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
+            tasks,
+        };
+
+        assert!(check_environment(&toastfile).is_ok());
+    }
+
+    #[test]
+    fn check_environment_ok() {
+        let mut environment = HashMap::new();
+        environment.insert("corge".to_owned(), None);
+        environment.insert("grault".to_owned(), None);
+        environment.insert("garply".to_owned(), None);
+
+        let mut tasks = HashMap::new();
+        tasks.insert(
+            "foo".to_owned(),
+            Task {
+                description: None,
+                extends: None,
+                dependencies: vec![],
+                cache: true,
+                environment,
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
+                input_paths: vec![],
+                output_paths: vec![],
+                mount_paths: vec![],
+                mount_readonly: false,
+                ports: vec![],
+                location: Path::new(DEFAULT_LOCATION).to_owned(),
+                user: DEFAULT_USER.to_owned(),
+                command: None,
+            },
+        );
+
+        let toastfile = Toastfile {
+            image: "encom:os-12".to_owned(),
+            default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
+            tasks,
+        };
+
+        assert!(check_environment(&toastfile).is_ok());
+    }
+
+    #[test]
+    fn check_environment_equals() {
+        let mut environment = HashMap::new();
+        environment.insert("corge".to_owned(), None);
+        environment.insert("gra=ult".to_owned(), None);
+        environment.insert("garply".to_owned(), None);
+
+        let mut tasks = HashMap::new();
+        tasks.insert(
+            "foo".to_owned(),
+            Task {
+                description: None,
+                extends: None,
+                dependencies: vec![],
+                cache: true,
+                environment,
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
+                input_paths: vec![],
+                output_paths: vec![],
+                mount_paths: vec![],
+                mount_readonly: false,
+                ports: vec![],
+                location: Path::new(DEFAULT_LOCATION).to_owned(),
+                user: DEFAULT_USER.to_owned(),
+                command: None,
+            },
+        );
 
-        let mut expected = HashMap::new();
-        expected.insert("foo1".to_owned(), "baz".to_owned());
+        let toastfile = Toastfile {
+            image: "encom:os-12".to_owned(),
+            default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
+            tasks,
+        };
 
-        env::set_var("foo1", "baz");
-        assert_eq!(env::var("foo1"), Ok("baz".to_owned()));
-        assert_eq!(environment(&task), Ok(expected));
+        let result = check_environment(&toastfile);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains('='));
     }
 
     #[test]
-    fn environment_default_not_overridden() {
-        // NOTE: We add an index to the test arg ("foo1", "foo2", ...) to avoid having parallel
-        // tests clobbering environment variables used by other threads.
-        let mut env_map = HashMap::new();
-        env_map.insert("foo2".to_owned(), Some("bar".to_owned()));
+    fn check_environment_cache_neutral_conflict() {
+        let mut environment = HashMap::new();
+        environment.insert("corge".to_owned(), None);
+        let mut cache_neutral_environment = HashMap::new();
+        cache_neutral_environment.insert("corge".to_owned(), None);
 
-        let task = Task {
-            description: None,
-            dependencies: vec![],
-            cache: true,
-            environment: env_map,
-            input_paths: vec![],
-            output_paths: vec![],
-            mount_paths: vec![],
-            mount_readonly: false,
-            ports: vec![],
-            location: Path::new(DEFAULT_LOCATION).to_owned(),
-            user: DEFAULT_USER.to_owned(),
-            command: None,
-        };
+        let mut tasks = HashMap::new();
+        tasks.insert(
+            "foo".to_owned(),
+            Task {
+                description: None,
+                extends: None,
+                dependencies: vec![],
+                cache: true,
+                environment,
+                cache_neutral_environment,
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
+                input_paths: vec![],
+                output_paths: vec![],
+                mount_paths: vec![],
+                mount_readonly: false,
+                ports: vec![],
+                location: Path::new(DEFAULT_LOCATION).to_owned(),
+                user: DEFAULT_USER.to_owned(),
+                command: None,
+            },
+        );
 
-        let mut expected = HashMap::new();
-        expected.insert("foo2".to_owned(), "bar".to_owned());
+        let toastfile = Toastfile {
+            image: "encom:os-12".to_owned(),
+            default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
+            tasks,
+        };
 
-        env::remove_var("foo2");
-        assert!(env::var("foo2").is_err());
-        assert_eq!(environment(&task), Ok(expected));
+        let result = check_environment(&toastfile);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("corge"));
     }
 
     #[test]
-    fn environment_missing() {
-        // NOTE: We add an index to the test arg ("foo1", "foo2", ...) to avoid having parallel
-        // tests clobbering environment variables used by other threads.
-        let mut env_map = HashMap::new();
-        env_map.insert("foo3".to_owned(), None);
+    fn check_secrets_ok() {
+        let mut secrets = HashMap::new();
+        secrets.insert(
+            "bar".to_owned(),
+            Some(Secret {
+                env: None,
+                file: Some(Path::new("baz").to_owned()),
+            }),
+        );
 
-        let task = Task {
-            description: None,
-            dependencies: vec![],
-            cache: true,
-            environment: env_map,
-            input_paths: vec![],
-            output_paths: vec![],
-            mount_paths: vec![],
-            mount_readonly: false,
-            ports: vec![],
-            location: Path::new(DEFAULT_LOCATION).to_owned(),
-            user: DEFAULT_USER.to_owned(),
-            command: None,
+        let mut tasks = HashMap::new();
+        tasks.insert(
+            "foo".to_owned(),
+            Task {
+                description: None,
+                extends: None,
+                dependencies: vec![],
+                cache: true,
+                environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets,
+                input_paths: vec![],
+                output_paths: vec![],
+                mount_paths: vec![],
+                mount_readonly: false,
+                ports: vec![],
+                location: Path::new(DEFAULT_LOCATION).to_owned(),
+                user: DEFAULT_USER.to_owned(),
+                command: None,
+            },
+        );
+
+        let toastfile = Toastfile {
+            image: "encom:os-12".to_owned(),
+            default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
+            tasks,
         };
 
-        env::remove_var("foo3");
-        assert!(env::var("foo3").is_err());
-        let result = environment(&task);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err()[0].to_owned(), "foo3");
+        assert!(check_secrets(&toastfile).is_ok());
     }
 
     #[test]
-    fn check_environment_empty() {
+    fn check_secrets_slash() {
+        let mut secrets = HashMap::new();
+        secrets.insert("bar/baz".to_owned(), None);
+
         let mut tasks = HashMap::new();
         tasks.insert(
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets,
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -751,27 +2737,43 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
-        assert!(check_environment(&toastfile).is_ok());
+        let result = check_secrets(&toastfile);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("bar/baz"));
     }
 
     #[test]
-    fn check_environment_ok() {
-        let mut environment = HashMap::new();
-        environment.insert("corge".to_owned(), None);
-        environment.insert("grault".to_owned(), None);
-        environment.insert("garply".to_owned(), None);
+    fn check_secrets_both_sources() {
+        let mut secrets = HashMap::new();
+        secrets.insert(
+            "bar".to_owned(),
+            Some(Secret {
+                env: Some("baz".to_owned()),
+                file: Some(Path::new("qux").to_owned()),
+            }),
+        );
 
         let mut tasks = HashMap::new();
         tasks.insert(
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
-                environment,
+                environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets,
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -786,27 +2788,43 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
-        assert!(check_environment(&toastfile).is_ok());
+        let result = check_secrets(&toastfile);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("bar"));
     }
 
     #[test]
-    fn check_environment_equals() {
-        let mut environment = HashMap::new();
-        environment.insert("corge".to_owned(), None);
-        environment.insert("gra=ult".to_owned(), None);
-        environment.insert("garply".to_owned(), None);
+    fn check_secrets_absolute_file() {
+        let mut secrets = HashMap::new();
+        secrets.insert(
+            "bar".to_owned(),
+            Some(Secret {
+                env: None,
+                file: Some(Path::new("/baz").to_owned()),
+            }),
+        );
 
         let mut tasks = HashMap::new();
         tasks.insert(
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
-                environment,
+                environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets,
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -821,12 +2839,17 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
-        let result = check_environment(&toastfile);
+        let result = check_secrets(&toastfile);
         assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains('='));
+        assert!(result.unwrap_err().to_string().contains("/baz"));
     }
 
     #[test]
@@ -836,9 +2859,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -853,6 +2881,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -866,11 +2899,19 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![Path::new("bar").to_owned()],
-                output_paths: vec![Path::new("baz").to_owned()],
+                output_paths: vec![OutputPath {
+                    path: Path::new("baz").to_owned(),
+                    mirror: false,
+                }],
                 mount_paths: vec![Path::new("qux").to_owned()],
                 mount_readonly: false,
                 ports: vec![],
@@ -883,6 +2924,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -896,11 +2942,19 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![Path::new("/bar").to_owned()],
-                output_paths: vec![Path::new("baz").to_owned()],
+                output_paths: vec![OutputPath {
+                    path: Path::new("baz").to_owned(),
+                    mirror: false,
+                }],
                 mount_paths: vec![Path::new("qux").to_owned()],
                 mount_readonly: false,
                 ports: vec![],
@@ -913,6 +2967,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -928,11 +2987,19 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![Path::new("bar").to_owned()],
-                output_paths: vec![Path::new("/baz").to_owned()],
+                output_paths: vec![OutputPath {
+                    path: Path::new("/baz").to_owned(),
+                    mirror: false,
+                }],
                 mount_paths: vec![Path::new("qux").to_owned()],
                 mount_readonly: false,
                 ports: vec![],
@@ -945,6 +3012,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -953,6 +3025,139 @@ tasks:
         assert!(result.unwrap_err().to_string().contains("/baz"));
     }
 
+    #[test]
+    fn check_paths_mirror_ok() {
+        let mut tasks = HashMap::new();
+        tasks.insert(
+            "foo".to_owned(),
+            Task {
+                description: None,
+                extends: None,
+                dependencies: vec![],
+                cache: true,
+                environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
+                input_paths: vec![],
+                output_paths: vec![OutputPath {
+                    path: Path::new("bar/baz").to_owned(),
+                    mirror: true,
+                }],
+                mount_paths: vec![],
+                mount_readonly: false,
+                ports: vec![],
+                location: Path::new(DEFAULT_LOCATION).to_owned(),
+                user: DEFAULT_USER.to_owned(),
+                command: None,
+            },
+        );
+
+        let toastfile = Toastfile {
+            image: "encom:os-12".to_owned(),
+            default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
+            tasks,
+        };
+
+        assert!(check_paths(&toastfile).is_ok());
+    }
+
+    #[test]
+    fn check_paths_mirror_parent() {
+        let mut tasks = HashMap::new();
+        tasks.insert(
+            "foo".to_owned(),
+            Task {
+                description: None,
+                extends: None,
+                dependencies: vec![],
+                cache: true,
+                environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
+                input_paths: vec![],
+                output_paths: vec![OutputPath {
+                    path: Path::new("bar/../../baz").to_owned(),
+                    mirror: true,
+                }],
+                mount_paths: vec![],
+                mount_readonly: false,
+                ports: vec![],
+                location: Path::new(DEFAULT_LOCATION).to_owned(),
+                user: DEFAULT_USER.to_owned(),
+                command: None,
+            },
+        );
+
+        let toastfile = Toastfile {
+            image: "encom:os-12".to_owned(),
+            default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
+            tasks,
+        };
+
+        let result = check_paths(&toastfile);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("bar/../../baz"));
+    }
+
+    #[test]
+    fn check_paths_mirror_toastfile_dir() {
+        let mut tasks = HashMap::new();
+        tasks.insert(
+            "foo".to_owned(),
+            Task {
+                description: None,
+                extends: None,
+                dependencies: vec![],
+                cache: true,
+                environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
+                input_paths: vec![],
+                output_paths: vec![OutputPath {
+                    path: Path::new("./.").to_owned(),
+                    mirror: true,
+                }],
+                mount_paths: vec![],
+                mount_readonly: false,
+                ports: vec![],
+                location: Path::new(DEFAULT_LOCATION).to_owned(),
+                user: DEFAULT_USER.to_owned(),
+                command: None,
+            },
+        );
+
+        let toastfile = Toastfile {
+            image: "encom:os-12".to_owned(),
+            default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
+            tasks,
+        };
+
+        let result = check_paths(&toastfile);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("./."));
+    }
+
     #[test]
     fn check_paths_absolute_mount_paths() {
         let mut tasks = HashMap::new();
@@ -960,11 +3165,19 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![Path::new("bar").to_owned()],
-                output_paths: vec![Path::new("baz").to_owned()],
+                output_paths: vec![OutputPath {
+                    path: Path::new("baz").to_owned(),
+                    mirror: false,
+                }],
                 mount_paths: vec![Path::new("/qux").to_owned()],
                 mount_readonly: false,
                 ports: vec![],
@@ -977,6 +3190,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -992,11 +3210,19 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![Path::new("bar").to_owned()],
-                output_paths: vec![Path::new("baz").to_owned()],
+                output_paths: vec![OutputPath {
+                    path: Path::new("baz").to_owned(),
+                    mirror: false,
+                }],
                 mount_paths: vec![Path::new("q,ux").to_owned()],
                 mount_readonly: false,
                 ports: vec![],
@@ -1009,6 +3235,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1024,11 +3255,19 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![Path::new("bar").to_owned()],
-                output_paths: vec![Path::new("baz").to_owned()],
+                output_paths: vec![OutputPath {
+                    path: Path::new("baz").to_owned(),
+                    mirror: false,
+                }],
                 mount_paths: vec![Path::new("qux").to_owned()],
                 mount_readonly: false,
                 ports: vec![],
@@ -1041,6 +3280,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1056,9 +3300,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1073,6 +3322,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1086,9 +3340,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1103,6 +3362,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1118,9 +3382,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: false,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1135,6 +3404,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1148,9 +3422,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![Path::new("bar").to_owned()],
@@ -1165,6 +3444,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1180,9 +3464,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: false,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![Path::new("bar").to_owned()],
@@ -1197,6 +3486,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1208,6 +3502,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks: HashMap::new(),
         };
 
@@ -1221,9 +3520,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1238,6 +3542,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1251,9 +3560,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1268,9 +3582,14 @@ tasks:
             "bar".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec!["foo".to_owned()],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1285,6 +3604,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1298,9 +3622,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec![],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1315,9 +3644,14 @@ tasks:
             "bar".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec!["foo".to_owned(), "baz".to_owned()],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1332,6 +3666,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1347,9 +3686,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec!["foo".to_owned()],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1364,6 +3708,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1379,9 +3728,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec!["bar".to_owned()],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1396,9 +3750,14 @@ tasks:
             "bar".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec!["foo".to_owned()],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1413,6 +3772,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -1428,9 +3792,14 @@ tasks:
             "foo".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec!["baz".to_owned()],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1445,9 +3814,14 @@ tasks:
             "bar".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec!["foo".to_owned()],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1462,9 +3836,14 @@ tasks:
             "baz".to_owned(),
             Task {
                 description: None,
+                extends: None,
                 dependencies: vec!["bar".to_owned()],
                 cache: true,
                 environment: HashMap::new(),
+                cache_neutral_environment: HashMap::new(),
+                env_file: None,
+                matrix: HashMap::new(),
+                secrets: HashMap::new(),
                 input_paths: vec![],
                 output_paths: vec![],
                 mount_paths: vec![],
@@ -1479,6 +3858,11 @@ tasks:
         let toastfile = Toastfile {
             image: "encom:os-12".to_owned(),
             default: None,
+            include: vec![],
+            env_file: None,
+            environment: HashMap::new(),
+            defaults: None,
+            templates: HashMap::new(),
             tasks,
         };
 
@@ -0,0 +1,291 @@
+use crate::{
+    failure,
+    failure::Failure,
+    format::CodeStr,
+    toastfile::{deserialize, FileFormat},
+};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+// The separator between a member and a task name, as in `services/api:build`
+pub const MEMBER_SEPARATOR: char = ':';
+
+// This struct represents a workspace, which is a file in place of a toastfile that lists the
+// directories of other toastfiles (the members) relative to its own directory.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    pub members: Vec<PathBuf>,
+}
+
+// Parse a workspace. If the data doesn't have a `members` key, it's a regular toastfile, and the
+// result is `None`.
+pub fn parse(workspace_data: &str, file_format: FileFormat) -> Result<Option<Workspace>, Failure> {
+    // Check whether this is a workspace at all.
+    let value: Value = deserialize(workspace_data, file_format)?;
+    if value.get("members").is_none() {
+        return Ok(None);
+    }
+
+    // Deserialize the data.
+    let mut workspace: Workspace = deserialize(workspace_data, file_format)?;
+
+    // Normalize the members so they can be compared, and make sure they're valid.
+    workspace.members = workspace
+        .members
+        .iter()
+        .map(|member| normalize(member))
+        .collect();
+    check_members(&workspace)?;
+
+    Ok(Some(workspace))
+}
+
+// Read a file and parse it as a workspace, if it is one.
+pub fn load(path: &Path) -> Result<Option<Workspace>, Failure> {
+    let data = fs::read_to_string(path).map_err(failure::user(format!(
+        "Unable to read file {}.",
+        path.to_string_lossy().code_str(),
+    )))?;
+
+    parse(&data, FileFormat::from_path(path)).map_err(failure::user(format!(
+        "Unable to parse file {}.",
+        path.to_string_lossy().code_str(),
+    )))
+}
+
+// Remove the `.` components from a path, so `./foo/` and `foo` are considered the same member.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+// Check that the members are relative paths and aren't listed more than once.
+fn check_members(workspace: &Workspace) -> Result<(), Failure> {
+    for (i, member) in workspace.members.iter().enumerate() {
+        if member.is_absolute() || member.as_os_str().is_empty() {
+            return Err(Failure::User(
+                format!(
+                    "The workspace has an invalid member {}. Members must be relative paths to \
+                     directories.",
+                    member.to_string_lossy().code_str(),
+                ),
+                None,
+            ));
+        }
+
+        if workspace.members[..i].contains(member) {
+            return Err(Failure::User(
+                format!(
+                    "The workspace lists the member {} more than once.",
+                    member.to_string_lossy().code_str(),
+                ),
+                None,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Determine the directory of a member.
+pub fn member_dir(workspace_path: &Path, member: &Path) -> PathBuf {
+    let mut workspace_dir = PathBuf::from(workspace_path);
+    workspace_dir.pop();
+    workspace_dir.join(member)
+}
+
+// Split tasks written like `services/api:build` into members and task names. The tasks are grouped
+// by member, and the members are in the order in which they first appear.
+pub fn group<'a>(
+    workspace: &'a Workspace,
+    tasks: &[String],
+) -> Result<Vec<(&'a Path, Vec<String>)>, Failure> {
+    let mut result: Vec<(&'a Path, Vec<String>)> = vec![];
+
+    for task in tasks {
+        let separator = task.find(MEMBER_SEPARATOR).ok_or_else(|| {
+            Failure::User(
+                format!(
+                    "Tasks in a workspace must be written as {}, but got {}.",
+                    "member:task".code_str(),
+                    task.code_str(),
+                ),
+                None,
+            )
+        })?;
+        let member = normalize(Path::new(&task[..separator]));
+        let task_name = task[separator + 1..].to_owned();
+
+        let member = workspace
+            .members
+            .iter()
+            .find(|candidate| **candidate == member)
+            .ok_or_else(|| {
+                Failure::User(
+                    format!(
+                        "No member {} in the workspace.",
+                        member.to_string_lossy().code_str(),
+                    ),
+                    None,
+                )
+            })?;
+
+        if let Some((_, member_tasks)) = result.iter_mut().find(|(path, _)| *path == member) {
+            member_tasks.push(task_name);
+        } else {
+            result.push((member, vec![task_name]));
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        toastfile::FileFormat,
+        workspace::{group, member_dir, parse, Workspace},
+    };
+    use std::path::Path;
+
+    #[test]
+    fn parse_toastfile() {
+        let input = r#"
+image: encom:os-12
+tasks: {}
+    "#
+        .trim();
+
+        assert_eq!(parse(input, FileFormat::Yaml).unwrap(), None);
+    }
+
+    #[test]
+    fn parse_members() {
+        let input = r#"
+members:
+  - ./services/api/
+  - tools
+    "#
+        .trim();
+
+        assert_eq!(
+            parse(input, FileFormat::Yaml).unwrap(),
+            Some(Workspace {
+                members: vec![
+                    Path::new("services/api").to_owned(),
+                    Path::new("tools").to_owned(),
+                ],
+            }),
+        );
+    }
+
+    #[test]
+    fn parse_duplicate_members() {
+        let input = r#"
+members:
+  - tools
+  - ./tools
+    "#
+        .trim();
+
+        let result = parse(input, FileFormat::Yaml);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("tools"));
+    }
+
+    #[test]
+    fn parse_absolute_member() {
+        let input = r#"
+members:
+  - /tools
+    "#
+        .trim();
+
+        assert!(parse(input, FileFormat::Yaml).is_err());
+    }
+
+    #[test]
+    fn parse_unknown_field() {
+        let input = r#"
+members:
+  - tools
+tasks: {}
+    "#
+        .trim();
+
+        assert!(parse(input, FileFormat::Yaml).is_err());
+    }
+
+    #[test]
+    fn member_dir_relative_to_workspace() {
+        assert_eq!(
+            member_dir(Path::new("/repo/toast.yml"), Path::new("tools")),
+            Path::new("/repo/tools").to_owned(),
+        );
+    }
+
+    #[test]
+    fn parse_json() {
+        let input = r#"{ "members": ["tools"] }"#;
+
+        assert_eq!(
+            parse(input, FileFormat::Json).unwrap(),
+            Some(Workspace {
+                members: vec![Path::new("tools").to_owned()],
+            }),
+        );
+    }
+
+    #[test]
+    fn group_tasks() {
+        let workspace = Workspace {
+            members: vec![
+                Path::new("services/api").to_owned(),
+                Path::new("tools").to_owned(),
+            ],
+        };
+
+        assert_eq!(
+            group(
+                &workspace,
+                &[
+                    "tools:lint".to_owned(),
+                    "./services/api:build".to_owned(),
+                    "tools:test".to_owned(),
+                ],
+            )
+            .unwrap(),
+            vec![
+                (
+                    Path::new("tools"),
+                    vec!["lint".to_owned(), "test".to_owned()],
+                ),
+                (Path::new("services/api"), vec!["build".to_owned()]),
+            ],
+        );
+    }
+
+    #[test]
+    fn group_missing_member() {
+        let workspace = Workspace {
+            members: vec![Path::new("tools").to_owned()],
+        };
+
+        assert!(group(&workspace, &["web:build".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn group_missing_separator() {
+        let workspace = Workspace {
+            members: vec![Path::new("tools").to_owned()],
+        };
+
+        assert!(group(&workspace, &["build".to_owned()]).is_err());
+    }
+}